@@ -3,17 +3,17 @@
 use std::{env, fmt::Display, path::Path, process};
 
 use async_trait::async_trait;
-use sqlness::{Database, EnvController, Runner};
+use sqlness::{Database, EnvController, ExecutionContext, Runner};
 
 struct MyController;
 struct MyDB;
 
 #[async_trait]
 impl Database for MyDB {
-    async fn query(&self, _query: String) -> Box<dyn Display> {
+    async fn query(&self, _context: ExecutionContext, _query: String) -> Box<dyn Display> {
         // Implement query logic here
         // println!("Exec {}...", query);
-        return Box::new("ok".to_string());
+        Box::new("ok".to_string())
     }
 }
 
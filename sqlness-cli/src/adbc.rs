@@ -0,0 +1,917 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! ADBC (Arrow Database Connectivity) drivers, enabled by the `adbc` feature.
+//! Any engine with an ADBC driver is reached by DSN like
+//! `adbc:///path/to/libadbc_driver_sqlite.so?uri=file:/tmp/test.db`. The
+//! driver is loaded at runtime via its `AdbcDriverInit` entrypoint, as the
+//! ADBC driver manager does, and parameters of the DSN are set as options of
+//! the database. Results are Arrow record batches, recorded as typed results
+//! with Arrow type names like `Int64` or `Utf8`.
+
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    fmt::{Display, Write},
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use sqlness::{BlockingDatabase, ExecutionContext, TypedColumn, TypedResult};
+
+/// `AdbcStatusCode` of successful calls.
+const ADBC_STATUS_OK: u8 = 0;
+/// `ADBC_VERSION_1_0_0`, whose function table is filled by drivers.
+const ADBC_VERSION_1_0_0: c_int = 1_000_000;
+const ENTRYPOINT: &CStr = c"AdbcDriverInit";
+/// `RTLD_NOW` of `dlopen`.
+const RTLD_NOW: c_int = 2;
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+}
+
+/// `struct AdbcError` of ADBC 1.0.0.
+#[repr(C)]
+struct RawError {
+    message: *mut c_char,
+    vendor_code: i32,
+    sqlstate: [c_char; 5],
+    release: Option<unsafe extern "C" fn(*mut RawError)>,
+}
+
+impl RawError {
+    fn new() -> Self {
+        Self {
+            message: ptr::null_mut(),
+            vendor_code: 0,
+            sqlstate: [0; 5],
+            release: None,
+        }
+    }
+
+    /// Message of a call returning `status`, `Ok` if it succeeded.
+    fn check(&mut self, status: u8, call: &str) -> Result<(), String> {
+        if status == ADBC_STATUS_OK {
+            return Ok(());
+        }
+        if self.message.is_null() {
+            return Err(format!("{} failed with status {}", call, status));
+        }
+
+        Err(unsafe { CStr::from_ptr(self.message) }
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+impl Drop for RawError {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            unsafe { release(self) }
+        }
+    }
+}
+
+/// `struct AdbcDatabase`, `struct AdbcConnection` and `struct
+/// AdbcStatement`, which share the same layout.
+#[repr(C)]
+struct RawHandle {
+    private_data: *mut c_void,
+    private_driver: *mut RawDriver,
+}
+
+impl RawHandle {
+    fn new() -> Self {
+        Self {
+            private_data: ptr::null_mut(),
+            private_driver: ptr::null_mut(),
+        }
+    }
+}
+
+type HandleFn = unsafe extern "C" fn(*mut RawHandle, *mut RawError) -> u8;
+/// Functions of the driver not called here.
+type UnusedFn = unsafe extern "C" fn();
+
+/// `struct AdbcDriver` of ADBC 1.0.0, the function table filled by
+/// `AdbcDriverInit`.
+#[repr(C)]
+struct RawDriver {
+    private_data: *mut c_void,
+    private_manager: *mut c_void,
+    release: Option<unsafe extern "C" fn(*mut RawDriver, *mut RawError) -> u8>,
+
+    database_init: Option<HandleFn>,
+    database_new: Option<HandleFn>,
+    database_set_option: Option<
+        unsafe extern "C" fn(*mut RawHandle, *const c_char, *const c_char, *mut RawError) -> u8,
+    >,
+    database_release: Option<HandleFn>,
+
+    connection_commit: Option<UnusedFn>,
+    connection_get_info: Option<UnusedFn>,
+    connection_get_objects: Option<UnusedFn>,
+    connection_get_table_schema: Option<UnusedFn>,
+    connection_get_table_types: Option<UnusedFn>,
+    connection_init:
+        Option<unsafe extern "C" fn(*mut RawHandle, *mut RawHandle, *mut RawError) -> u8>,
+    connection_new: Option<HandleFn>,
+    connection_set_option: Option<UnusedFn>,
+    connection_read_partition: Option<UnusedFn>,
+    connection_release: Option<HandleFn>,
+    connection_rollback: Option<UnusedFn>,
+
+    statement_bind: Option<UnusedFn>,
+    statement_bind_stream: Option<UnusedFn>,
+    statement_execute_query:
+        Option<unsafe extern "C" fn(*mut RawHandle, *mut RawStream, *mut i64, *mut RawError) -> u8>,
+    statement_execute_partitions: Option<UnusedFn>,
+    statement_get_parameter_schema: Option<UnusedFn>,
+    statement_new:
+        Option<unsafe extern "C" fn(*mut RawHandle, *mut RawHandle, *mut RawError) -> u8>,
+    statement_prepare: Option<UnusedFn>,
+    statement_release: Option<HandleFn>,
+    statement_set_option: Option<UnusedFn>,
+    statement_set_sql_query:
+        Option<unsafe extern "C" fn(*mut RawHandle, *const c_char, *mut RawError) -> u8>,
+    statement_set_substrait_plan: Option<UnusedFn>,
+}
+
+type DriverInitFn = unsafe extern "C" fn(c_int, *mut RawDriver, *mut RawError) -> u8;
+
+/// `struct ArrowSchema` of the Arrow C data interface.
+#[repr(C)]
+struct RawSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut RawSchema,
+    dictionary: *mut RawSchema,
+    release: Option<unsafe extern "C" fn(*mut RawSchema)>,
+    private_data: *mut c_void,
+}
+
+/// `struct ArrowArray` of the Arrow C data interface.
+#[repr(C)]
+struct RawArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut RawArray,
+    dictionary: *mut RawArray,
+    release: Option<unsafe extern "C" fn(*mut RawArray)>,
+    private_data: *mut c_void,
+}
+
+/// `struct ArrowArrayStream` of the Arrow C stream interface.
+#[repr(C)]
+struct RawStream {
+    get_schema: Option<unsafe extern "C" fn(*mut RawStream, *mut RawSchema) -> c_int>,
+    get_next: Option<unsafe extern "C" fn(*mut RawStream, *mut RawArray) -> c_int>,
+    get_last_error: Option<unsafe extern "C" fn(*mut RawStream) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut RawStream)>,
+    private_data: *mut c_void,
+}
+
+/// Release a structure of the Arrow C interfaces if it's not released yet.
+macro_rules! release_on_drop {
+    ($($raw:ident),*) => {$(
+        impl Drop for $raw {
+            fn drop(&mut self) {
+                if let Some(release) = self.release {
+                    unsafe { release(self) }
+                }
+            }
+        }
+    )*};
+}
+
+release_on_drop!(RawSchema, RawArray, RawStream);
+
+/// A function of the driver, which may be missing in broken drivers.
+fn required<T>(function: Option<T>, name: &str) -> Result<T, String> {
+    function.ok_or_else(|| format!("adbc driver doesn't implement {}", name))
+}
+
+/// A loaded driver. The shared library is never unloaded, as most drivers
+/// don't support it.
+struct Driver(Box<RawDriver>);
+
+// drivers are only accessed through their functions, which are thread safe
+unsafe impl Send for Driver {}
+unsafe impl Sync for Driver {}
+
+impl Driver {
+    fn load(path: &str) -> Result<Self, String> {
+        let c_path = CString::new(path).map_err(|e| e.to_string())?;
+        let library = unsafe { dlopen(c_path.as_ptr(), RTLD_NOW) };
+        if library.is_null() {
+            return Err(format!(
+                "failed to load adbc driver `{}`, error: {}",
+                path,
+                dl_error()
+            ));
+        }
+        let init = unsafe { dlsym(library, ENTRYPOINT.as_ptr()) };
+        if init.is_null() {
+            return Err(format!(
+                "adbc driver `{}` has no entrypoint `AdbcDriverInit`",
+                path
+            ));
+        }
+        let init: DriverInitFn = unsafe { std::mem::transmute(init) };
+
+        // function pointers and raw pointers are valid when zeroed
+        let mut driver: Box<RawDriver> = Box::new(unsafe { std::mem::zeroed() });
+        let mut error = RawError::new();
+        let status = unsafe { init(ADBC_VERSION_1_0_0, &mut *driver, &mut error) };
+        error.check(status, "AdbcDriverInit")?;
+
+        Ok(Self(driver))
+    }
+
+    fn raw(&self) -> *mut RawDriver {
+        &*self.0 as *const RawDriver as *mut RawDriver
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        if let Some(release) = self.0.release {
+            unsafe { release(&mut *self.0, &mut RawError::new()) };
+        }
+    }
+}
+
+fn dl_error() -> String {
+    let error = unsafe { dlerror() };
+    if error.is_null() {
+        return "unknown error".to_string();
+    }
+
+    unsafe { CStr::from_ptr(error) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// An initialized `AdbcDatabase`, released when all its connections are
+/// dropped.
+struct Handle {
+    driver: Arc<Driver>,
+    database: Mutex<RawHandle>,
+}
+
+// access to the database is synchronized by the mutex
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    fn open(driver: Arc<Driver>, options: &Options) -> Result<Self, String> {
+        let functions = &driver.0;
+        let mut database = RawHandle::new();
+        let mut error = RawError::new();
+        let status = unsafe {
+            required(functions.database_new, "AdbcDatabaseNew")?(&mut database, &mut error)
+        };
+        error.check(status, "AdbcDatabaseNew")?;
+        database.private_driver = driver.raw();
+        // released by `Drop` from now on, even if options are invalid
+        let handle = Self {
+            database: Mutex::new(database),
+            driver: driver.clone(),
+        };
+
+        let mut database = handle.database.lock().unwrap();
+        let set_option = required(functions.database_set_option, "AdbcDatabaseSetOption")?;
+        for (key, value) in options {
+            let key = CString::new(key.as_str()).map_err(|e| e.to_string())?;
+            let value = CString::new(value.as_str()).map_err(|e| e.to_string())?;
+            let status =
+                unsafe { set_option(&mut *database, key.as_ptr(), value.as_ptr(), &mut error) };
+            error.check(status, "AdbcDatabaseSetOption")?;
+        }
+        let status = unsafe {
+            required(functions.database_init, "AdbcDatabaseInit")?(&mut *database, &mut error)
+        };
+        error.check(status, "AdbcDatabaseInit")?;
+        drop(database);
+
+        Ok(handle)
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        if let Some(release) = self.driver.0.database_release {
+            unsafe { release(self.database.get_mut().unwrap(), &mut RawError::new()) };
+        }
+    }
+}
+
+struct Connection {
+    handle: Arc<Handle>,
+    raw: RawHandle,
+}
+
+// a connection is only used by one thread at a time, behind a mutex
+unsafe impl Send for Connection {}
+
+impl Connection {
+    fn open(handle: &Arc<Handle>) -> Result<Self, String> {
+        let functions = &handle.driver.0;
+        let mut raw = RawHandle::new();
+        let mut error = RawError::new();
+        let status = unsafe {
+            required(functions.connection_new, "AdbcConnectionNew")?(&mut raw, &mut error)
+        };
+        error.check(status, "AdbcConnectionNew")?;
+        raw.private_driver = handle.driver.raw();
+        let mut connection = Self {
+            handle: handle.clone(),
+            raw,
+        };
+
+        let mut database = handle.database.lock().unwrap();
+        let status = unsafe {
+            required(functions.connection_init, "AdbcConnectionInit")?(
+                &mut connection.raw,
+                &mut *database,
+                &mut error,
+            )
+        };
+        error.check(status, "AdbcConnectionInit")?;
+
+        Ok(connection)
+    }
+
+    fn execute(&mut self, query: &str) -> Result<TypedResult, String> {
+        let functions = &self.handle.driver.0;
+        let c_query = CString::new(query).map_err(|e| e.to_string())?;
+        let mut statement = RawHandle::new();
+        let mut error = RawError::new();
+        let status = unsafe {
+            required(functions.statement_new, "AdbcStatementNew")?(
+                &mut self.raw,
+                &mut statement,
+                &mut error,
+            )
+        };
+        error.check(status, "AdbcStatementNew")?;
+        statement.private_driver = self.handle.driver.raw();
+
+        let result = unsafe { Self::execute_statement(functions, &mut statement, &c_query) };
+        // the statement must be released even if the query failed
+        if let Some(release) = functions.statement_release {
+            unsafe { release(&mut statement, &mut RawError::new()) };
+        }
+
+        result
+    }
+
+    /// Safety: `statement` is a new statement of this connection.
+    unsafe fn execute_statement(
+        functions: &RawDriver,
+        statement: &mut RawHandle,
+        query: &CStr,
+    ) -> Result<TypedResult, String> {
+        let mut error = RawError::new();
+        let status = required(
+            functions.statement_set_sql_query,
+            "AdbcStatementSetSqlQuery",
+        )?(statement, query.as_ptr(), &mut error);
+        error.check(status, "AdbcStatementSetSqlQuery")?;
+        let mut stream: RawStream = std::mem::zeroed();
+        let mut rows_affected = -1;
+        let status = required(
+            functions.statement_execute_query,
+            "AdbcStatementExecuteQuery",
+        )?(statement, &mut stream, &mut rows_affected, &mut error);
+        error.check(status, "AdbcStatementExecuteQuery")?;
+
+        read_stream(&mut stream)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Some(release) = self.handle.driver.0.connection_release {
+            unsafe { release(&mut self.raw, &mut RawError::new()) };
+        }
+    }
+}
+
+/// Read all record batches of `stream`.
+///
+/// Safety: `stream` is a valid stream returned by the driver.
+unsafe fn read_stream(stream: &mut RawStream) -> Result<TypedResult, String> {
+    let mut schema: RawSchema = std::mem::zeroed();
+    let status = required(stream.get_schema, "ArrowArrayStream.get_schema")?(stream, &mut schema);
+    stream_error(stream, status)?;
+    let formats: Vec<_> = (0..schema.n_children as usize)
+        .map(|column| string(&**schema.children.add(column), |child| child.format))
+        .collect();
+    let columns = (0..schema.n_children as usize)
+        .map(|column| TypedColumn {
+            name: string(&**schema.children.add(column), |child| child.name),
+            data_type: type_name(&formats[column]),
+        })
+        .collect();
+
+    let mut rows = vec![];
+    loop {
+        let mut batch: RawArray = std::mem::zeroed();
+        let status = required(stream.get_next, "ArrowArrayStream.get_next")?(stream, &mut batch);
+        stream_error(stream, status)?;
+        // a released array marks the end of the stream
+        if batch.release.is_none() {
+            break;
+        }
+        rows.extend(read_batch(&batch, &formats));
+    }
+
+    Ok(TypedResult { columns, rows })
+}
+
+fn stream_error(stream: &mut RawStream, status: c_int) -> Result<(), String> {
+    if status == 0 {
+        return Ok(());
+    }
+    let error = match stream.get_last_error {
+        Some(get_last_error) => unsafe { get_last_error(stream) },
+        None => ptr::null(),
+    };
+    if error.is_null() {
+        return Err(format!("failed to read results, error code {}", status));
+    }
+
+    Err(unsafe { CStr::from_ptr(error) }
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Safety: the field selected by `field` is null or a valid C string.
+unsafe fn string<T>(raw: &T, field: impl Fn(&T) -> *const c_char) -> String {
+    let ptr = field(raw);
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// Rows of a record batch, which is a struct array with one child array per
+/// column.
+///
+/// Safety: `batch` is a valid struct array of columns in `formats`.
+unsafe fn read_batch(batch: &RawArray, formats: &[String]) -> Vec<Vec<Option<String>>> {
+    (0..batch.length)
+        .map(|row| {
+            formats
+                .iter()
+                .enumerate()
+                .map(|(column, format)| {
+                    value(&**batch.children.add(column), format, batch.offset + row)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Arrow type name of C data interface `format`, like `Int64` or
+/// `Timestamp(Microsecond, Some("UTC"))`.
+fn type_name(format: &str) -> String {
+    let name = match format {
+        "n" => "Null",
+        "b" => "Boolean",
+        "c" => "Int8",
+        "C" => "UInt8",
+        "s" => "Int16",
+        "S" => "UInt16",
+        "i" => "Int32",
+        "I" => "UInt32",
+        "l" => "Int64",
+        "L" => "UInt64",
+        "e" => "Float16",
+        "f" => "Float32",
+        "g" => "Float64",
+        "z" => "Binary",
+        "Z" => "LargeBinary",
+        "u" => "Utf8",
+        "U" => "LargeUtf8",
+        "tdD" => "Date32",
+        "tdm" => "Date64",
+        "tts" => "Time32(Second)",
+        "ttm" => "Time32(Millisecond)",
+        "ttu" => "Time64(Microsecond)",
+        "ttn" => "Time64(Nanosecond)",
+        _ => {
+            if let Some(decimal) = format.strip_prefix("d:") {
+                let mut parts = decimal.split(',');
+                let precision = parts.next().unwrap_or_default();
+                let scale = parts.next().unwrap_or_default();
+                return match parts.next() {
+                    None | Some("128") => format!("Decimal128({}, {})", precision, scale),
+                    Some(bits) => format!("Decimal{}({}, {})", bits, precision, scale),
+                };
+            }
+            if let Some((unit, timezone)) = timestamp_unit(format) {
+                return match timezone {
+                    "" => format!("Timestamp({}, None)", unit_name(unit)),
+                    timezone => format!("Timestamp({}, Some({:?}))", unit_name(unit), timezone),
+                };
+            }
+            return format!("Unsupported({})", format);
+        }
+    };
+
+    name.to_string()
+}
+
+/// Unit (`s`, `m`, `u` or `n`) and timezone of a timestamp `format`.
+fn timestamp_unit(format: &str) -> Option<(char, &str)> {
+    let rest = format.strip_prefix("ts")?;
+    let mut chars = rest.chars();
+    let unit = chars.next()?;
+    let timezone = chars.as_str().strip_prefix(':')?;
+
+    Some((unit, timezone))
+}
+
+fn unit_name(unit: char) -> &'static str {
+    match unit {
+        's' => "Second",
+        'm' => "Millisecond",
+        'u' => "Microsecond",
+        _ => "Nanosecond",
+    }
+}
+
+/// Number of fractional digits and units per second of `unit`.
+fn unit_scale(unit: char) -> (usize, i64) {
+    match unit {
+        's' => (0, 1),
+        'm' => (3, 1_000),
+        'u' => (6, 1_000_000),
+        _ => (9, 1_000_000_000),
+    }
+}
+
+/// Value at `index` (including the offset of the parent) of `array` in
+/// `format`, `None` for nulls.
+///
+/// Safety: `array` is a valid array in `format`.
+unsafe fn value(array: &RawArray, format: &str, index: i64) -> Option<String> {
+    let index = (array.offset + index) as usize;
+    if format == "n" {
+        return None;
+    }
+    let validity = *array.buffers as *const u8;
+    if !validity.is_null() && !bit(validity, index) {
+        return None;
+    }
+
+    let buffer = |i: usize| *array.buffers.add(i);
+    let fixed = |size: usize| buffer(1).cast::<u8>().add(index * size);
+    let value = match format {
+        "b" => bit(buffer(1).cast(), index).to_string(),
+        "c" => fixed(1).cast::<i8>().read().to_string(),
+        "C" => fixed(1).read().to_string(),
+        "s" => fixed(2).cast::<i16>().read_unaligned().to_string(),
+        "S" => fixed(2).cast::<u16>().read_unaligned().to_string(),
+        "i" => fixed(4).cast::<i32>().read_unaligned().to_string(),
+        "I" => fixed(4).cast::<u32>().read_unaligned().to_string(),
+        "l" => fixed(8).cast::<i64>().read_unaligned().to_string(),
+        "L" => fixed(8).cast::<u64>().read_unaligned().to_string(),
+        "f" => fixed(4).cast::<f32>().read_unaligned().to_string(),
+        "g" => fixed(8).cast::<f64>().read_unaligned().to_string(),
+        "u" | "z" | "U" | "Z" => {
+            let (start, end) = if format == "u" || format == "z" {
+                let offsets = buffer(1).cast::<i32>();
+                (
+                    offsets.add(index).read_unaligned() as usize,
+                    offsets.add(index + 1).read_unaligned() as usize,
+                )
+            } else {
+                let offsets = buffer(1).cast::<i64>();
+                (
+                    offsets.add(index).read_unaligned() as usize,
+                    offsets.add(index + 1).read_unaligned() as usize,
+                )
+            };
+            let bytes = std::slice::from_raw_parts(buffer(2).cast::<u8>().add(start), end - start);
+            if format.eq_ignore_ascii_case("u") {
+                String::from_utf8_lossy(bytes).into_owned()
+            } else {
+                hex(bytes)
+            }
+        }
+        "tdD" => date(fixed(4).cast::<i32>().read_unaligned() as i64),
+        "tdm" => date(
+            fixed(8)
+                .cast::<i64>()
+                .read_unaligned()
+                .div_euclid(86_400_000),
+        ),
+        "tts" | "ttm" => {
+            let unit = format.chars().nth(2).unwrap();
+            time(fixed(4).cast::<i32>().read_unaligned() as i64, unit)
+        }
+        "ttu" | "ttn" => {
+            let unit = format.chars().nth(2).unwrap();
+            time(fixed(8).cast::<i64>().read_unaligned(), unit)
+        }
+        _ => {
+            if let Some(decimal) = format.strip_prefix("d:") {
+                let mut parts = decimal.split(',');
+                let scale = parts.nth(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+                if matches!(parts.next(), None | Some("128")) {
+                    return Some(decimal_string(
+                        fixed(16).cast::<i128>().read_unaligned(),
+                        scale,
+                    ));
+                }
+            } else if let Some((unit, _)) = timestamp_unit(format) {
+                return Some(timestamp(fixed(8).cast::<i64>().read_unaligned(), unit));
+            }
+            format!("<{}>", type_name(format))
+        }
+    };
+
+    Some(value)
+}
+
+/// Safety: `bitmap` holds at least `index + 1` bits.
+unsafe fn bit(bitmap: *const u8, index: usize) -> bool {
+    *bitmap.add(index / 8) & (1 << (index % 8)) != 0
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+
+    hex
+}
+
+/// `value` with `scale` digits after the decimal point.
+fn decimal_string(value: i128, scale: i32) -> String {
+    if scale <= 0 {
+        let mut digits = value.to_string();
+        if value != 0 {
+            digits.extend(std::iter::repeat_n('0', scale.unsigned_abs() as usize));
+        }
+        return digits;
+    }
+
+    let scale = scale as usize;
+    let digits = format!("{:0>width$}", value.unsigned_abs(), width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+
+    format!("{}{}.{}", sign, integer, fraction)
+}
+
+/// `YYYY-MM-DD` of `days` since the Unix epoch.
+fn date(days: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// `HH:MM:SS[.fraction]` of `value` in `unit` since midnight.
+fn time(value: i64, unit: char) -> String {
+    let (digits, per_second) = unit_scale(unit);
+    let seconds = value.div_euclid(per_second);
+    let mut time = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    if digits > 0 {
+        write!(
+            time,
+            ".{:0width$}",
+            value.rem_euclid(per_second),
+            width = digits
+        )
+        .unwrap();
+    }
+
+    time
+}
+
+/// `YYYY-MM-DD HH:MM:SS[.fraction]` of `value` in `unit` since the Unix
+/// epoch, in UTC.
+fn timestamp(value: i64, unit: char) -> String {
+    let (_, per_second) = unit_scale(unit);
+    let per_day = per_second * 86_400;
+
+    format!(
+        "{} {}",
+        date(value.div_euclid(per_day)),
+        time(value.rem_euclid(per_day), unit)
+    )
+}
+
+/// Options of a database, as (key, value).
+type Options = Vec<(String, String)>;
+
+/// Path of the driver and options of the database in DSN `rest` (after
+/// `adbc://`), like `/path/to/driver.so?uri=file:test.db&key=value`.
+fn parse_dsn(rest: &str) -> Result<(&str, Options), String> {
+    let (path, params) = rest.split_once('?').unwrap_or((rest, ""));
+    if path.is_empty() {
+        return Err(
+            "adbc dsn requires a driver library, like `adbc:///path/to/libadbc_driver_sqlite.so`"
+                .to_string(),
+        );
+    }
+    let options = params
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| match param.split_once('=') {
+            Some((key, value)) => Ok((key.to_string(), value.to_string())),
+            None => Err(format!(
+                "invalid adbc option `{}`, expect `key=value`",
+                param
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((path, options))
+}
+
+/// A connection of a database opened by an ADBC driver, from DSN like
+/// `adbc:///path/to/libadbc_driver_sqlite.so?uri=file:/tmp/test.db`.
+pub struct Adbc {
+    handle: Arc<Handle>,
+    connection: Mutex<Connection>,
+}
+
+impl Adbc {
+    /// Load the driver and open the database, `rest` is the DSN after
+    /// `adbc://`.
+    pub fn open(rest: &str) -> Result<Self, String> {
+        let (path, options) = parse_dsn(rest)?;
+        let driver = Arc::new(Driver::load(path)?);
+        let handle = Arc::new(Handle::open(driver, &options)?);
+        let connection = Connection::open(&handle)?;
+
+        Ok(Self {
+            handle,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Another connection of the same database.
+    pub fn connect(&self) -> Result<Self, String> {
+        Ok(Self {
+            handle: self.handle.clone(),
+            connection: Mutex::new(Connection::open(&self.handle)?),
+        })
+    }
+
+    fn execute(&self, query: &str) -> Result<TypedResult, String> {
+        self.connection.lock().unwrap().execute(query)
+    }
+}
+
+impl BlockingDatabase for Adbc {
+    fn query(&self, _context: ExecutionContext, query: String) -> Box<dyn Display + Send> {
+        match self.execute(&query) {
+            Ok(result) => Box::new(result),
+            Err(e) => Box::new(e),
+        }
+    }
+
+    fn query_typed(
+        &self,
+        _context: ExecutionContext,
+        query: String,
+    ) -> Option<Result<TypedResult, String>> {
+        Some(self.execute(&query))
+    }
+
+    fn reconnect(&self) {
+        match Connection::open(&self.handle) {
+            Ok(connection) => *self.connection.lock().unwrap() = connection,
+            Err(e) => println!("Failed to reconnect, error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_names() {
+        assert_eq!(type_name("l"), "Int64");
+        assert_eq!(type_name("u"), "Utf8");
+        assert_eq!(type_name("d:10,2"), "Decimal128(10, 2)");
+        assert_eq!(type_name("d:40,2,256"), "Decimal256(40, 2)");
+        assert_eq!(type_name("tsu:"), "Timestamp(Microsecond, None)");
+        assert_eq!(type_name("tsn:UTC"), "Timestamp(Nanosecond, Some(\"UTC\"))");
+        assert_eq!(type_name("+l"), "Unsupported(+l)");
+    }
+
+    #[test]
+    fn temporal_values() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(19_782), "2024-02-29");
+        assert_eq!(date(-1), "1969-12-31");
+        assert_eq!(time(3_723_000, 'm'), "01:02:03.000");
+        assert_eq!(time(59, 's'), "00:00:59");
+        assert_eq!(
+            timestamp(1_709_164_800_123_456, 'u'),
+            "2024-02-29 00:00:00.123456"
+        );
+        assert_eq!(timestamp(-1, 's'), "1969-12-31 23:59:59");
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!(decimal_string(12345, 2), "123.45");
+        assert_eq!(decimal_string(-5, 3), "-0.005");
+        assert_eq!(decimal_string(0, 2), "0.00");
+        assert_eq!(decimal_string(12, -2), "1200");
+    }
+
+    #[test]
+    fn record_batches() {
+        // Int64 column [1, NULL, 3] and Utf8 column ["a", "bc", ""], in a
+        // batch sliced from the second row
+        let validity = [0b101u8];
+        let ints = [1i64, 0, 3];
+        let mut int_buffers = [validity.as_ptr().cast(), ints.as_ptr().cast()];
+        let offsets = [0i32, 1, 3, 3];
+        let data = b"abc";
+        let mut str_buffers = [ptr::null(), offsets.as_ptr().cast(), data.as_ptr().cast()];
+        let mut int_array = RawArray {
+            length: 3,
+            null_count: 1,
+            n_buffers: 2,
+            buffers: int_buffers.as_mut_ptr(),
+            ..unsafe { std::mem::zeroed() }
+        };
+        let mut str_array = RawArray {
+            length: 3,
+            n_buffers: 3,
+            buffers: str_buffers.as_mut_ptr(),
+            ..unsafe { std::mem::zeroed() }
+        };
+        let mut children = [&mut int_array as *mut RawArray, &mut str_array];
+        let batch = RawArray {
+            length: 2,
+            offset: 1,
+            n_children: 2,
+            children: children.as_mut_ptr(),
+            ..unsafe { std::mem::zeroed() }
+        };
+
+        let rows = unsafe { read_batch(&batch, &["l".to_string(), "u".to_string()]) };
+        assert_eq!(
+            rows,
+            vec![
+                vec![None, Some("bc".to_string())],
+                vec![Some("3".to_string()), Some("".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn dsn() {
+        let (path, options) = parse_dsn("/lib/driver.so?uri=file:a.db&mode=ro").unwrap();
+        assert_eq!(path, "/lib/driver.so");
+        assert_eq!(
+            options,
+            vec![
+                ("uri".to_string(), "file:a.db".to_string()),
+                ("mode".to_string(), "ro".to_string())
+            ]
+        );
+        assert!(parse_dsn("?uri=a").is_err());
+        assert!(parse_dsn("/lib/driver.so?uri").is_err());
+
+        let error = Adbc::open("/nonexistent/driver.so").err().unwrap();
+        assert!(error.contains("failed to load adbc driver"), "{}", error);
+    }
+}
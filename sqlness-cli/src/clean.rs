@@ -0,0 +1,102 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Removal of files left by runs, for `sqlness-cli clean`.
+
+use std::path::{Path, PathBuf};
+
+use sqlness::{Config, SqlnessError};
+
+/// Prefix of directories created by `EmbeddedCases::extract_to_temp_dir`.
+const TEMP_DIR_PREFIX: &str = "sqlness-";
+
+/// Remove output files under [`Config::case_dir`] of config at `config_path`,
+/// and directories of embedded cases extracted to the temporary directory if
+/// `temp_dirs`. Only print them if `dry_run`.
+pub async fn clean(config_path: &Path, temp_dirs: bool, dry_run: bool) -> Result<(), SqlnessError> {
+    let config = Config::from_file(config_path).await?;
+    let mut files = vec![];
+    collect_files(
+        Path::new(&config.case_dir),
+        &config.output_result_extension,
+        &mut files,
+    )
+    .await?;
+    files.sort();
+    let dirs = if temp_dirs {
+        extracted_dirs().await?
+    } else {
+        vec![]
+    };
+
+    let action = if dry_run { "Would remove" } else { "Removed" };
+    for file in &files {
+        if !dry_run {
+            tokio::fs::remove_file(file).await?;
+        }
+        println!("{} {}", action, file.display());
+    }
+    for dir in &dirs {
+        if !dry_run {
+            tokio::fs::remove_dir_all(dir).await?;
+        }
+        println!("{} {}", action, dir.display());
+    }
+    println!(
+        "{} {} files and {} directories",
+        action,
+        files.len(),
+        dirs.len()
+    );
+
+    Ok(())
+}
+
+/// Collect files with `extension` under `dir` recursively into `files`.
+pub async fn collect_files(
+    dir: &Path,
+    extension: &str,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), SqlnessError> {
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .map_err(|e| SqlnessError::ReadPath {
+                source: e,
+                path: dir.clone(),
+            })?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .map(|ext| ext == extension)
+                .unwrap_or(false)
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Directories like `sqlness-<hex>` under the temporary directory.
+async fn extracted_dirs() -> Result<Vec<PathBuf>, SqlnessError> {
+    let mut dirs = vec![];
+    let mut entries = tokio::fs::read_dir(std::env::temp_dir()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_extracted = name
+            .strip_prefix(TEMP_DIR_PREFIX)
+            .map(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .unwrap_or(false);
+        if is_extracted && entry.file_type().await?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+
+    Ok(dirs)
+}
@@ -0,0 +1,253 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! In-process DuckDB via its C API, enabled by the `duckdb` feature, which
+//! links to `libduckdb`. Unlike the `duckdb` command line client, sessions
+//! and in-memory databases live across queries, and results are typed.
+
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    fmt::Display,
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use sqlness::{BlockingDatabase, ExecutionContext, TypedColumn, TypedResult};
+
+type RawDatabase = *mut c_void;
+type RawConnection = *mut c_void;
+
+/// `duckdb_state` of successful calls.
+const DUCKDB_SUCCESS: c_int = 0;
+
+/// `duckdb_result`, whose fields are only accessed through functions.
+#[repr(C)]
+struct RawResult {
+    column_count: u64,
+    row_count: u64,
+    rows_changed: u64,
+    columns: *mut c_void,
+    error_message: *mut c_char,
+    internal_data: *mut c_void,
+}
+
+#[link(name = "duckdb")]
+extern "C" {
+    fn duckdb_open(path: *const c_char, out_database: *mut RawDatabase) -> c_int;
+    fn duckdb_close(database: *mut RawDatabase);
+    fn duckdb_connect(database: RawDatabase, out_connection: *mut RawConnection) -> c_int;
+    fn duckdb_disconnect(connection: *mut RawConnection);
+    fn duckdb_query(
+        connection: RawConnection,
+        query: *const c_char,
+        out_result: *mut RawResult,
+    ) -> c_int;
+    fn duckdb_destroy_result(result: *mut RawResult);
+    fn duckdb_result_error(result: *mut RawResult) -> *const c_char;
+    fn duckdb_column_count(result: *mut RawResult) -> u64;
+    fn duckdb_row_count(result: *mut RawResult) -> u64;
+    fn duckdb_column_name(result: *mut RawResult, column: u64) -> *const c_char;
+    fn duckdb_column_type(result: *mut RawResult, column: u64) -> c_int;
+    fn duckdb_value_is_null(result: *mut RawResult, column: u64, row: u64) -> bool;
+    fn duckdb_value_varchar(result: *mut RawResult, column: u64, row: u64) -> *mut c_char;
+    fn duckdb_free(ptr: *mut c_void);
+}
+
+/// An opened database file, or an in-memory database, closed when all its
+/// connections are dropped.
+struct Handle(RawDatabase);
+
+// DuckDB databases can be connected from any thread
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { duckdb_close(&mut self.0) }
+    }
+}
+
+struct Connection(RawConnection);
+
+// a connection is only used by one thread at a time, behind a mutex
+unsafe impl Send for Connection {}
+
+impl Connection {
+    fn open(handle: &Handle) -> Result<Self, String> {
+        let mut connection = ptr::null_mut();
+        if unsafe { duckdb_connect(handle.0, &mut connection) } != DUCKDB_SUCCESS {
+            return Err("failed to connect to duckdb".to_string());
+        }
+
+        Ok(Self(connection))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { duckdb_disconnect(&mut self.0) }
+    }
+}
+
+/// A session of an in-process DuckDB database, from DSN like
+/// `duckdb:///path/to/file.db`, or `duckdb://` for an in-memory database.
+pub struct DuckDb {
+    handle: Arc<Handle>,
+    connection: Mutex<Connection>,
+}
+
+impl DuckDb {
+    /// Open the database file at `path`, or an in-memory database if it's
+    /// empty.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let c_path = CString::new(path).map_err(|e| e.to_string())?;
+        let mut database = ptr::null_mut();
+        let path_ptr = if path.is_empty() {
+            ptr::null()
+        } else {
+            c_path.as_ptr()
+        };
+        if unsafe { duckdb_open(path_ptr, &mut database) } != DUCKDB_SUCCESS {
+            return Err(format!("failed to open duckdb database `{}`", path));
+        }
+        let handle = Arc::new(Handle(database));
+        let connection = Connection::open(&handle)?;
+
+        Ok(Self {
+            handle,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Another session of the same database.
+    pub fn connect(&self) -> Result<Self, String> {
+        Ok(Self {
+            handle: self.handle.clone(),
+            connection: Mutex::new(Connection::open(&self.handle)?),
+        })
+    }
+
+    fn execute(&self, query: &str) -> Result<TypedResult, String> {
+        let c_query = CString::new(query).map_err(|e| e.to_string())?;
+        let connection = self.connection.lock().unwrap();
+        let mut raw = RawResult {
+            column_count: 0,
+            row_count: 0,
+            rows_changed: 0,
+            columns: ptr::null_mut(),
+            error_message: ptr::null_mut(),
+            internal_data: ptr::null_mut(),
+        };
+        // the result must be destroyed even if the query failed
+        let state = unsafe { duckdb_query(connection.0, c_query.as_ptr(), &mut raw) };
+        let result = if state == DUCKDB_SUCCESS {
+            Ok(unsafe { Self::read_result(&mut raw) })
+        } else {
+            let error = unsafe { duckdb_result_error(&mut raw) };
+            Err(if error.is_null() {
+                "query failed".to_string()
+            } else {
+                unsafe { CStr::from_ptr(error) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        };
+        unsafe { duckdb_destroy_result(&mut raw) };
+
+        result
+    }
+
+    /// Safety: `raw` is the result of a successful query.
+    unsafe fn read_result(raw: &mut RawResult) -> TypedResult {
+        let column_count = duckdb_column_count(raw);
+        let row_count = duckdb_row_count(raw);
+        let columns = (0..column_count)
+            .map(|column| TypedColumn {
+                name: CStr::from_ptr(duckdb_column_name(raw, column))
+                    .to_string_lossy()
+                    .into_owned(),
+                data_type: type_name(duckdb_column_type(raw, column)).to_string(),
+            })
+            .collect();
+        let rows = (0..row_count)
+            .map(|row| {
+                (0..column_count)
+                    .map(|column| {
+                        if duckdb_value_is_null(raw, column, row) {
+                            return None;
+                        }
+                        let value = duckdb_value_varchar(raw, column, row);
+                        let text = CStr::from_ptr(value).to_string_lossy().into_owned();
+                        duckdb_free(value.cast());
+                        Some(text)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        TypedResult { columns, rows }
+    }
+}
+
+/// Name of `duckdb_type`, as in DuckDB's SQL.
+fn type_name(duckdb_type: c_int) -> &'static str {
+    match duckdb_type {
+        1 => "BOOLEAN",
+        2 => "TINYINT",
+        3 => "SMALLINT",
+        4 => "INTEGER",
+        5 => "BIGINT",
+        6 => "UTINYINT",
+        7 => "USMALLINT",
+        8 => "UINTEGER",
+        9 => "UBIGINT",
+        10 => "FLOAT",
+        11 => "DOUBLE",
+        12 => "TIMESTAMP",
+        13 => "DATE",
+        14 => "TIME",
+        15 => "INTERVAL",
+        16 => "HUGEINT",
+        17 => "VARCHAR",
+        18 => "BLOB",
+        19 => "DECIMAL",
+        20 => "TIMESTAMP_S",
+        21 => "TIMESTAMP_MS",
+        22 => "TIMESTAMP_NS",
+        23 => "ENUM",
+        24 => "LIST",
+        25 => "STRUCT",
+        26 => "MAP",
+        27 => "UUID",
+        28 => "UNION",
+        29 => "BIT",
+        30 => "TIME_TZ",
+        31 => "TIMESTAMP_TZ",
+        32 => "UHUGEINT",
+        33 => "ARRAY",
+        _ => "UNKNOWN",
+    }
+}
+
+impl BlockingDatabase for DuckDb {
+    fn query(&self, _context: ExecutionContext, query: String) -> Box<dyn Display + Send> {
+        match self.execute(&query) {
+            Ok(result) => Box::new(result),
+            Err(e) => Box::new(e),
+        }
+    }
+
+    fn query_typed(
+        &self,
+        _context: ExecutionContext,
+        query: String,
+    ) -> Option<Result<TypedResult, String>> {
+        Some(self.execute(&query))
+    }
+
+    fn reconnect(&self) {
+        match Connection::open(&self.handle) {
+            Ok(connection) => *self.connection.lock().unwrap() = connection,
+            Err(e) => println!("Failed to reconnect, error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Detection of duplicate cases, for `sqlness-cli duplicates`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use sqlness::{discovery, Config, SqlnessError, TestCase};
+
+/// Queries of a case, normalized.
+struct Fingerprint {
+    path: PathBuf,
+    queries: Vec<String>,
+    distinct: BTreeSet<String>,
+}
+
+/// Normalize `query` so that differences of whitespaces and cases don't
+/// matter.
+fn normalize(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Report cases with the same queries (in the same order) as exact
+/// duplicates, and pairs of cases sharing at least `threshold` (0 to 1) of
+/// their distinct queries as near duplicates.
+pub async fn duplicates(config_path: &Path, threshold: f64) -> Result<(), SqlnessError> {
+    let config = Config::from_file(config_path).await?;
+    let mut cases = vec![];
+    for env in discovery::collect_envs(&config).await? {
+        for path in discovery::collect_case_paths(&config, &env).await? {
+            let case =
+                TestCase::from_file(path.with_extension(&config.test_case_extension), &config)
+                    .await?;
+            let queries: Vec<_> = case.query_texts().iter().map(|q| normalize(q)).collect();
+            if queries.is_empty() {
+                continue;
+            }
+            cases.push(Fingerprint {
+                path,
+                distinct: queries.iter().cloned().collect(),
+                queries,
+            });
+        }
+    }
+
+    let mut exact: BTreeMap<&[String], Vec<&Path>> = BTreeMap::new();
+    for case in &cases {
+        exact.entry(&case.queries).or_default().push(&case.path);
+    }
+    let exact: Vec<_> = exact
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect();
+    if !exact.is_empty() {
+        println!("Exact duplicates:");
+        for paths in &exact {
+            let paths: Vec<_> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            println!("    {}", paths.join(" == "));
+        }
+    }
+
+    // only cases sharing a query are compared
+    let mut by_query: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, case) in cases.iter().enumerate() {
+        for query in &case.distinct {
+            by_query.entry(query).or_default().push(i);
+        }
+    }
+    let mut shared: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for indexes in by_query.values() {
+        for (n, i) in indexes.iter().enumerate() {
+            for j in &indexes[n + 1..] {
+                *shared.entry((*i, *j)).or_default() += 1;
+            }
+        }
+    }
+    let mut near = vec![];
+    for ((i, j), count) in shared {
+        let (a, b) = (&cases[i], &cases[j]);
+        if a.queries == b.queries {
+            continue;
+        }
+        let similarity = count as f64 / (a.distinct.len() + b.distinct.len() - count) as f64;
+        if similarity >= threshold {
+            near.push((similarity, &a.path, &b.path));
+        }
+    }
+    near.sort_by(|a, b| b.0.total_cmp(&a.0));
+    if !near.is_empty() {
+        println!(
+            "Near duplicates (sharing at least {:.0}% of distinct queries):",
+            threshold * 100.0
+        );
+        for (similarity, a, b) in &near {
+            println!(
+                "    {} ~ {} ({:.0}%)",
+                a.display(),
+                b.display(),
+                similarity * 100.0
+            );
+        }
+    }
+
+    println!(
+        "{} cases checked, {} groups of exact duplicates, {} pairs of near duplicates",
+        cases.len(),
+        exact.len(),
+        near.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,269 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Formatter of case files, for `sqlness-cli fmt`.
+//!
+//! Directives and queries are echoed in expected results, so their headers in
+//! `<case>.result` are rewritten along with the case.
+
+use std::{collections::HashMap, path::Path};
+
+use sqlness::{Config, SqlnessError, TestCase};
+
+use crate::clean::collect_files;
+
+/// Keywords cased by `--keyword-case`.
+const KEYWORDS: &[&str] = &[
+    "ADD", "ALL", "ALTER", "AND", "AS", "ASC", "BETWEEN", "BY", "CASE", "CAST", "COLUMN", "CREATE",
+    "CROSS", "DATABASE", "DEFAULT", "DELETE", "DESC", "DESCRIBE", "DISTINCT", "DROP", "ELSE",
+    "END", "EXISTS", "EXPLAIN", "FALSE", "FROM", "FULL", "GROUP", "HAVING", "IF", "IN", "INDEX",
+    "INNER", "INSERT", "INTERVAL", "INTO", "IS", "JOIN", "LEFT", "LIKE", "LIMIT", "NOT", "NULL",
+    "OFFSET", "ON", "OR", "ORDER", "OUTER", "PRIMARY", "RIGHT", "SELECT", "SET", "SHOW", "TABLE",
+    "TABLES", "THEN", "TRUE", "UNION", "UPDATE", "USE", "USING", "VALUES", "VIEW", "WHEN", "WHERE",
+    "WITH",
+];
+
+/// Case of SQL keywords, see [`KEYWORDS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+}
+
+impl std::str::FromStr for KeywordCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(Self::Upper),
+            "lower" => Ok(Self::Lower),
+            _ => Err(format!("expect `upper` or `lower`, got `{}`", s)),
+        }
+    }
+}
+
+struct Formatter<'a> {
+    config: &'a Config,
+    keyword_case: Option<KeywordCase>,
+    /// Uppercase directive names to themselves.
+    directives: HashMap<String, &'static str>,
+}
+
+/// Format case files under [`Config::case_dir`] of config at `config_path`.
+/// With `check`, files are not written, and an error is returned if any of
+/// them would change.
+pub async fn fmt(
+    config_path: &Path,
+    keyword_case: Option<KeywordCase>,
+    check: bool,
+) -> Result<(), SqlnessError> {
+    let config = Config::from_file(config_path).await?;
+    let formatter = Formatter {
+        config: &config,
+        keyword_case,
+        directives: TestCase::directive_names()
+            .into_iter()
+            .map(|name| (name.to_string(), name))
+            .collect(),
+    };
+    let mut paths = vec![];
+    collect_files(
+        Path::new(&config.case_dir),
+        &config.test_case_extension,
+        &mut paths,
+    )
+    .await?;
+    paths.sort();
+
+    let mut changed = 0;
+    for path in &paths {
+        let content = config.file_encoding.decode(tokio::fs::read(path).await?)?;
+        let formatted = formatter.format_case(&content);
+        if formatted == content {
+            continue;
+        }
+        // formatting must not change how queries are split
+        let name = path.display().to_string();
+        let query_count = TestCase::from_reader(name.clone(), content.as_bytes(), &config)
+            .await?
+            .query_count();
+        let formatted_count = TestCase::from_reader(name, formatted.as_bytes(), &config)
+            .await?
+            .query_count();
+        if query_count != formatted_count {
+            println!(
+                "Skipped {}, formatting changes {} queries into {}",
+                path.display(),
+                query_count,
+                formatted_count
+            );
+            continue;
+        }
+        changed += 1;
+        if check {
+            println!("Would format {}", path.display());
+            continue;
+        }
+
+        let result_path = path.with_extension(&config.expect_result_extension);
+        let result = if result_path.exists() {
+            let case = TestCase::from_file(path, &config).await?;
+            let result = config
+                .file_encoding
+                .decode(tokio::fs::read(&result_path).await?)?;
+            Some(formatter.format_result(&case, &result))
+        } else {
+            None
+        };
+        tokio::fs::write(path, config.file_encoding.encode(&formatted)).await?;
+        if let Some(result) = result {
+            tokio::fs::write(&result_path, config.file_encoding.encode(&result)).await?;
+        }
+        println!("Formatted {}", path.display());
+    }
+
+    if check && changed > 0 {
+        return Err(SqlnessError::RunFailed { count: changed });
+    }
+    println!("{} of {} case files formatted", changed, paths.len());
+
+    Ok(())
+}
+
+impl Formatter<'_> {
+    /// Format `content` of a case file. Runs of blank lines are collapsed,
+    /// queries are followed by a blank line, and the file ends with exactly
+    /// one newline.
+    fn format_case(&self, content: &str) -> String {
+        let mut lines: Vec<String> = vec![];
+        let mut query_ended = false;
+        for line in content.lines() {
+            let after_blank = lines.last().map(String::is_empty).unwrap_or(true);
+            if line.is_empty() {
+                if !after_blank {
+                    lines.push(String::new());
+                }
+                continue;
+            }
+            if query_ended && !after_blank {
+                lines.push(String::new());
+            }
+
+            let is_comment = line.starts_with(&self.config.comment_prefix)
+                && !line.starts_with(&self.config.interceptor_prefix);
+            query_ended = !is_comment
+                && !line.starts_with(&self.config.interceptor_prefix)
+                && self.is_query_end(line);
+            lines.push(if is_comment {
+                line.to_string()
+            } else {
+                self.format_line(line)
+            });
+        }
+        while lines.last().map(String::is_empty).unwrap_or(false) {
+            lines.pop();
+        }
+        if lines.is_empty() {
+            return String::new();
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Format headers of query blocks in `result`, the expected result of
+    /// `case` before it's formatted.
+    fn format_result(&self, case: &TestCase, result: &str) -> String {
+        let mut lines: Vec<_> = result.split('\n').map(str::to_string).collect();
+        for block in case.split_result(result) {
+            // the header ends with a blank line before the result
+            for line in &mut lines[block.line - 1..block.result_line - 2] {
+                *line = self.format_line(line);
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    fn is_query_end(&self, line: &str) -> bool {
+        match self.config.query_delimiter.as_str() {
+            "\n" => true,
+            delimiter => line.ends_with(delimiter),
+        }
+    }
+
+    /// Format a line of directive or query, which is echoed in results.
+    fn format_line(&self, line: &str) -> String {
+        match line.strip_prefix(&self.config.interceptor_prefix) {
+            Some(directive) => self.format_directive(directive),
+            None => self.format_query_line(line),
+        }
+    }
+
+    /// Uppercase the name of `directive` (without prefix) if it's known, and
+    /// separate it from the prefix with one space.
+    fn format_directive(&self, directive: &str) -> String {
+        let prefix = &self.config.interceptor_prefix;
+        // continuation lines like `-- SQLNESS+ ...` are kept
+        if directive.starts_with('+') {
+            return format!("{}{}", prefix, directive);
+        }
+        let directive = directive.trim_start();
+        let (name, args) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        match self.directives.get(&name.to_uppercase()) {
+            Some(name) if args.is_empty() => format!("{} {}", prefix, name),
+            Some(name) => format!("{} {} {}", prefix, name, args),
+            None => format!("{} {}", prefix, directive),
+        }
+    }
+
+    /// Change the case of keywords outside of quotes and trailing comments.
+    fn format_query_line(&self, line: &str) -> String {
+        let keyword_case = match self.keyword_case {
+            Some(keyword_case) => keyword_case,
+            None => return line.to_string(),
+        };
+
+        let mut formatted = String::with_capacity(line.len());
+        let mut chars = line.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c == '\'' || c == '"' || c == '`' {
+                let mut end = line.len();
+                for (i, next) in chars.by_ref() {
+                    if next == c {
+                        end = i + next.len_utf8();
+                        break;
+                    }
+                }
+                formatted.push_str(&line[start..end]);
+            } else if line[start..].starts_with("--") {
+                formatted.push_str(&line[start..]);
+                break;
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let mut end = line.len();
+                while let Some(&(i, next)) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || next == '_') {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                let word = &line[start..end];
+                // words like `t.select` are identifiers
+                let qualified = line[..start].ends_with('.');
+                if !qualified && KEYWORDS.contains(&word.to_uppercase().as_str()) {
+                    formatted.push_str(&match keyword_case {
+                        KeywordCase::Upper => word.to_uppercase(),
+                        KeywordCase::Lower => word.to_lowercase(),
+                    });
+                } else {
+                    formatted.push_str(word);
+                }
+            } else {
+                formatted.push(c);
+            }
+        }
+
+        formatted
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Scaffold of a new suite, written by `sqlness-cli init`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use sqlness::SqlnessError;
+
+/// Name of the case directory, beside the root config.
+const CASE_DIR: &str = "cases";
+/// Name of the example environment.
+const ENV: &str = "local";
+
+/// Root config, with `{case_dir}` replaced.
+const CONFIG_TEMPLATE: &str = r#"# Config of sqlness, see https://docs.rs/sqlness/latest/sqlness/struct.Config.html
+# Commented values are defaults.
+
+# Directory of cases, whose sub-directories are environments.
+case_dir = "{case_dir}"
+
+# Create expected results (`.result`) from outputs of cases without them, which
+# is handy for new cases. Remove it to fail such cases instead.
+missing_result = "create"
+
+# test_case_extension = "sql"
+# output_result_extension = "output"
+# expect_result_extension = "result"
+# interceptor_prefix = "-- SQLNESS"
+# comment_prefix = "--"
+# query_delimiter = ";"
+
+# Config file of each environment, under its directory.
+# env_config_file = "config.toml"
+
+# Stop as soon as one case fails.
+# fail_fast = true
+
+# Cases of an environment running at the same time, and cases running alone.
+# parallelism = 1
+# serial_patterns = ["*/ddl/"]
+
+# Only run cases containing this string in their names.
+# test_filter = ""
+
+# Overwrite expected results with outputs of different cases.
+# update_results = false
+
+# Timeouts of starting and stopping environments, and budget of the whole run.
+# env_start_timeout = "5m"
+# env_stop_timeout = "30s"
+# max_run_duration = "30m"
+
+# `unified` or `side_by_side`, and lines of context around changes.
+# diff_style = "unified"
+# diff_context = 0
+
+# Reports for CI.
+# html_report = "target/sqlness/report.html"
+# markdown_report = "target/sqlness/summary.md"
+# allure_results_dir = "target/sqlness/allure-results"
+
+# Rules applied to results of all queries.
+# [normalize]
+# replace = [
+#     { pattern = "elapsed:\\s\\d+ms", replacement = "elapsed: <elapsed>" },
+# ]
+"#;
+
+/// Config of the example environment.
+const ENV_CONFIG: &str = r#"# Config of this environment, passed to `EnvController::start`.
+
+# Server to connect, used by `ExternalEnv` and `sqlness-cli run`.
+# dsn = "mysql://root@127.0.0.1:3306/test"
+
+# Statements run on every new connection.
+# init_statements = ["SET time_zone = 'UTC'"]
+"#;
+
+/// The example case.
+const EXAMPLE_CASE: &str = r#"-- Queries end with `;`, and their results are recorded in `select.result`.
+SELECT 1;
+
+-- Directives like `SORT_RESULT` apply to the query following them.
+-- SQLNESS SORT_RESULT
+SELECT 2 UNION ALL SELECT 1;
+"#;
+
+/// Write the root config at `config_path`, and an example environment and case
+/// beside it. Existing files are kept unless `force`.
+pub async fn init(config_path: &Path, force: bool) -> Result<(), SqlnessError> {
+    let case_dir = config_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(CASE_DIR);
+    let env_dir = case_dir.join(ENV);
+    let config = CONFIG_TEMPLATE.replace("{case_dir}", &case_dir.display().to_string());
+    let files: [(PathBuf, &str); 3] = [
+        (config_path.to_path_buf(), &config),
+        (env_dir.join("config.toml"), ENV_CONFIG),
+        (env_dir.join("select.sql"), EXAMPLE_CASE),
+    ];
+
+    if !force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(SqlnessError::IO(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists, use --force to overwrite",
+                    path.display()
+                ),
+            )));
+        }
+    }
+    tokio::fs::create_dir_all(&env_dir).await?;
+    for (path, content) in &files {
+        tokio::fs::write(path, content).await?;
+        println!("Created {}", path.display());
+    }
+
+    Ok(())
+}
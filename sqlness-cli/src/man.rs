@@ -0,0 +1,108 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Man pages rendered from help messages of the command line.
+
+use std::fmt::Write;
+
+use structopt::clap::{App, ErrorKind};
+
+/// Help message of `subcommand` of `app`, or of `app` itself if it's `None`.
+fn help(app: App, subcommand: Option<&str>) -> String {
+    let mut args = vec![app.get_name().to_string()];
+    args.extend(subcommand.map(str::to_string));
+    args.push("--help".to_string());
+    match app.get_matches_from_safe(args) {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => e.message,
+        _ => String::new(),
+    }
+}
+
+/// Names of subcommands listed in the help message of `app`, except `help`.
+fn subcommands(app: App) -> Vec<String> {
+    help(app, None)
+        .lines()
+        .skip_while(|line| line.trim() != "SUBCOMMANDS:")
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        // wrapped descriptions are indented deeper
+        .filter(|line| line.starts_with("    ") && !line.starts_with("     "))
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| *name != "help")
+        .map(str::to_string)
+        .collect()
+}
+
+/// Man pages of `app` and its subcommands, as (file name, content). Pages of
+/// subcommands are named like `sqlness-cli-run.1`.
+pub fn pages(app: impl Fn() -> App<'static, 'static>) -> Vec<(String, String)> {
+    let name = app().get_name().to_string();
+    let version = env!("CARGO_PKG_VERSION");
+    let mut pages = vec![(
+        format!("{}.1", name),
+        page(&name, &name, version, &help(app(), None)),
+    )];
+    for subcommand in subcommands(app()) {
+        let page_name = format!("{}-{}", name, subcommand);
+        let content = page(&page_name, &name, version, &help(app(), Some(&subcommand)));
+        pages.push((format!("{}.1", page_name), content));
+    }
+
+    pages
+}
+
+/// Render help message `help` of command `name` of tool `tool` in roff.
+/// Sections like `USAGE:` become headings, and others are kept as
+/// preformatted text.
+fn page(name: &str, tool: &str, version: &str, help: &str) -> String {
+    let mut lines = help.lines().peekable();
+    // the first line is the name and version
+    lines.next();
+    let mut about = vec![];
+    while let Some(line) = lines.next_if(|line| !is_heading(line)) {
+        if !line.trim().is_empty() {
+            about.push(line.trim());
+        }
+    }
+
+    let mut roff = String::new();
+    writeln!(
+        roff,
+        ".TH \"{}\" 1 \"\" \"{} {}\"",
+        name.to_uppercase(),
+        tool,
+        version
+    )
+    .unwrap();
+    writeln!(roff, ".SH NAME\n{} \\- {}", name, escape(&about.join(" "))).unwrap();
+    let mut preformatted = false;
+    for line in lines {
+        if is_heading(line) {
+            if preformatted {
+                roff.push_str(".fi\n");
+            }
+            writeln!(roff, ".SH {}\n.nf", line.trim_end_matches(':')).unwrap();
+            preformatted = true;
+        } else if !line.trim().is_empty() {
+            writeln!(roff, "{}", escape(line)).unwrap();
+        }
+    }
+    if preformatted {
+        roff.push_str(".fi\n");
+    }
+
+    roff
+}
+
+fn is_heading(line: &str) -> bool {
+    line.ends_with(':') && !line.starts_with(' ') && line == line.to_uppercase()
+}
+
+/// Escape backslashes, and control characters at the start of lines.
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\e");
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
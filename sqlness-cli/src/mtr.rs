@@ -0,0 +1,234 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Conversion from MySQL test framework (mtr) `.test`/`.result` pairs to
+//! sqlness cases.
+//!
+//! Only a common subset of mtr commands is understood:
+//! - `--sorted_result` becomes `SORT_RESULT` of the following statement.
+//! - `--echo`, `--error` and `#` comments become comments.
+//! - `delimiter` changes how statements are terminated.
+//!
+//! Other commands (`--source`, `connect`, `let` etc.) are kept as comments and
+//! reported, as they need manual conversion.
+
+/// Interceptor prefix used in generated cases.
+const INTERCEPTOR_PREFIX: &str = "-- SQLNESS";
+const DEFAULT_DELIMITER: &str = ";";
+
+/// mtr commands which may be written without the leading `--`.
+const COMMANDS: &[&str] = &[
+    "connect",
+    "connection",
+    "dec",
+    "die",
+    "disconnect",
+    "echo",
+    "end",
+    "error",
+    "eval",
+    "exec",
+    "exit",
+    "if",
+    "inc",
+    "let",
+    "query_vertical",
+    "reap",
+    "real_sleep",
+    "remove_file",
+    "replace_column",
+    "replace_regex",
+    "replace_result",
+    "send",
+    "sleep",
+    "sorted_result",
+    "source",
+    "system",
+    "while",
+];
+
+/// Result of a conversion.
+pub struct Converted {
+    /// Content of the sqlness case.
+    pub case: String,
+    pub statements: usize,
+    /// Commands that are not converted.
+    pub unsupported: Vec<String>,
+}
+
+/// Part of a converted case.
+enum Item {
+    /// Lines written as is, like comments.
+    Lines(String),
+    Statement {
+        sql: String,
+        /// Statement as echoed in `.result`, with its delimiter.
+        echoed: String,
+        directives: Vec<String>,
+    },
+}
+
+/// Convert content of a `.test` file, and optionally its `.result` whose
+/// outputs are kept as comments for reference.
+pub fn convert(test: &str, result: Option<&str>) -> Converted {
+    let (items, unsupported) = parse(test);
+    let expected = match result {
+        Some(result) => find_expected(&items, result),
+        None => vec![],
+    };
+
+    let mut case = String::new();
+    let mut statements = 0;
+    for item in items {
+        match item {
+            Item::Lines(lines) => case.push_str(&lines),
+            Item::Statement {
+                sql, directives, ..
+            } => {
+                if let Some(expected) = expected.get(statements).filter(|e| !e.is_empty()) {
+                    case.push_str("-- Expected:\n");
+                    for line in expected.lines() {
+                        case.push_str(&format!("--   {}\n", line));
+                    }
+                }
+                for directive in directives {
+                    case.push_str(&directive);
+                    case.push('\n');
+                }
+                case.push_str(&sql);
+                case.push_str(";\n");
+                statements += 1;
+            }
+        }
+    }
+
+    Converted {
+        case,
+        statements,
+        unsupported,
+    }
+}
+
+/// Parse a `.test` file into items, and unsupported commands.
+fn parse(test: &str) -> (Vec<Item>, Vec<String>) {
+    let mut items = vec![];
+    let mut unsupported = vec![];
+    let mut delimiter = DEFAULT_DELIMITER.to_string();
+    let mut pending_sql: Vec<&str> = vec![];
+    // directives for the next statement
+    let mut pending_directives = vec![];
+
+    for line in test.lines() {
+        let trimmed = line.trim();
+        if pending_sql.is_empty() {
+            if trimmed.is_empty() {
+                items.push(Item::Lines("\n".to_string()));
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                items.push(Item::Lines(format!("-- {}\n", comment.trim())));
+                continue;
+            }
+            if let Some(command) = parse_command(trimmed, &delimiter) {
+                match command.split_once(char::is_whitespace) {
+                    Some(("delimiter", new_delimiter)) => {
+                        delimiter = new_delimiter.trim().to_string();
+                    }
+                    Some(("echo", text)) => {
+                        items.push(Item::Lines(format!("-- {}\n", text.trim())));
+                    }
+                    Some(("error", error)) => {
+                        pending_directives.push(format!("-- Expect error {}", error.trim()));
+                    }
+                    None if command == "sorted_result" => {
+                        pending_directives.push(format!("{} SORT_RESULT", INTERCEPTOR_PREFIX));
+                    }
+                    _ if command.starts_with("disable_") || command.starts_with("enable_") => {}
+                    _ => {
+                        items.push(Item::Lines(format!("-- mtr: {}\n", command)));
+                        unsupported.push(command);
+                    }
+                }
+                continue;
+            }
+        }
+
+        pending_sql.push(line.trim_end());
+        if trimmed.ends_with(delimiter.as_str()) {
+            let echoed = pending_sql.join("\n");
+            pending_sql.clear();
+            let sql = echoed
+                .strip_suffix(delimiter.as_str())
+                .unwrap_or(&echoed)
+                .trim_end()
+                .to_string();
+            items.push(Item::Statement {
+                sql,
+                echoed,
+                directives: std::mem::take(&mut pending_directives),
+            });
+        }
+    }
+
+    (items, unsupported)
+}
+
+/// Return the command if `line` is a mtr command, without leading `--` or
+/// trailing delimiter.
+fn parse_command(line: &str, delimiter: &str) -> Option<String> {
+    if let Some(command) = line.strip_prefix("--") {
+        // SQL comments like `-- foo` are not commands
+        if command.starts_with(char::is_whitespace) || command.is_empty() {
+            return None;
+        }
+        return Some(command.trim().to_string());
+    }
+
+    let first_word = line
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ';')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    if first_word == "delimiter" || COMMANDS.contains(&first_word.as_str()) {
+        let command = line.strip_suffix(delimiter).unwrap_or(line);
+        return Some(command.trim().to_string());
+    }
+
+    None
+}
+
+/// Find output of each statement in `result`, which is the text between its
+/// echo and the echo of the next statement. Statements not found in `result`
+/// get an empty output.
+fn find_expected(items: &[Item], result: &str) -> Vec<String> {
+    // (start, end) of each echoed statement
+    let mut echoes = vec![];
+    let mut pos = 0;
+    for item in items {
+        if let Item::Statement { echoed, .. } = item {
+            match result[pos..].find(echoed.as_str()) {
+                Some(offset) => {
+                    let start = pos + offset;
+                    pos = start + echoed.len();
+                    echoes.push(Some((start, pos)));
+                }
+                None => echoes.push(None),
+            }
+        }
+    }
+
+    let mut expected = vec![];
+    for (i, echo) in echoes.iter().enumerate() {
+        let output = echo.map(|(_, end)| {
+            let next_start = echoes[i + 1..]
+                .iter()
+                .flatten()
+                .map(|(start, _)| *start)
+                .next()
+                .unwrap_or(result.len());
+            result[end..next_start].trim().to_string()
+        });
+        expected.push(output.unwrap_or_default());
+    }
+
+    expected
+}
@@ -0,0 +1,213 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Conversion between [sqllogictest] (`.slt`) files and sqlness cases.
+//!
+//! [sqllogictest]: https://www.sqlite.org/sqllogictest/doc/trunk/about.wiki
+
+use sqlness::ResultBlock;
+
+/// Interceptor prefix used in generated cases.
+const INTERCEPTOR_PREFIX: &str = "-- SQLNESS";
+/// Placeholder of empty lines in exported results, which would otherwise end
+/// the record.
+const EMPTY_LINE: &str = "(empty)";
+
+/// One record of a `.slt` file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Record {
+    Statement {
+        expect_error: bool,
+        sql: String,
+    },
+    Query {
+        sort_mode: SortMode,
+        sql: String,
+        /// Expected values, one per line.
+        expected: Vec<String>,
+    },
+    Comment(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SortMode {
+    Unsorted,
+    Rows,
+    Values,
+}
+
+/// Parse content of a `.slt` file.
+///
+/// Records guarded by `skipif <engine>` / `onlyif <engine>` are kept or
+/// dropped according to `engine`. Parsing stops at `halt`.
+pub fn parse(content: &str, engine: Option<&str>) -> Result<Vec<Record>, String> {
+    let mut records = vec![];
+    let mut lines = content.lines().enumerate().peekable();
+    // whether the next record is kept, decided by conditions before it
+    let mut keep = true;
+
+    while let Some((line_no, line)) = lines.next() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            records.push(Record::Comment(comment.trim().to_string()));
+            continue;
+        }
+
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        match tokens[0] {
+            "skipif" | "onlyif" => {
+                let target = *tokens
+                    .get(1)
+                    .ok_or_else(|| format!("line {}: missing engine name", line_no + 1))?;
+                let matches = engine == Some(target);
+                keep &= if tokens[0] == "skipif" {
+                    !matches
+                } else {
+                    matches
+                };
+            }
+            "halt" => break,
+            "hash-threshold" => {}
+            "statement" => {
+                let expect_error = match tokens.get(1) {
+                    Some(&"ok") | Some(&"count") => false,
+                    Some(&"error") => true,
+                    _ => return Err(format!("line {}: unknown statement kind", line_no + 1)),
+                };
+                let sql = read_sql(&mut lines);
+                if std::mem::replace(&mut keep, true) {
+                    records.push(Record::Statement { expect_error, sql });
+                }
+            }
+            "query" => {
+                let sort_mode = match tokens.get(2) {
+                    Some(&"rowsort") => SortMode::Rows,
+                    Some(&"valuesort") => SortMode::Values,
+                    _ => SortMode::Unsorted,
+                };
+                let sql = read_sql(&mut lines);
+                let mut expected = vec![];
+                if lines.peek().map(|(_, line)| line.trim() == "----") == Some(true) {
+                    lines.next();
+                    while let Some((_, line)) = lines.next_if(|(_, line)| !line.trim().is_empty()) {
+                        expected.push(line.to_string());
+                    }
+                }
+                if std::mem::replace(&mut keep, true) {
+                    records.push(Record::Query {
+                        sort_mode,
+                        sql,
+                        expected,
+                    });
+                }
+            }
+            other => return Err(format!("line {}: unknown record `{}`", line_no + 1, other)),
+        }
+    }
+
+    Ok(records)
+}
+
+/// Read SQL lines until an empty line or the result separator `----`.
+fn read_sql<'a, I>(lines: &mut std::iter::Peekable<I>) -> String
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let mut sql = vec![];
+    while let Some((_, line)) =
+        lines.next_if(|(_, line)| !line.trim().is_empty() && line.trim() != "----")
+    {
+        sql.push(line.trim_end());
+    }
+
+    sql.join("\n")
+}
+
+/// Render records as the content of a sqlness case file.
+///
+/// Expected values of queries are kept as comments for reference, since the
+/// textual result format depends on the [`Database`] implementation. The
+/// `.result` file should be recorded by running the case.
+///
+/// [`Database`]: sqlness::Database
+pub fn to_case(records: &[Record]) -> String {
+    let mut case = String::new();
+    for record in records {
+        match record {
+            Record::Comment(comment) => {
+                case.push_str(&format!("-- {}\n", comment));
+                continue;
+            }
+            Record::Statement { expect_error, sql } => {
+                if *expect_error {
+                    case.push_str("-- Expect error\n");
+                }
+                push_sql(&mut case, sql);
+            }
+            Record::Query {
+                sort_mode,
+                sql,
+                expected,
+            } => {
+                if !expected.is_empty() {
+                    case.push_str("-- Expected values:\n");
+                    for value in expected {
+                        case.push_str(&format!("--   {}\n", value));
+                    }
+                }
+                if *sort_mode != SortMode::Unsorted {
+                    case.push_str(&format!("{} SORT_RESULT\n", INTERCEPTOR_PREFIX));
+                }
+                push_sql(&mut case, sql);
+            }
+        }
+        case.push('\n');
+    }
+
+    case
+}
+
+fn push_sql(case: &mut String, sql: &str) {
+    case.push_str(sql.trim_end_matches(';'));
+    case.push_str(";\n");
+}
+
+/// Render query blocks of a case and its recorded result as a `.slt` file.
+///
+/// Queries with an empty result become `statement ok`. Others become a single
+/// text column `query T`, whose values are the result lines as rendered by the
+/// [`Database`] implementation. Queries sorted via `SORT_RESULT` are exported
+/// with `rowsort`.
+///
+/// [`Database`]: sqlness::Database
+pub fn from_blocks(blocks: &[ResultBlock], interceptor_prefix: &str) -> String {
+    let sort_directive = format!("{} SORT_RESULT", interceptor_prefix);
+    let mut slt = String::new();
+    for block in blocks {
+        let sql = block.query.trim().trim_end_matches(';');
+        if block.result.trim().is_empty() {
+            slt.push_str(&format!("statement ok\n{}\n\n", sql));
+            continue;
+        }
+
+        let sorted = block
+            .interceptor_lines
+            .iter()
+            .any(|line| line.starts_with(&sort_directive));
+        let sort_mode = if sorted { " rowsort" } else { "" };
+        slt.push_str(&format!("query T{}\n{}\n----\n", sort_mode, sql));
+        for line in block.result.lines() {
+            if line.trim().is_empty() {
+                slt.push_str(EMPTY_LINE);
+            } else {
+                slt.push_str(line);
+            }
+            slt.push('\n');
+        }
+        slt.push('\n');
+    }
+
+    slt
+}
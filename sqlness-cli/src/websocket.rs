@@ -0,0 +1,407 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! [`Database`] sending queries to SQL gateways streaming results over
+//! WebSocket, chosen by `ws://` DSN.
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    io,
+};
+
+use async_trait::async_trait;
+use sqlness::{Database, ExecutionContext};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::client::{json_string, QUERY_PLACEHOLDER};
+
+/// Appended to the key of the opening handshake to compute the accept key,
+/// see RFC 6455 section 4.2.2.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Max size of a frame, or a message of fragmented frames. Larger ones are
+/// rejected instead of being buffered.
+const MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+pub struct WsClient {
+    /// `host:port`
+    address: String,
+    host: String,
+    path: String,
+    /// JSON frame with [`QUERY_PLACEHOLDER`] replaced by the query.
+    template: String,
+}
+
+impl WsClient {
+    /// Build a client from DSN like `ws://host:port/path`. Each query opens a
+    /// new connection, sends one JSON text frame `{"query": <query>}`, and
+    /// records all text and binary frames received until the server closes
+    /// the connection, one frame per line. Parameters:
+    /// - `field`: name of the field holding the query, e.g. `field=sql`.
+    /// - `template`: JSON frame with `{query}` replaced by the query (as a JSON
+    ///   string), e.g. `template={"type":"query","sql":{query}}`. It takes
+    ///   precedence over `field`.
+    ///
+    /// TLS (`wss://`) is not supported.
+    pub fn try_new(rest: &str) -> Result<Self, String> {
+        let (rest, params) = rest.split_once('?').unwrap_or((rest, ""));
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            return Err("ws dsn requires a host, like `ws://host:port/path`".to_string());
+        }
+        let address = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+
+        let mut template = None;
+        let mut field = "query";
+        for (key, value) in params.split('&').filter_map(|param| param.split_once('=')) {
+            match key {
+                "template" => template = Some(value.to_string()),
+                "field" => field = value,
+                _ => return Err(format!("unknown ws dsn parameter `{}`", key)),
+            }
+        }
+        let template =
+            template.unwrap_or_else(|| format!("{{\"{}\":{}}}", field, QUERY_PLACEHOLDER));
+        if !template.contains(QUERY_PLACEHOLDER) {
+            return Err(format!("ws template must contain `{}`", QUERY_PLACEHOLDER));
+        }
+
+        Ok(Self {
+            address,
+            host: authority.to_string(),
+            path: path.to_string(),
+            template,
+        })
+    }
+
+    async fn execute(&self, query: &str) -> io::Result<String> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let mut stream = BufReader::new(stream);
+        self.handshake(&mut stream).await?;
+
+        let frame = self
+            .template
+            .replace(QUERY_PLACEHOLDER, &json_string(query));
+        write_frame(&mut stream, OPCODE_TEXT, frame.as_bytes()).await?;
+
+        let mut frames = vec![];
+        let mut message = vec![];
+        loop {
+            let (fin, opcode, payload) = match read_frame(&mut stream).await {
+                Ok(frame) => frame,
+                // servers may drop the connection without closing handshake
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            match opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    if (message.len() + payload.len()) as u64 > MAX_FRAME_SIZE {
+                        return Err(too_large());
+                    }
+                    message.extend(payload);
+                    if fin {
+                        frames.push(String::from_utf8_lossy(&message).into_owned());
+                        message.clear();
+                    }
+                }
+                OPCODE_CLOSE => {
+                    write_frame(&mut stream, OPCODE_CLOSE, &payload).await.ok();
+                    break;
+                }
+                OPCODE_PING => write_frame(&mut stream, OPCODE_PONG, &payload).await?,
+                _ => {}
+            }
+        }
+
+        Ok(frames.join("\n"))
+    }
+
+    async fn handshake(&self, stream: &mut BufReader<TcpStream>) -> io::Result<()> {
+        let key = base64(&random_bytes::<16>());
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            self.path, self.host, key
+        );
+        stream.get_mut().write_all(request.as_bytes()).await?;
+
+        let mut response = vec![];
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(stream.read_u8().await?);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let status = response.lines().next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("101") {
+            return Err(io::Error::other(format!(
+                "WebSocket handshake failed, response: {}",
+                status
+            )));
+        }
+        let accept = response.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("sec-websocket-accept")
+                .then(|| value.trim())
+        });
+        let expected = accept_key(&key);
+        if accept != Some(expected.as_str()) {
+            return Err(io::Error::other(format!(
+                "WebSocket handshake failed, expect Sec-WebSocket-Accept {}, got {}",
+                expected,
+                accept.unwrap_or("none")
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// `Sec-WebSocket-Accept` of a server accepting handshake `key`.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// Random bytes, from hashers of [`RandomState`] which are seeded by the OS.
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    for chunk in bytes.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish().to_be_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+
+    bytes
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("frame is larger than {} bytes", MAX_FRAME_SIZE),
+    )
+}
+
+/// SHA-1 digest of `data`, only used for the handshake.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(bit_len.to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+
+    digest
+}
+
+/// Standard base64 with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Write a single masked frame, as required for clients.
+async fn write_frame(
+    stream: &mut BufReader<TcpStream>,
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(0x80 | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    // masks must be unpredictable, so proxies can't be poisoned
+    let mask = random_bytes::<4>();
+    frame.extend(mask);
+    frame.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4]),
+    );
+
+    stream.get_mut().write_all(&frame).await
+}
+
+/// Read a frame, returning whether it's the final fragment, its opcode and
+/// payload. Frames larger than [`MAX_FRAME_SIZE`] are rejected.
+async fn read_frame<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<(bool, u8, Vec<u8>)> {
+    let first = stream.read_u8().await?;
+    let second = stream.read_u8().await?;
+    let len = match second & 0x7F {
+        126 => stream.read_u16().await? as u64,
+        127 => stream.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_FRAME_SIZE {
+        return Err(too_large());
+    }
+    let mask = if second & 0x80 != 0 {
+        Some(stream.read_u32().await?.to_be_bytes())
+    } else {
+        None
+    };
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload).await?;
+    if let Some(mask) = mask {
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, byte)| *byte ^= mask[i % 4]);
+    }
+
+    Ok((first & 0x80 != 0, first & 0x0F, payload))
+}
+
+#[async_trait]
+impl Database for WsClient {
+    async fn query(&self, _context: ExecutionContext, query: String) -> Box<dyn Display> {
+        match self.execute(&query).await {
+            Ok(result) => Box::new(result.trim_end().to_string()),
+            Err(e) => Box::new(format!(
+                "Failed to query {}{}, error: {}",
+                self.host, self.path, e
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn sha1_vectors() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn accept_key_of_rfc_example() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn random_masks() {
+        assert_ne!(random_bytes::<16>(), random_bytes::<16>());
+    }
+
+    #[tokio::test]
+    async fn read_frames() {
+        let frame = [0x81, 0x02, b'o', b'k'];
+        let (fin, opcode, payload) = read_frame(&mut &frame[..]).await.unwrap();
+        assert!(fin);
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(payload, b"ok");
+
+        // the length is checked before allocating the payload
+        let mut frame = vec![0x82, 127];
+        frame.extend((MAX_FRAME_SIZE + 1).to_be_bytes());
+        let e = read_frame(&mut &frame[..]).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Procedural macros for sqlness, re-exported by the `sqlness` crate.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, Expr, Ident, LitStr, Token,
+};
+
+/// Extension of case files.
+const CASE_EXTENSION: &str = "sql";
+/// Extension of output files, which are not embedded.
+const OUTPUT_EXTENSION: &str = "output";
+/// Suffixes of fixtures, which are not cases.
+const FIXTURE_SUFFIXES: [&str; 2] = [".setup", ".teardown"];
+
+struct TestCases {
+    case_dir: LitStr,
+    env: Expr,
+    config: Option<LitStr>,
+}
+
+impl Parse for TestCases {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let case_dir = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let env = input.parse()?;
+        let mut config = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key != "config" {
+                return Err(syn::Error::new(key.span(), "expect `config = \"...\"`"));
+            }
+            input.parse::<Token![=]>()?;
+            config = Some(input.parse()?);
+            input.parse::<Option<Token![,]>>()?;
+        }
+
+        Ok(Self {
+            case_dir,
+            env,
+            config,
+        })
+    }
+}
+
+/// Generate a `#[tokio::test]` for each case file (`*.sql`) under a case
+/// directory, so cases can be run, filtered and parallelized by `cargo test`
+/// or `cargo nextest` natively, with per-test reporting.
+///
+/// ```rust, ignore
+/// sqlness::test_cases!("tests/cases", MyEnv::new());
+/// // with a config file, whose `case_dir` is overridden
+/// sqlness::test_cases!("tests/cases", MyEnv::new(), config = "tests/sqlness.toml");
+/// ```
+///
+/// Paths are relative to the directory of `Cargo.toml`. Tests are named by
+/// paths of cases, e.g. `local/dml/basic.sql` becomes `local_dml_basic`. Each
+/// test evaluates the env expression, and runs its case with
+/// [`Runner::run_case`], which starts and stops the environment.
+///
+/// The crate using this macro needs `tokio` with `macros` and `rt-multi-thread`
+/// features. Changes of existing case files trigger recompilation, while new
+/// case files are only picked up after the crate is rebuilt, e.g. by touching
+/// the source file.
+///
+/// [`Runner::run_case`]: https://docs.rs/sqlness/latest/sqlness/struct.Runner.html#method.run_case
+#[proc_macro]
+pub fn test_cases(input: TokenStream) -> TokenStream {
+    let TestCases {
+        case_dir,
+        env,
+        config,
+    } = parse_macro_input!(input as TestCases);
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let case_dir_path = manifest_dir.join(case_dir.value());
+    let cases = match collect_cases(&case_dir_path) {
+        Ok(cases) => cases,
+        Err(e) => {
+            return syn::Error::new(
+                case_dir.span(),
+                format!("failed to read {}: {}", case_dir_path.display(), e),
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+    let case_dir_str = case_dir_path.to_string_lossy().into_owned();
+    let new_config = match config {
+        Some(config) => {
+            let config_path = manifest_dir.join(config.value());
+            let config_path = config_path.to_string_lossy().into_owned();
+            quote! {
+                let mut config = ::sqlness::Config::from_file(#config_path)
+                    .await
+                    .expect("failed to read sqlness config");
+                config.case_dir = #case_dir_str.to_string();
+            }
+        }
+        None => quote! {
+            let config = ::sqlness::ConfigBuilder::default()
+                .case_dir(#case_dir_str.to_string())
+                .build()
+                .expect("failed to build sqlness config");
+        },
+    };
+
+    let mut names = HashSet::new();
+    let tests = cases.iter().map(|path| {
+        let relative = path.strip_prefix(&case_dir_path).unwrap_or(path);
+        let name = unique_name(test_name(relative), &mut names);
+        let name = format_ident!("{}", name, span = Span::call_site());
+        let path = path.to_string_lossy().into_owned();
+        quote! {
+            #[::tokio::test]
+            async fn #name() {
+                // recompile when the case changes
+                const _: &str = include_str!(#path);
+                #new_config
+                let runner = ::sqlness::Runner::new_with_config(config, #env)
+                    .await
+                    .expect("failed to create sqlness runner");
+                if let Err(e) = runner.run_case(#path).await {
+                    panic!("case {} failed: {}", #path, e);
+                }
+            }
+        }
+    });
+
+    quote! { #(#tests)* }.into()
+}
+
+/// Embed all files under a case directory into the binary, so it doesn't
+/// depend on the source checkout at runtime. Evaluates to a
+/// `sqlness::EmbeddedCases`, which extracts them to a directory to run.
+///
+/// ```rust, ignore
+/// static CASES: sqlness::EmbeddedCases = sqlness::embed_cases!("tests/cases");
+/// ```
+///
+/// The path is relative to the directory of `Cargo.toml`. Output files
+/// (`*.output`) are not embedded. Like [`test_cases!`], new files are only
+/// picked up after the crate is rebuilt.
+#[proc_macro]
+pub fn embed_cases(input: TokenStream) -> TokenStream {
+    let case_dir = parse_macro_input!(input as LitStr);
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let case_dir_path = manifest_dir.join(case_dir.value());
+    let files = match collect_files(&case_dir_path) {
+        Ok(files) => files,
+        Err(e) => {
+            return syn::Error::new(
+                case_dir.span(),
+                format!("failed to read {}: {}", case_dir_path.display(), e),
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    let files = files.iter().map(|path| {
+        let relative = path
+            .strip_prefix(&case_dir_path)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let path = path.to_string_lossy().into_owned();
+        quote! { (#relative, include_bytes!(#path) as &[u8]) }
+    });
+
+    quote! { ::sqlness::EmbeddedCases::new(&[#(#files),*]) }.into()
+}
+
+/// Paths of files to embed under `dir`, sorted.
+fn collect_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .map(|ext| ext != OUTPUT_EXTENSION)
+                .unwrap_or(true)
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Paths of case files under `dir`, sorted.
+fn collect_cases(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut cases = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_case(&path) {
+                cases.push(path);
+            }
+        }
+    }
+    cases.sort();
+
+    Ok(cases)
+}
+
+fn is_case(path: &Path) -> bool {
+    let is_case_file = path
+        .extension()
+        .map(|ext| ext == CASE_EXTENSION)
+        .unwrap_or(false);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+
+    is_case_file && !FIXTURE_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+}
+
+/// Name of the test of case at `relative` path, like `local_dml_basic`.
+fn test_name(relative: &Path) -> String {
+    let relative = relative.with_extension("");
+    let mut name: String = relative
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert_str(0, "case_");
+    }
+
+    name
+}
+
+/// Append a number to `name` if it's taken, e.g. by `a-b.sql` and `a_b.sql`.
+fn unique_name(name: String, names: &mut HashSet<String>) -> String {
+    let mut unique = name.clone();
+    let mut i = 2;
+    while !names.insert(unique.clone()) {
+        unique = format!("{}_{}", name, i);
+        i += 1;
+    }
+
+    unique
+}
@@ -0,0 +1,38 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! GitHub Actions annotations, which show failures inline in the diff view of
+//! pull requests. See [`Config::github_annotations`].
+//!
+//! [`Config::github_annotations`]: crate::Config::github_annotations
+
+use std::path::Path;
+
+use crate::config::Config;
+
+/// Whether annotations should be printed, i.e. enabled by config and running
+/// under GitHub Actions.
+pub(crate) fn enabled(config: &Config) -> bool {
+    config.github_annotations && std::env::var("GITHUB_ACTIONS").as_deref() == Ok("true")
+}
+
+/// Format an error annotation like `::error file=case.sql,line=3::message`.
+pub(crate) fn error(file: &Path, line: Option<usize>, message: &str) -> String {
+    let mut properties = format!("file={}", escape_property(&file.display().to_string()));
+    if let Some(line) = line {
+        properties.push_str(&format!(",line={}", line));
+    }
+
+    format!("::error {}::{}", properties, escape_data(message))
+}
+
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
@@ -0,0 +1,134 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Latencies of queries measured in benchmark mode, see
+//! [`Config::benchmark`].
+//!
+//! [`Config::benchmark`]: crate::Config::benchmark
+
+use std::{fmt::Write, sync::Mutex, time::Duration};
+
+/// Latencies of a query in measured iterations.
+pub(crate) struct QueryLatency {
+    /// Index (0-based) of the query in its case.
+    pub index: usize,
+    pub name: Option<String>,
+    pub query: String,
+    /// Sorted.
+    pub samples: Vec<Duration>,
+}
+
+impl QueryLatency {
+    pub(crate) fn new(
+        index: usize,
+        name: Option<String>,
+        query: String,
+        mut samples: Vec<Duration>,
+    ) -> Self {
+        samples.sort();
+        Self {
+            index,
+            name,
+            query,
+            samples,
+        }
+    }
+
+    /// Sample at percentile `p` (0 to 100), by the nearest-rank method.
+    fn percentile(&self, p: usize) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p * self.samples.len()).div_ceil(100);
+
+        self.samples[rank.clamp(1, self.samples.len()) - 1]
+    }
+
+    /// Min, p50, p95 and max.
+    fn stats(&self) -> [Duration; 4] {
+        [
+            self.percentile(0),
+            self.percentile(50),
+            self.percentile(95),
+            self.percentile(100),
+        ]
+    }
+
+    /// Title like `#2 select-all`.
+    fn title(&self) -> String {
+        match &self.name {
+            Some(name) => format!("#{} {}", self.index + 1, name),
+            None => format!("#{}", self.index + 1),
+        }
+    }
+}
+
+/// Render latencies of queries of a case as a table for the console.
+pub(crate) fn render(latencies: &[QueryLatency]) -> String {
+    let mut table = String::new();
+    writeln!(
+        table,
+        "    {:<24}{:>12}{:>12}{:>12}{:>12}",
+        "query", "min", "p50", "p95", "max"
+    )
+    .unwrap();
+    for latency in latencies {
+        write!(table, "    {:<24}", latency.title()).unwrap();
+        for duration in latency.stats() {
+            write!(table, "{:>12}", format!("{:.3}ms", millis(duration))).unwrap();
+        }
+        table.push('\n');
+    }
+
+    table
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Collector of latencies of a run.
+#[derive(Default)]
+pub(crate) struct Benchmarks {
+    cases: Mutex<Vec<(String, Vec<QueryLatency>)>>,
+}
+
+impl Benchmarks {
+    pub(crate) fn record(&self, case: String, latencies: Vec<QueryLatency>) {
+        self.cases.lock().unwrap().push((case, latencies));
+    }
+
+    /// Render latencies of all queries as CSV, in microseconds.
+    pub(crate) fn to_csv(&self) -> String {
+        let mut csv = String::from("case,query,name,text,min_us,p50_us,p95_us,max_us\n");
+        for (case, latencies) in self.cases.lock().unwrap().iter() {
+            for latency in latencies {
+                let [min, p50, p95, max] = latency.stats().map(|duration| duration.as_micros());
+                let query = latency.query.split_whitespace().collect::<Vec<_>>();
+                writeln!(
+                    csv,
+                    "{},{},{},{},{},{},{},{}",
+                    quote(case),
+                    latency.index + 1,
+                    quote(latency.name.as_deref().unwrap_or_default()),
+                    quote(&query.join(" ")),
+                    min,
+                    p50,
+                    p95,
+                    max
+                )
+                .unwrap();
+            }
+        }
+
+        csv
+    }
+}
+
+/// Quote `field` of CSV if needed.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
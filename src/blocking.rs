@@ -0,0 +1,86 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{fmt::Display, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::database::{Database, ExecutionContext, TypedResult};
+
+/// Query executor backed by a blocking client library, like `rusqlite`. Wrap
+/// it with [`BlockingAdapter`] to use it as a [`Database`].
+pub trait BlockingDatabase: Send + Sync + 'static {
+    /// See [`Database::query`].
+    fn query(&self, context: ExecutionContext, query: String) -> Box<dyn Display + Send>;
+
+    /// See [`Database::query_typed`]. The default implementation returns
+    /// `None`.
+    fn query_typed(
+        &self,
+        _context: ExecutionContext,
+        _query: String,
+    ) -> Option<std::result::Result<TypedResult, String>> {
+        None
+    }
+
+    /// See [`Database::reconnect`]. The default implementation does nothing.
+    fn reconnect(&self) {}
+}
+
+/// Adapter running a [`BlockingDatabase`] on the blocking thread pool of
+/// tokio (via [`tokio::task::spawn_blocking`]), so it doesn't block the
+/// runtime.
+///
+/// ```rust, ignore, no_run
+/// impl EnvController for MyController {
+///     type DB = BlockingAdapter<MySqliteDB>;
+///
+///     async fn start(&self, env: &str, config: Option<&Path>) -> Self::DB {
+///         BlockingAdapter::new(MySqliteDB::open(env))
+///     }
+///     // ...
+/// }
+/// ```
+pub struct BlockingAdapter<D> {
+    db: Arc<D>,
+}
+
+impl<D: BlockingDatabase> BlockingAdapter<D> {
+    pub fn new(db: D) -> Self {
+        Self { db: Arc::new(db) }
+    }
+
+    /// The wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.db
+    }
+}
+
+#[async_trait]
+impl<D: BlockingDatabase> Database for BlockingAdapter<D> {
+    async fn query(&self, context: ExecutionContext, query: String) -> Box<dyn Display> {
+        let db = self.db.clone();
+        match tokio::task::spawn_blocking(move || db.query(context, query)).await {
+            Ok(result) => result,
+            Err(e) => Box::new(format!("Failed to execute query, error: {}", e)),
+        }
+    }
+
+    async fn query_typed(
+        &self,
+        context: ExecutionContext,
+        query: String,
+    ) -> Option<std::result::Result<TypedResult, String>> {
+        let db = self.db.clone();
+        match tokio::task::spawn_blocking(move || db.query_typed(context, query)).await {
+            Ok(result) => result,
+            Err(e) => Some(Err(format!("Failed to execute query, error: {}", e))),
+        }
+    }
+
+    async fn reconnect(&self) {
+        let db = self.db.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || db.reconnect()).await {
+            println!("Failed to reconnect, error: {}", e);
+        }
+    }
+}
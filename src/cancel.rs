@@ -0,0 +1,51 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+/// Token to abort a run of [`Runner`], get it by
+/// [`Runner::cancellation_token`].
+///
+/// After cancelled, the running case is dropped, environments are stopped and
+/// the report of finished cases is printed. The run returns
+/// [`SqlnessError::Cancelled`].
+///
+/// [`Runner`]: crate::Runner
+/// [`Runner::cancellation_token`]: crate::Runner::cancellation_token
+/// [`SqlnessError::Cancelled`]: crate::SqlnessError::Cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until cancelled.
+    pub async fn cancelled(&self) {
+        // `Notified` receives notifications once created, so there is no race
+        // between checking the flag and awaiting
+        let notified = self.inner.notify.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
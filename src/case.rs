@@ -1,75 +1,844 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
-use std::{fmt::Display, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    future::{poll_fn, Future},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
 
 use tokio::{
-    fs::File,
-    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt},
+    time,
 };
 
-use crate::{config::Config, error::Result, Database, SqlnessError};
+use crate::{
+    benchmark::QueryLatency,
+    config::Config,
+    discovery::EnvDirs,
+    error::Result,
+    hash::md5_hex,
+    interceptor::{self, schema_snapshot, InterceptorRef, QueryContext},
+    normalize::elapsed_line,
+    query_id,
+    shrink::Block,
+    version::Version,
+    Database, ExecutionContext, SqlnessError,
+};
 
-const COMMENT_PREFIX: &str = "--";
+/// Mark of directive continuation lines, like `-- SQLNESS+`.
+const CONTINUATION_MARK: &str = "+";
+const NAME_PREFIX: &str = "NAME";
+const MIN_VERSION_PREFIX: &str = "MIN_VERSION";
+const REQUIRE_PREFIX: &str = "REQUIRE";
+const PRIORITY_PREFIX: &str = "PRIORITY";
+const DEPENDS_ON_PREFIX: &str = "DEPENDS_ON";
+const SERIAL: &str = "SERIAL";
+/// Delimiter of front-matter, see [`CaseMetadata`].
+const FRONT_MATTER_DELIMITER: &str = "---";
 
-pub(crate) struct TestCase {
+/// Information about the server of an environment, used to decide whether a
+/// case applies to it.
+#[derive(Debug, Default)]
+pub(crate) struct ServerInfo {
+    pub version: Option<Version>,
+    pub features: HashSet<String>,
+    /// Statements executed on every new connection, declared by the
+    /// `init_statements` array in env config file.
+    pub init_statements: Vec<String>,
+    /// Log files of the server, declared by the `server_logs` array in env
+    /// config file. Their tails are attached to failed cases in reports.
+    pub server_logs: Vec<PathBuf>,
+}
+
+/// Metadata declared in the front-matter of a case file, a block of comments
+/// at its top like:
+///
+/// ```sql
+/// -- ---
+/// -- owner: storage-team
+/// -- description: Updates on partitioned tables
+/// -- issues: https://github.com/CeresDB/ceresdb/issues/1
+/// -- tags: dml, partition
+/// -- ---
+/// ```
+///
+/// `issues` and `tags` are comma separated. Other keys are kept in `extra`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaseMetadata {
+    pub owner: Option<String>,
+    pub description: Option<String>,
+    pub issues: Vec<String>,
+    pub tags: Vec<String>,
+    pub extra: BTreeMap<String, String>,
+}
+
+impl CaseMetadata {
+    fn set(&mut self, key: &str, value: &str) {
+        let list = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        match key {
+            "owner" => self.owner = Some(value.to_string()),
+            "description" => self.description = Some(value.to_string()),
+            "issues" => self.issues = list(value),
+            "tags" => self.tags = list(value),
+            _ => {
+                self.extra.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+}
+
+/// Parsing state of front-matter.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FrontMatter {
+    /// Before the first non-empty line.
+    Pending,
+    Inside,
+    Done,
+}
+
+/// A parsed case file.
+pub struct TestCase {
     name: String,
+    metadata: CaseMetadata,
     queries: Vec<Query>,
+    /// Minimal server version required, declared via `MIN_VERSION`.
+    min_version: Option<Version>,
+    /// Features required, declared via `REQUIRE feature=<name>`.
+    required_features: Vec<String>,
+    /// Declared via `PRIORITY <n>`, cases with higher priority run first.
+    priority: Option<i32>,
+    /// Cases (without extension) declared via `DEPENDS_ON <case>`, relative to
+    /// the directory of this case.
+    dependencies: Vec<PathBuf>,
+    /// Declared via `SERIAL`.
+    serial: bool,
+    /// Comment prefix of elapsed time lines, if
+    /// [`Config::record_elapsed`] is set.
+    elapsed_comment: Option<String>,
 }
 
 impl TestCase {
-    pub(crate) async fn from_file<P: AsRef<Path>>(path: P, cfg: &Config) -> Result<Self> {
-        let file = File::open(path.as_ref())
+    pub async fn from_file<P: AsRef<Path>>(path: P, cfg: &Config) -> Result<Self> {
+        let env_dirs = EnvDirs::resolve(cfg).await?;
+        Self::from_file_with(path, cfg, &env_dirs).await
+    }
+
+    /// Like [`TestCase::from_file`], with environment directories resolved
+    /// once for all cases of a run.
+    pub(crate) async fn from_file_with<P: AsRef<Path>>(
+        path: P,
+        cfg: &Config,
+        env_dirs: &EnvDirs,
+    ) -> Result<Self> {
+        let content = tokio::fs::read(path.as_ref())
             .await
             .map_err(|e| SqlnessError::ReadPath {
                 source: e,
                 path: path.as_ref().to_path_buf(),
             })?;
+        let content = cfg.file_encoding.decode(content)?;
+
+        Self::parse(
+            path.as_ref().to_str().unwrap().to_string(),
+            content.as_bytes(),
+            cfg,
+            env_dirs,
+        )
+        .await
+    }
 
-        let mut queries = vec![];
+    /// Parse a case from `reader`, which contains the content of a case file.
+    pub async fn from_reader<R>(name: String, reader: R, cfg: &Config) -> Result<Self>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let env_dirs = EnvDirs::resolve(cfg).await?;
+        Self::parse(name, reader, cfg, &env_dirs).await
+    }
+
+    pub(crate) async fn parse<R>(
+        name: String,
+        reader: R,
+        cfg: &Config,
+        env_dirs: &EnvDirs,
+    ) -> Result<Self>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut case = Self {
+            name,
+            metadata: CaseMetadata::default(),
+            queries: vec![],
+            min_version: None,
+            required_features: vec![],
+            priority: None,
+            dependencies: vec![],
+            serial: false,
+            elapsed_comment: cfg.record_elapsed.then(|| cfg.comment_prefix.clone()),
+        };
         let mut query = Query::default();
+        let splitter = cfg.splitter();
+        let base_dir = Path::new(&case.name)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let mut config_interceptors =
+            interceptor::from_directory_rules(cfg, env_dirs, Path::new(&case.name), &base_dir)?;
+        config_interceptors.extend(interceptor::from_config(cfg)?);
 
-        let mut lines = BufReader::new(file).lines();
+        let path = PathBuf::from(&case.name);
+        let parse_error = |line: usize, e: SqlnessError| SqlnessError::ParseCase {
+            path: path.clone(),
+            line,
+            source: Box::new(e),
+        };
+        // directive whose continuation lines may follow, and its line number
+        let mut pending_directive: Option<(String, usize)> = None;
+
+        let mut lines = reader.lines();
+        let mut line_number = 0;
+        let mut front_matter = FrontMatter::Pending;
         while let Some(line) = lines.next_line().await? {
+            line_number += 1;
+            if front_matter != FrontMatter::Done && !line.trim().is_empty() {
+                let comment = line
+                    .strip_prefix(&cfg.comment_prefix)
+                    .map(str::trim)
+                    .filter(|_| !line.starts_with(&cfg.interceptor_prefix));
+                front_matter = match (front_matter, comment) {
+                    (FrontMatter::Pending, Some(FRONT_MATTER_DELIMITER)) => FrontMatter::Inside,
+                    (FrontMatter::Inside, Some(FRONT_MATTER_DELIMITER)) => FrontMatter::Done,
+                    (FrontMatter::Inside, Some(comment)) => {
+                        if let Some((key, value)) = comment.split_once(':') {
+                            case.metadata.set(key.trim(), value.trim());
+                        }
+                        FrontMatter::Inside
+                    }
+                    _ => FrontMatter::Done,
+                };
+            }
             // intercept command start with INTERCEPTOR_PREFIX
-            if line.starts_with(&cfg.interceptor_prefix) {
-                query.push_interceptor(line);
+            if let Some(directive) = line.strip_prefix(&cfg.interceptor_prefix) {
+                if let Some(continuation) = directive.strip_prefix(CONTINUATION_MARK) {
+                    let (pending, _) = pending_directive.as_mut().ok_or_else(|| {
+                        parse_error(
+                            line_number,
+                            SqlnessError::InvalidInterceptor {
+                                directive: line.clone(),
+                                reason: "continuation line without a directive".to_string(),
+                            },
+                        )
+                    })?;
+                    pending.push(' ');
+                    pending.push_str(continuation.trim());
+                } else if let Some((pending, pending_line)) =
+                    pending_directive.replace((directive.to_string(), line_number))
+                {
+                    case.apply_directive(&mut query, &pending, &base_dir)
+                        .map_err(|e| parse_error(pending_line, e))?;
+                }
+                query.push_interceptor_line(line);
                 continue;
             }
+            if let Some((pending, pending_line)) = pending_directive.take() {
+                case.apply_directive(&mut query, &pending, &base_dir)
+                    .map_err(|e| parse_error(pending_line, e))?;
+            }
 
             // ignore comment and empty line
-            if line.starts_with(COMMENT_PREFIX) || line.is_empty() {
+            if line.starts_with(&cfg.comment_prefix) || line.is_empty() {
                 continue;
             }
 
+            if query.query_lines.is_empty() {
+                query.line = line_number;
+            }
             query.append_query_line(&line);
 
-            // SQL statement ends with ';'
-            if line.ends_with(';') {
-                queries.push(query);
+            if splitter.is_query_end(&line) {
+                for interceptor in &config_interceptors {
+                    query.push_interceptor(interceptor.clone());
+                }
+                case.queries.push(query);
                 query = Query::default();
             } else {
                 query.append_query_line("\n");
             }
         }
+        if let Some((pending, pending_line)) = pending_directive.take() {
+            case.apply_directive(&mut query, &pending, &base_dir)
+                .map_err(|e| parse_error(pending_line, e))?;
+        }
+
+        Ok(case)
+    }
+
+    /// Apply a directive (with prefix stripped) to the whole case, or to
+    /// `query` as an interceptor.
+    fn apply_directive(
+        &mut self,
+        query: &mut Query,
+        directive: &str,
+        base_dir: &Path,
+    ) -> Result<()> {
+        if self.parse_case_directive(directive, base_dir)? {
+            return Ok(());
+        }
 
-        Ok(Self {
-            name: path.as_ref().to_str().unwrap().to_string(),
-            queries,
+        let (name, args) = interceptor::split_directive(directive);
+        if name == NAME_PREFIX {
+            if args.is_empty() {
+                return Err(SqlnessError::InvalidInterceptor {
+                    directive: directive.trim().to_string(),
+                    reason: "missing query name".to_string(),
+                });
+            }
+            query.name = Some(args.to_string());
+        } else if let Some(interceptor) = interceptor::parse(directive, base_dir)? {
+            query.push_interceptor(interceptor);
+        }
+
+        Ok(())
+    }
+
+    /// Names of all directives (e.g. `SORT_RESULT`), of interceptors and
+    /// those applied to the whole case.
+    pub fn directive_names() -> Vec<&'static str> {
+        let mut names = vec![
+            NAME_PREFIX,
+            MIN_VERSION_PREFIX,
+            REQUIRE_PREFIX,
+            PRIORITY_PREFIX,
+            DEPENDS_ON_PREFIX,
+            SERIAL,
+        ];
+        names.extend(interceptor::NAMES);
+
+        names
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn query_count(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Whether any query changes the state of its connection for subsequent
+    /// queries, e.g. via `SESSION` or `USE`.
+    pub(crate) fn changes_session(&self) -> bool {
+        self.queries.iter().any(|query| {
+            query
+                .interceptors
+                .iter()
+                .any(|interceptor| interceptor.changes_session())
         })
     }
 
-    pub(crate) async fn execute<W>(&self, db: &dyn Database, writer: &mut W) -> Result<()>
+    /// Text of each query, as written in the case file.
+    pub fn query_texts(&self) -> Vec<String> {
+        self.queries.iter().map(Query::text).collect()
+    }
+
+    /// Priority declared via `PRIORITY <n>`.
+    pub fn priority(&self) -> Option<i32> {
+        self.priority
+    }
+
+    /// Cases (without extension) this case depends on, declared via
+    /// `DEPENDS_ON <case>`.
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    /// Whether the case is declared `SERIAL`, see [`Config::parallelism`].
+    pub fn is_serial(&self) -> bool {
+        self.serial
+    }
+
+    pub fn metadata(&self) -> &CaseMetadata {
+        &self.metadata
+    }
+
+    /// Case level requirements in their directive form, like
+    /// `MIN_VERSION 0.9.0` or `REQUIRE feature=json`.
+    pub fn requirements(&self) -> Vec<String> {
+        let mut requirements = vec![];
+        if let Some(min_version) = &self.min_version {
+            requirements.push(format!("{} {}", MIN_VERSION_PREFIX, min_version));
+        }
+        for feature in &self.required_features {
+            requirements.push(format!("{} feature={}", REQUIRE_PREFIX, feature));
+        }
+
+        requirements
+    }
+
+    /// Split the content of an output or result file of this case into blocks
+    /// of each query.
+    ///
+    /// Splitting stops at the first query whose text is not found in
+    /// `content`.
+    pub fn split_result(&self, content: &str) -> Vec<ResultBlock> {
+        let mut blocks = vec![];
+        let mut pos = 0;
+        for (index, query) in self.queries.iter().enumerate() {
+            let header = query.header();
+            let start = match content[pos..].find(&header) {
+                Some(offset) => pos + offset,
+                None => break,
+            };
+            let result_start = start + header.len();
+            let line = content[..start].matches('\n').count() + 1;
+            let result_end = self
+                .queries
+                .get(index + 1)
+                .and_then(|next| content[result_start..].find(&next.header()))
+                .map(|offset| result_start + offset)
+                .unwrap_or(content.len());
+            let result = &content[result_start..result_end];
+
+            blocks.push(ResultBlock {
+                index,
+                line,
+                end_line: content[..result_end]
+                    .trim_end_matches('\n')
+                    .matches('\n')
+                    .count()
+                    + 1,
+                result_line: line + header.matches('\n').count(),
+                name: query.name.clone(),
+                case_line: query.line,
+                interceptor_lines: query.interceptor_lines.clone(),
+                query: query.text(),
+                result: result.strip_suffix("\n\n").unwrap_or(result).to_string(),
+            });
+            pos = result_end;
+        }
+
+        blocks
+    }
+
+    /// Parse directives applied to the whole case. Return false if `directive`
+    /// is not one of them.
+    fn parse_case_directive(&mut self, directive: &str, base_dir: &Path) -> Result<bool> {
+        let directive = directive.trim();
+        let (name, args) = interceptor::split_directive(directive);
+        let invalid = |reason: String| SqlnessError::InvalidInterceptor {
+            directive: directive.to_string(),
+            reason,
+        };
+
+        if name == MIN_VERSION_PREFIX {
+            self.min_version = Some(args.parse().map_err(invalid)?);
+        } else if name == REQUIRE_PREFIX {
+            for requirement in args.split_whitespace() {
+                match requirement.split_once('=') {
+                    Some(("feature", feature)) if !feature.is_empty() => {
+                        self.required_features.push(feature.to_string())
+                    }
+                    _ => {
+                        return Err(invalid(format!(
+                            "expect `feature=<name>`, got `{}`",
+                            requirement
+                        )))
+                    }
+                }
+            }
+        } else if name == PRIORITY_PREFIX {
+            self.priority = Some(
+                args.parse()
+                    .map_err(|_| invalid(format!("expect an integer, got `{}`", args)))?,
+            );
+        } else if directive == SERIAL {
+            self.serial = true;
+        } else if name == DEPENDS_ON_PREFIX {
+            if args.is_empty() {
+                return Err(invalid("missing case path".to_string()));
+            }
+            self.dependencies.extend(
+                args.split_whitespace()
+                    .map(|dependency| base_dir.join(dependency)),
+            );
+        } else {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Return the reason if this case should be skipped against `server`.
+    pub(crate) fn skip_reason(&self, server: &ServerInfo) -> Option<String> {
+        if let (Some(min_version), Some(server_version)) = (&self.min_version, &server.version) {
+            if server_version < min_version {
+                return Some(format!(
+                    "requires version {}, server is {}",
+                    min_version, server_version
+                ));
+            }
+        }
+
+        let missing_features: Vec<_> = self
+            .required_features
+            .iter()
+            .filter(|feature| !server.features.contains(*feature))
+            .map(String::as_str)
+            .collect();
+        if !missing_features.is_empty() {
+            return Some(format!(
+                "requires unsupported features {}",
+                missing_features.join(", ")
+            ));
+        }
+
+        None
+    }
+
+    /// Execute all queries against `db`, and write their results to `writer`
+    /// in the format of result files.
+    ///
+    /// Return the IDs of executed queries, in the order of queries.
+    pub async fn execute<W>(&self, db: &dyn Database, writer: &mut W) -> Result<Vec<String>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let timings = self.execute_timed(db, writer).await?;
+
+        Ok(timings.into_iter().map(|timing| timing.query_id).collect())
+    }
+
+    /// Same as [`Self::execute`], but return timings of executed queries, in
+    /// the order of queries.
+    pub(crate) async fn execute_timed<W>(
+        &self,
+        db: &dyn Database,
+        writer: &mut W,
+    ) -> Result<Vec<QueryTiming>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut timings = Vec::with_capacity(self.queries.len());
+        // queries which are asserted to block, in the order they are issued
+        let mut blocked = vec![];
+        // declared via `CONNECTION`, dropped when the case ends
+        let mut connections = HashMap::new();
+        for (index, query) in self.queries.iter().enumerate() {
+            let (query_str, context) = query.before_execute();
+            let execution = self.execution_context(index, &context);
+            let started = Instant::now();
+            timings.push(QueryTiming {
+                query_id: context.query_id.clone(),
+                query: query_str.clone(),
+                line: query.line,
+                start: SystemTime::now(),
+                elapsed: Duration::ZERO,
+            });
+            let block_timeout = context.block_timeout;
+            let unblocks = context.unblocks;
+            let connection = match &context.connection {
+                Some(name) => Some(
+                    Self::connection(db, name, &mut connections)
+                        .await
+                        .map_err(|e| query.failed(e))?,
+                ),
+                None => None,
+            };
+            let mut fut: Pin<Box<dyn Future<Output = Result<String>> + Send + '_>> =
+                match connection {
+                    // the blocked query may outlive this iteration
+                    Some(connection) => Box::pin(async move {
+                        query
+                            .submit(&*connection, query_str, context, execution)
+                            .await
+                    }),
+                    None => Box::pin(query.submit(db, query_str, context, execution)),
+                };
+
+            match block_timeout {
+                Some(timeout) => {
+                    match time::timeout(timeout, drive_with_blocked(&mut fut, &mut blocked)).await {
+                        Ok(result) => {
+                            let result = result?;
+                            let result = format!(
+                                "Query is expected to block for {:?}, but it returned:\n{}",
+                                timeout, result
+                            );
+                            let elapsed = started.elapsed();
+                            timings.last_mut().unwrap().elapsed = elapsed;
+                            query
+                                .write_result(writer, self.with_elapsed(result, elapsed))
+                                .await
+                                .map_err(|e| query.failed(e))?;
+                        }
+                        Err(_) => {
+                            let result = format!("Blocked for at least {:?}", timeout);
+                            query
+                                .write_result(writer, result)
+                                .await
+                                .map_err(|e| query.failed(e))?;
+                            blocked.push(BlockedQuery {
+                                query,
+                                fut,
+                                result: None,
+                                timing: timings.len() - 1,
+                                started,
+                                elapsed: Duration::ZERO,
+                            });
+                        }
+                    }
+                }
+                None => {
+                    let result = drive_with_blocked(&mut fut, &mut blocked).await?;
+                    let elapsed = started.elapsed();
+                    timings.last_mut().unwrap().elapsed = elapsed;
+                    query
+                        .write_result(writer, self.with_elapsed(result, elapsed))
+                        .await
+                        .map_err(|e| query.failed(e))?;
+                }
+            }
+
+            if unblocks {
+                self.release_blocked(&mut blocked, writer, &mut timings)
+                    .await?;
+            }
+        }
+        // queries not released explicitly are awaited at the end of case
+        self.release_blocked(&mut blocked, writer, &mut timings)
+            .await?;
+
+        Ok(timings)
+    }
+
+    /// Connection `name` of this case, opened on its first use.
+    async fn connection(
+        db: &dyn Database,
+        name: &str,
+        connections: &mut HashMap<String, Arc<dyn Database>>,
+    ) -> std::result::Result<Arc<dyn Database>, String> {
+        if let Some(connection) = connections.get(name) {
+            return Ok(connection.clone());
+        }
+        let connection: Arc<dyn Database> = db
+            .connection(name)
+            .await
+            .ok_or_else(|| format!("database doesn't support connection `{}`", name))?
+            .into();
+        connections.insert(name.to_string(), connection.clone());
+
+        Ok(connection)
+    }
+
+    /// Directive lines and text of queries, to shrink the case.
+    pub(crate) fn shrink_blocks(&self) -> Vec<Block> {
+        self.queries
+            .iter()
+            .map(|query| Block {
+                directives: query.interceptor_lines.clone(),
+                lines: query.text().lines().map(str::to_string).collect(),
+                target: false,
+            })
+            .collect()
+    }
+
+    /// Execute each query `warmups` times and then `iterations` times, return
+    /// latencies of the latter. Results are discarded.
+    pub(crate) async fn benchmark(
+        &self,
+        db: &dyn Database,
+        warmups: usize,
+        iterations: usize,
+    ) -> Result<Vec<QueryLatency>> {
+        let mut latencies = Vec::with_capacity(self.queries.len());
+        for (index, query) in self.queries.iter().enumerate() {
+            let mut samples = Vec::with_capacity(iterations);
+            for iteration in 0..warmups + iterations {
+                let (query_str, context) = query.before_execute();
+                let execution = self.execution_context(index, &context);
+                let started = Instant::now();
+                query.submit(db, query_str, context, execution).await?;
+                if iteration >= warmups {
+                    samples.push(started.elapsed());
+                }
+            }
+            latencies.push(QueryLatency::new(
+                index,
+                query.name.clone(),
+                query.text(),
+                samples,
+            ));
+        }
+
+        Ok(latencies)
+    }
+
+    /// [`ExecutionContext`] of the query at `index`, whose interceptors
+    /// filled `context`.
+    fn execution_context(&self, index: usize, context: &QueryContext) -> ExecutionContext {
+        ExecutionContext {
+            case_path: PathBuf::from(&self.name),
+            query_index: index,
+            query_id: context.query_id.clone(),
+            tags: self.metadata.tags.clone(),
+            options: context.options.clone(),
+        }
+    }
+
+    /// Append the elapsed time line to `result`, see [`Config::record_elapsed`].
+    fn with_elapsed(&self, result: String, elapsed: Duration) -> String {
+        match &self.elapsed_comment {
+            Some(comment_prefix) => {
+                format!("{}\n{}", result, elapsed_line(comment_prefix, elapsed))
+            }
+            None => result,
+        }
+    }
+
+    /// Wait for all blocked queries to finish, and write their results.
+    async fn release_blocked<W>(
+        &self,
+        blocked: &mut Vec<BlockedQuery<'_>>,
+        writer: &mut W,
+        timings: &mut [QueryTiming],
+    ) -> Result<()>
     where
         W: AsyncWrite + Unpin,
     {
-        for query in &self.queries {
-            query.execute(db, writer).await?;
+        poll_fn(|cx| {
+            let mut all_done = true;
+            for query in blocked.iter_mut() {
+                all_done &= query.poll(cx).is_ready();
+            }
+            if all_done {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        for query in blocked.drain(..) {
+            timings[query.timing].elapsed = query.elapsed;
+            let result = query.result.transpose()?.unwrap_or_default();
+            let result = self.with_elapsed(result, query.elapsed);
+            query
+                .query
+                .write_released_result(writer, result)
+                .await
+                .map_err(|e| query.query.failed(e))?;
         }
 
         Ok(())
     }
 }
 
+/// Part of an output or result file belonging to one query.
+#[derive(Debug, Clone)]
+pub struct ResultBlock {
+    /// Index of the query in its case.
+    pub index: usize,
+    /// Line number (1-based) where this block starts.
+    pub line: usize,
+    /// Line number (1-based) where this block ends, inclusive.
+    pub end_line: usize,
+    /// Line number (1-based) where the result starts.
+    pub result_line: usize,
+    /// Name of the query, declared via `NAME`.
+    pub name: Option<String>,
+    /// Line number (1-based) of the query in the case file.
+    pub case_line: usize,
+    /// Interceptor lines in front of the query.
+    pub interceptor_lines: Vec<String>,
+    /// Query text, as written in the case file.
+    pub query: String,
+    pub result: String,
+}
+
+/// When and how long a query of a case is executed.
+#[derive(Debug, Clone)]
+pub(crate) struct QueryTiming {
+    pub query_id: String,
+    /// Query sent to the database, after interceptors are applied.
+    pub query: String,
+    /// Line number (1-based) where the query starts in the case file.
+    pub line: usize,
+    pub start: SystemTime,
+    pub elapsed: Duration,
+}
+
+/// A query that is asserted to block by the `BLOCKS` interceptor. It keeps
+/// being polled while following queries are executed.
+struct BlockedQuery<'a> {
+    query: &'a Query,
+    fut: Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>,
+    result: Option<Result<String>>,
+    /// Index of its [`QueryTiming`].
+    timing: usize,
+    started: Instant,
+    elapsed: Duration,
+}
+
+impl BlockedQuery<'_> {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.result.is_none() {
+            match self.fut.as_mut().poll(cx) {
+                Poll::Ready(result) => {
+                    self.result = Some(result);
+                    self.elapsed = self.started.elapsed();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(())
+    }
+}
+
+/// Await `fut`, while polling `blocked` queries concurrently so they can make
+/// progress.
+async fn drive_with_blocked<F>(fut: &mut F, blocked: &mut [BlockedQuery<'_>]) -> F::Output
+where
+    F: Future + Unpin,
+{
+    poll_fn(|cx| {
+        for query in blocked.iter_mut() {
+            let _ = query.poll(cx);
+        }
+        Pin::new(&mut *fut).poll(cx)
+    })
+    .await
+}
+
+/// Await `fut`, return the payload of its panic as an error, if any.
+async fn catch_unwind<F: Future>(fut: F) -> std::result::Result<F::Output, String> {
+    let mut fut = Box::pin(fut);
+    poll_fn(
+        |cx| match std::panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic payload".to_string()))),
+        },
+    )
+    .await
+}
+
 impl Display for TestCase {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.name)
@@ -78,27 +847,196 @@ impl Display for TestCase {
 
 #[derive(Default)]
 struct Query {
+    /// Label used in reports, declared via `NAME`.
+    name: Option<String>,
+    /// Line number (1-based) where the query text starts in the case file.
+    line: usize,
     query_lines: Vec<String>,
-    interceptors: Vec<String>,
+    interceptor_lines: Vec<String>,
+    interceptors: Vec<InterceptorRef>,
 }
 
 impl Query {
-    fn push_interceptor(&mut self, post_process: String) {
-        self.interceptors.push(post_process);
+    fn push_interceptor(&mut self, interceptor: InterceptorRef) {
+        self.interceptors.push(interceptor);
+    }
+
+    fn push_interceptor_line(&mut self, line: String) {
+        self.interceptor_lines.push(line);
     }
 
     fn append_query_line(&mut self, line: &str) {
         self.query_lines.push(line.to_string());
     }
 
-    async fn execute<W>(&self, db: &dyn Database, writer: &mut W) -> Result<()>
-    where
-        W: AsyncWrite + Unpin,
-    {
-        let result = db.query(self.concat_query_lines()).await;
-        self.write_result(writer, result.to_string()).await?;
+    /// Query text, as written in the case file.
+    fn text(&self) -> String {
+        self.query_lines.concat()
+    }
 
-        Ok(())
+    /// Error of executing this query for `reason`.
+    fn failed(&self, reason: impl Display) -> SqlnessError {
+        SqlnessError::QueryFailed {
+            query: self.text(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Text written in front of the result, see [`Self::write_result`].
+    fn header(&self) -> String {
+        let mut header = String::new();
+        for line in &self.interceptor_lines {
+            header.push_str(line);
+            header.push('\n');
+        }
+        header.push_str(&self.text());
+        header.push_str("\n\n");
+
+        header
+    }
+
+    /// Apply interceptors to the query, return the query to execute and its
+    /// context.
+    fn before_execute(&self) -> (String, QueryContext) {
+        let mut query = self.concat_query_lines();
+        let mut context = QueryContext {
+            query_id: query_id::next(),
+            ..Default::default()
+        };
+        for interceptor in &self.interceptors {
+            interceptor.before_execute(&mut query, &mut context);
+        }
+
+        (query, context)
+    }
+
+    /// Submit the query to `db`, return its intercepted result. A panic of the
+    /// driver fails the query with the panic payload, so the run continues.
+    async fn submit(
+        &self,
+        db: &dyn Database,
+        query: String,
+        context: QueryContext,
+        execution: ExecutionContext,
+    ) -> Result<String> {
+        catch_unwind(self.submit_intercepted(db, query, context, execution))
+            .await
+            .map_err(|payload| self.failed(format!("driver panicked: {}", payload)))
+    }
+
+    async fn submit_intercepted(
+        &self,
+        db: &dyn Database,
+        query: String,
+        context: QueryContext,
+        execution: ExecutionContext,
+    ) -> String {
+        if let Some(database) = &context.database {
+            db.use_database(database).await;
+        }
+        for statement in context.session_statements {
+            // results of session statements are not recorded
+            db.query(execution.clone(), statement).await;
+        }
+
+        let mut write_failures = vec![];
+        for path in &context.write_lines {
+            let written = match tokio::fs::read_to_string(path).await {
+                Ok(lines) => db.write_lines(lines).await,
+                Err(e) => Err(format!("Failed to read {}, error: {}", path.display(), e)),
+            };
+            if let Err(e) = written {
+                write_failures.push(format!("Failed to write lines: {}", e));
+            }
+        }
+
+        // shape of typed results, otherwise described from the rendered one
+        let mut description = None;
+        let mut result = match (context.copy_from, context.copy_to, context.result_file) {
+            (Some(path), _, _) => match tokio::fs::read(&path).await {
+                Ok(data) => db.copy_in(query, data).await.to_string(),
+                Err(e) => format!("Failed to read {}, error: {}", path.display(), e),
+            },
+            (None, Some(path), _) => {
+                // `dyn Display` is not `Send`, drop it before next await
+                let (result, data) = {
+                    let (result, data) = db.copy_out(query).await;
+                    (result.to_string(), data)
+                };
+                match tokio::fs::write(&path, data).await {
+                    Ok(_) => result,
+                    Err(e) => format!("Failed to write {}, error: {}", path.display(), e),
+                }
+            }
+            (None, None, Some(file)) => {
+                let (result, data) = {
+                    let (result, data) = db.copy_out(query).await;
+                    (result.to_string(), data)
+                };
+                match tokio::fs::write(&file.path, &data).await {
+                    Ok(_) => format!(
+                        "{}\nData written to {}, {} bytes, md5 {}",
+                        result,
+                        file.name,
+                        data.len(),
+                        md5_hex(&data)
+                    ),
+                    Err(e) => format!("Failed to write {}, error: {}", file.path.display(), e),
+                }
+            }
+            (None, None, None) => match db.query_typed(execution.clone(), query.clone()).await {
+                Some(Ok(typed)) => {
+                    description = Some(typed.describe());
+                    typed.to_string()
+                }
+                Some(Err(e)) => e,
+                None => db.query(execution.clone(), query).await.to_string(),
+            },
+        };
+        if !write_failures.is_empty() {
+            result = format!("{}\n{}", write_failures.join("\n"), result);
+        }
+        if let Some(expected) = &context.expected_error_code {
+            result = match db.error_code(&result) {
+                Some(code) if &code == expected => format!("Error code: {}", code),
+                Some(code) => format!("Expected error code {}, got {}: {}", expected, code, result),
+                None => format!("Expected error code {}, got no error: {}", expected, result),
+            };
+        }
+        // assertions are checked against the raw result
+        let failures = context.assertion.as_ref().map(|assertion| {
+            let description = description.or_else(|| db.describe_result(&result));
+            assertion.check(description.as_ref())
+        });
+        for interceptor in &self.interceptors {
+            interceptor.after_execute(&mut result);
+        }
+        if let (Some(assertion), Some(failures)) = (&context.assertion, failures) {
+            result = match (failures.is_empty(), assertion.record_result) {
+                (true, true) => result,
+                (true, false) => "Assertion passed".to_string(),
+                (false, true) => format!("{}\n{}", failures.join("\n"), result),
+                (false, false) => failures.join("\n"),
+            };
+        }
+
+        for table in &context.schema_snapshots {
+            let schema = db.table_schema(table).await.to_string();
+            result.push_str(&format!(
+                "\n\nSchema of {}:\n{}",
+                table,
+                schema_snapshot::canonicalize(&schema)
+            ));
+        }
+        for snapshot in context.table_snapshots {
+            let rows = db
+                .query(execution.clone(), snapshot.query)
+                .await
+                .to_string();
+            result.push_str(&format!("\n\nSnapshot of {}:\n{}", snapshot.table, rows));
+        }
+
+        result
     }
 
     fn concat_query_lines(&self) -> String {
@@ -112,9 +1050,26 @@ impl Query {
     where
         W: AsyncWrite + Unpin,
     {
-        for interceptor in &self.interceptors {
-            writer.write_all(interceptor.as_bytes()).await?;
+        for line in &self.interceptor_lines {
+            writer.write_all(line.as_bytes()).await?;
+            writer.write("\n".as_bytes()).await?;
+        }
+        for line in &self.query_lines {
+            writer.write_all(line.as_bytes()).await?;
         }
+        writer.write("\n\n".as_bytes()).await?;
+        writer.write_all(result.as_bytes()).await?;
+        writer.write("\n\n".as_bytes()).await?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::unused_io_amount)]
+    async fn write_released_result<W>(&self, writer: &mut W, result: String) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        writer.write("Released:\n".as_bytes()).await?;
         for line in &self.query_lines {
             writer.write_all(line.as_bytes()).await?;
         }
@@ -125,3 +1080,262 @@ impl Query {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::ConfigBuilder;
+
+    async fn parse(content: &str) -> Result<TestCase> {
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        TestCase::from_reader("case".to_string(), content.as_bytes(), &config).await
+    }
+
+    #[tokio::test]
+    async fn require_features() {
+        let case = parse("-- SQLNESS REQUIRE feature=json feature=geo\nSELECT 1;\n")
+            .await
+            .unwrap();
+        assert_eq!(case.required_features, vec!["json", "geo"]);
+
+        // only the exact directive name counts
+        let case = parse("-- SQLNESS REQUIRES feature=x\nSELECT 1;\n")
+            .await
+            .unwrap();
+        assert!(case.required_features.is_empty());
+
+        assert!(parse("-- SQLNESS REQUIRE json\nSELECT 1;\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn min_version() {
+        let case = parse("-- SQLNESS MIN_VERSION 0.9.1\nSELECT 1;\n")
+            .await
+            .unwrap();
+        assert_eq!(case.requirements(), vec!["MIN_VERSION 0.9.1"]);
+
+        let case = parse("-- SQLNESS MIN_VERSIONS 0.9.1\nSELECT 1;\n")
+            .await
+            .unwrap();
+        assert!(case.min_version.is_none());
+    }
+
+    #[tokio::test]
+    async fn priority() {
+        let case = parse("-- SQLNESS PRIORITY -2\nSELECT 1;\n").await.unwrap();
+        assert_eq!(case.priority(), Some(-2));
+
+        let case = parse("-- SQLNESS PRIORITY_HIGH\nSELECT 1;\n")
+            .await
+            .unwrap();
+        assert_eq!(case.priority(), None);
+
+        assert!(parse("-- SQLNESS PRIORITY high\nSELECT 1;\n")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn dependencies() {
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        let case = TestCase::from_reader(
+            "cases/env/b".to_string(),
+            "-- SQLNESS DEPENDS_ON a common/c\nSELECT 1;\n".as_bytes(),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            case.dependencies(),
+            [
+                PathBuf::from("cases/env/a"),
+                PathBuf::from("cases/env/common/c")
+            ]
+        );
+
+        let case = parse("-- SQLNESS DEPENDS_ON_ALL a\nSELECT 1;\n")
+            .await
+            .unwrap();
+        assert!(case.dependencies().is_empty());
+
+        assert!(parse("-- SQLNESS DEPENDS_ON\nSELECT 1;\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn query_names() {
+        let case =
+            parse("-- SQLNESS NAME count rows\nSELECT 1;\n\n-- SQLNESS NAMESPACE foo\nSELECT 2;\n")
+                .await
+                .unwrap();
+        let names: Vec<_> = case
+            .queries
+            .iter()
+            .map(|query| query.name.clone())
+            .collect();
+        assert_eq!(names, vec![Some("count rows".to_string()), None]);
+
+        assert!(parse("-- SQLNESS NAME\nSELECT 1;\n").await.is_err());
+    }
+
+    /// Sessions of a server with one lock, `WAIT` blocks until it's released
+    /// by `UNLOCK`.
+    struct Session {
+        name: String,
+        locked: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Database for Session {
+        async fn query(&self, _context: ExecutionContext, query: String) -> Box<dyn Display> {
+            if query.contains("UNLOCK") {
+                self.locked.store(false, Ordering::SeqCst);
+            } else if query.contains("LOCK") {
+                self.locked.store(true, Ordering::SeqCst);
+            } else {
+                while self.locked.load(Ordering::SeqCst) {
+                    time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+            Box::new(format!("{} done", self.name))
+        }
+
+        async fn connection(&self, name: &str) -> Option<Box<dyn Database>> {
+            Some(Box::new(Session {
+                name: name.to_string(),
+                locked: self.locked.clone(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn blocks_across_connections() {
+        let case = "-- SQLNESS CONNECTION a
+LOCK;
+
+-- SQLNESS CONNECTION b
+-- SQLNESS BLOCKS 100ms
+WAIT;
+
+-- SQLNESS CONNECTION a
+-- SQLNESS UNBLOCKS
+UNLOCK;
+
+WAIT;
+";
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        let case = TestCase::from_reader("case".to_string(), case.as_bytes(), &config)
+            .await
+            .unwrap();
+        let db = Session {
+            name: "default".to_string(),
+            locked: Arc::new(AtomicBool::new(false)),
+        };
+        let mut output = vec![];
+        case.execute_timed(&db, &mut output).await.unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let results: Vec<_> = output
+            .lines()
+            .filter(|line| line.ends_with("done") || line.starts_with("Blocked"))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                "a done",
+                "Blocked for at least 100ms",
+                "a done",
+                "b done",
+                "default done"
+            ],
+            "{}",
+            output
+        );
+    }
+
+    #[tokio::test]
+    async fn result_file_of_exported_data() {
+        struct Db;
+
+        #[async_trait]
+        impl Database for Db {
+            async fn query(&self, _context: ExecutionContext, _query: String) -> Box<dyn Display> {
+                Box::new("rows")
+            }
+
+            async fn copy_out(&self, _query: String) -> (Box<dyn Display>, Vec<u8>) {
+                (Box::new("COPY 2"), vec![0, 1, 2, 255])
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("sqlness-result-file-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        let case = TestCase::from_reader(
+            dir.join("case").display().to_string(),
+            "-- SQLNESS RESULT_FILE t.bin\nCOPY t TO STDOUT;\n".as_bytes(),
+            &config,
+        )
+        .await
+        .unwrap();
+        let mut output = vec![];
+        case.execute(&Db, &mut output).await.unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let expected = format!(
+            "COPY 2\nData written to t.bin, 4 bytes, md5 {}",
+            md5_hex(&[0, 1, 2, 255])
+        );
+        assert!(output.contains(&expected), "{}", output);
+        assert_eq!(
+            tokio::fs::read(dir.join("t.bin")).await.unwrap(),
+            [0, 1, 2, 255]
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn connections_unsupported() {
+        struct Db;
+
+        #[async_trait]
+        impl Database for Db {
+            async fn query(&self, _context: ExecutionContext, _query: String) -> Box<dyn Display> {
+                Box::new("ok")
+            }
+        }
+
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        let case = TestCase::from_reader(
+            "case".to_string(),
+            "-- SQLNESS CONNECTION a\nSELECT 1;\n".as_bytes(),
+            &config,
+        )
+        .await
+        .unwrap();
+        let result = case.execute_timed(&Db, &mut vec![]).await;
+        assert!(
+            matches!(&result, Err(SqlnessError::QueryFailed { reason, .. }) if reason.contains("connection `a`")),
+            "{:?}",
+            result.map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Cases constructed in memory, for fuzzers and property tests which generate
+//! cases on the fly.
+
+use std::path::Path;
+
+use crate::case::TestCase;
+use crate::compare;
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::Result;
+use crate::normalize::{mask_elapsed, normalize};
+
+/// Builder of a [`TestCase`] from a list of queries, without case files.
+///
+/// ```rust, ignore, no_run
+/// let output = TestCaseBuilder::new("generated/1")
+///     .query("CREATE TABLE t (a INT);")
+///     .query_with(&["SORT_RESULT"], "SELECT a FROM t;")
+///     .expected(expected)
+///     .run(&db, &config)
+///     .await?;
+/// assert!(!output.is_different, "{}", output.report);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TestCaseBuilder {
+    name: String,
+    /// Directives (without prefix) and text of each query.
+    queries: Vec<(Vec<String>, String)>,
+    expected: Option<String>,
+}
+
+/// Output of a case run by [`TestCaseBuilder::run`].
+#[derive(Debug, Clone)]
+pub struct CaseOutput {
+    /// Output in the format of result files.
+    pub output: String,
+    /// Whether the output is different from the expected result. Always false
+    /// if no expected result is given.
+    pub is_different: bool,
+    /// Different queries and their diffs, empty if not different.
+    pub report: String,
+}
+
+impl TestCaseBuilder {
+    /// `name` is used like the path of case files in reports, and relative
+    /// paths in directives are resolved against its parent.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Append a query, which should end with [`Config::query_delimiter`].
+    pub fn query(self, query: impl Into<String>) -> Self {
+        self.query_with(&[], query)
+    }
+
+    /// Append a query with directives like `SORT_RESULT`, without
+    /// [`Config::interceptor_prefix`].
+    pub fn query_with(mut self, directives: &[&str], query: impl Into<String>) -> Self {
+        let directives = directives
+            .iter()
+            .map(|directive| directive.to_string())
+            .collect();
+        self.queries.push((directives, query.into()));
+        self
+    }
+
+    /// Expected result, in the format of result files.
+    pub fn expected(mut self, expected: impl Into<String>) -> Self {
+        self.expected = Some(expected.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Content of the case file, e.g. to persist a generated case.
+    pub fn to_case_file(&self, config: &Config) -> String {
+        let mut content = String::new();
+        for (directives, query) in &self.queries {
+            for directive in directives {
+                content.push_str(&format!("{} {}\n", config.interceptor_prefix, directive));
+            }
+            content.push_str(query.trim_end());
+            content.push_str("\n\n");
+        }
+
+        content
+    }
+
+    /// Parse the case, as if it's read from a case file.
+    pub async fn build(&self, config: &Config) -> Result<TestCase> {
+        let content = self.to_case_file(config);
+
+        TestCase::from_reader(self.name.clone(), content.as_bytes(), config).await
+    }
+
+    /// Execute the case against `db`, and compare its output with the expected
+    /// result like cases read from files. Fixtures and files of results are
+    /// not involved.
+    pub async fn run(&self, db: &dyn Database, config: &Config) -> Result<CaseOutput> {
+        let case = self.build(config).await?;
+        let mut output = vec![];
+        let query_ids = case.execute(db, &mut output).await?;
+        let output = normalize(config, &String::from_utf8(output)?);
+
+        let mut report = String::new();
+        let is_different = match &self.expected {
+            Some(expected) => {
+                let expected = mask_elapsed(config, &normalize(config, expected));
+                let actual = mask_elapsed(config, &output);
+                let is_different = !expected.lines().eq(actual.lines());
+                if is_different {
+                    compare::report_different_queries(
+                        config,
+                        &case,
+                        Path::new(&self.name),
+                        &expected,
+                        &actual,
+                        &query_ids,
+                        &mut report,
+                        &mut vec![],
+                    );
+                }
+                is_different
+            }
+            None => false,
+        };
+
+        Ok(CaseOutput {
+            output,
+            is_different,
+            report,
+        })
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Comparison between output and expected result of cases.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+use crate::annotation;
+use crate::case::{ResultBlock, TestCase};
+use crate::compression::Compression;
+use crate::config::Config;
+use crate::diff::{diff, hunks, render};
+use crate::error::{Result, SqlnessError};
+use crate::normalize::{mask_elapsed, normalize};
+
+/// Compare output of case at `path` (without extension) with its expected
+/// result. Return the report of differences if they are different.
+pub async fn compare<P: AsRef<Path>>(config: &Config, path: P) -> Result<Option<String>> {
+    let mut log = String::new();
+    let is_different =
+        compare_with_query_ids(config, path, None, &[], &mut log, &mut vec![]).await?;
+
+    Ok(is_different.then_some(log))
+}
+
+/// Same as [`compare`], and include IDs of executed queries (in the order of
+/// queries) in the report of different queries. The report is written to
+/// `log`, and different queries are pushed to `diffs`. `case` is parsed from
+/// the case file if not given.
+pub(crate) async fn compare_with_query_ids<P: AsRef<Path>>(
+    config: &Config,
+    path: P,
+    case: Option<&TestCase>,
+    query_ids: &[String],
+    log: &mut String,
+    diffs: &mut Vec<QueryDiff>,
+) -> Result<bool> {
+    let expect_path = expect_result_path(config, path.as_ref());
+    let result_lines = mask_elapsed(
+        config,
+        &normalize(config, &read_expect_result(config, &expect_path).await?),
+    );
+
+    let output_path = path
+        .as_ref()
+        .with_extension(&config.output_result_extension);
+    let mut output_lines = vec![];
+    let read = async {
+        File::open(&output_path)
+            .await?
+            .read_to_end(&mut output_lines)
+            .await
+    };
+    read.await.map_err(|e| SqlnessError::CompareIo {
+        source: e,
+        path: output_path.clone(),
+    })?;
+    let output_lines = mask_elapsed(
+        config,
+        &normalize(config, &config.file_encoding.decode(output_lines)?),
+    );
+
+    let is_different = !result_lines.lines().eq(output_lines.lines());
+    if is_different {
+        writeln!(log, "Result unexpected, path:{:?}", path.as_ref()).unwrap();
+        // nothing is reported if the case can't be parsed
+        let case_path = path.as_ref().with_extension(&config.test_case_extension);
+        let parsed = match case {
+            Some(_) => None,
+            None => TestCase::from_file(&case_path, config).await.ok(),
+        };
+        if let Some(case) = case.or(parsed.as_ref()) {
+            report_different_queries(
+                config,
+                case,
+                &case_path,
+                &result_lines,
+                &output_lines,
+                query_ids,
+                log,
+                diffs,
+            );
+        }
+        writeln!(
+            log,
+            "Hint: compare them with \"diff {} {}\"\n",
+            path.as_ref()
+                .with_extension(&config.output_result_extension)
+                .display(),
+            expect_path.display()
+        )
+        .unwrap();
+    }
+
+    Ok(is_different)
+}
+
+/// A query whose result is different from the expected one.
+pub(crate) struct QueryDiff {
+    /// Index (0-based) of the query in its case.
+    pub index: usize,
+    pub name: Option<String>,
+    pub query_id: Option<String>,
+    /// Range of lines (1-based, inclusive) of the query in expected result
+    /// file, `None` if it's missing.
+    pub expected_lines: Option<(usize, usize)>,
+    /// Range of lines of the query in output file.
+    pub actual_lines: Option<(usize, usize)>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Changed lines with [`Config::diff_context`] lines around them.
+pub(crate) struct DiffHunk {
+    /// Line number (1-based) of the first line in expected result file.
+    pub expected_line: usize,
+    /// Line number (1-based) of the first line in output file.
+    pub actual_line: usize,
+    /// Lines prefixed with ` `, `-` or `+`.
+    pub lines: Vec<String>,
+}
+
+/// Write which queries of `case` (whose file is at `case_path`) have
+/// different results, and push them to `diffs`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn report_different_queries(
+    config: &Config,
+    case: &TestCase,
+    case_path: &Path,
+    expected: &str,
+    actual: &str,
+    query_ids: &[String],
+    log: &mut String,
+    diffs: &mut Vec<QueryDiff>,
+) {
+    let expected_blocks = case.split_result(expected);
+    let actual_blocks = case.split_result(actual);
+    writeln!(log, "Different queries:").unwrap();
+    for index in 0..case.query_count() {
+        let expected = expected_blocks.get(index);
+        let actual = actual_blocks.get(index);
+        let position = match (expected, actual) {
+            (Some(expected), Some(actual)) if expected.result == actual.result => continue,
+            (Some(expected), Some(actual)) => format!(
+                "expected lines {}-{}, actual lines {}-{}",
+                expected.line, expected.end_line, actual.line, actual.end_line
+            ),
+            (Some(expected), None) => format!(
+                "expected lines {}-{}, missing in actual",
+                expected.line, expected.end_line
+            ),
+            (None, Some(actual)) => format!(
+                "missing in expected, actual lines {}-{}",
+                actual.line, actual.end_line
+            ),
+            (None, None) => "missing in both".to_string(),
+        };
+        let name = expected
+            .or(actual)
+            .and_then(|block| block.name.as_deref())
+            .map(|name| format!(" {}", name))
+            .unwrap_or_default();
+        let query_id = query_ids
+            .get(index)
+            .map(|id| format!(", query id {}", id))
+            .unwrap_or_default();
+        writeln!(log, "    #{}{} ({}{})", index + 1, name, position, query_id).unwrap();
+        let (diff, hunks) = match (expected, actual) {
+            (Some(expected), Some(actual)) => (
+                result_diff(config, &expected.result, &actual.result),
+                diff_hunks(config, expected, actual),
+            ),
+            _ => (String::new(), vec![]),
+        };
+        diffs.push(QueryDiff {
+            index,
+            name: expected.or(actual).and_then(|block| block.name.clone()),
+            query_id: query_ids.get(index).cloned(),
+            expected_lines: expected.map(|block| (block.line, block.end_line)),
+            actual_lines: actual.map(|block| (block.line, block.end_line)),
+            expected: expected.map(|block| block.result.clone()),
+            actual: actual.map(|block| block.result.clone()),
+            hunks,
+        });
+        log.push_str(&diff);
+        if annotation::enabled(config) {
+            let message = format!(
+                "Result of query #{}{} is unexpected ({})\n{}",
+                index + 1,
+                name,
+                position,
+                diff
+            );
+            let line = expected.or(actual).map(|block| block.case_line);
+            writeln!(log, "{}", annotation::error(case_path, line, &message)).unwrap();
+        }
+    }
+}
+
+/// Hunks of the diff between results of `expected` and `actual`, with line
+/// numbers in their files.
+fn diff_hunks(config: &Config, expected: &ResultBlock, actual: &ResultBlock) -> Vec<DiffHunk> {
+    let expected_lines: Vec<_> = expected.result.lines().collect();
+    let actual_lines: Vec<_> = actual.result.lines().collect();
+    let lines = diff(config.diff_algorithm, &expected_lines, &actual_lines);
+
+    hunks(&lines, config.diff_context)
+        .into_iter()
+        .map(|hunk| {
+            let count = |excluded: char| {
+                lines[..hunk.start]
+                    .iter()
+                    .filter(|(sign, _)| *sign != excluded)
+                    .count()
+            };
+            DiffHunk {
+                expected_line: expected.result_line + count('+'),
+                actual_line: actual.result_line + count('-'),
+                lines: lines[hunk]
+                    .iter()
+                    .map(|(sign, line)| format!("{}{}", sign, line))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Lines removed from `expected` and lines added in `actual`, in
+/// [`Config::diff_style`], with [`Config::diff_context`] lines around them.
+/// Differing words are highlighted if [`Config::highlight_words`] is set.
+/// Hunks are separated by `...`, and at most [`Config::max_diff_lines`] lines
+/// are written.
+fn result_diff(config: &Config, expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    let lines = diff(config.diff_algorithm, &expected, &actual);
+
+    let mut diff_lines = vec![];
+    for (index, hunk) in hunks(&lines, config.diff_context).into_iter().enumerate() {
+        if index > 0 && config.diff_context > 0 {
+            diff_lines.push("...".to_string());
+        }
+        diff_lines.extend(render(
+            &lines[hunk],
+            config.diff_style,
+            config.highlight_words,
+        ));
+    }
+
+    let mut diff = String::new();
+    let max_lines = match config.max_diff_lines {
+        0 => diff_lines.len(),
+        max_lines => max_lines,
+    };
+    for line in diff_lines.iter().take(max_lines) {
+        writeln!(diff, "        {}", line).unwrap();
+    }
+    if diff_lines.len() > max_lines {
+        writeln!(
+            diff,
+            "        ... {} more lines",
+            diff_lines.len() - max_lines
+        )
+        .unwrap();
+    }
+
+    diff
+}
+
+/// Path of the expected result. A platform-specific one like
+/// `case.result.windows` takes precedence over `case.result` if it exists.
+/// Compressed ones like `case.result.gz` are also looked up, see
+/// [`Compression`].
+///
+/// The platform suffix is the value of [`std::env::consts::OS`].
+pub fn expect_result_path(config: &Config, path: &Path) -> PathBuf {
+    let expect_path = path.with_extension(&config.expect_result_extension);
+    let mut os_specific_path = expect_path.clone().into_os_string();
+    os_specific_path.push(".");
+    os_specific_path.push(std::env::consts::OS);
+    let os_specific_path = PathBuf::from(os_specific_path);
+
+    [os_specific_path, expect_path.clone()]
+        .into_iter()
+        .flat_map(|path| {
+            let compressed: Vec<_> = Compression::candidates(&path).collect();
+            std::iter::once(path).chain(compressed)
+        })
+        .find(|path| path.exists())
+        .unwrap_or(expect_path)
+}
+
+/// Read the expected result at `path`, decompressed and converted from
+/// [`Config::file_encoding`].
+pub async fn read_expect_result(config: &Config, path: &Path) -> Result<String> {
+    let content = match Compression::from_path(path) {
+        Some(compression) => compression.decompress(path).await?,
+        None => tokio::fs::read(path)
+            .await
+            .map_err(|e| SqlnessError::CompareIo {
+                source: e,
+                path: path.to_path_buf(),
+            })?,
+    };
+
+    config.file_encoding.decode(content)
+}
@@ -0,0 +1,180 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::{
+    error::{Result, SqlnessError},
+    gzip,
+};
+
+/// Compression of expected result files, like `case.result.gz`. Gzip files
+/// are (de)compressed in process, zstd ones by the `zstd` command, which must
+/// be installed to use them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    const ALL: [Compression; 2] = [Compression::Gzip, Compression::Zstd];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Detect compression by extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+        Self::ALL
+            .into_iter()
+            .find(|compression| extension == compression.extension())
+    }
+
+    /// `path` with the extension of this compression appended.
+    pub fn compressed_path(&self, path: &Path) -> PathBuf {
+        let mut compressed = path.as_os_str().to_owned();
+        compressed.push(".");
+        compressed.push(self.extension());
+        PathBuf::from(compressed)
+    }
+
+    /// Paths of compressed versions of `path`.
+    pub(crate) fn candidates(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+        Self::ALL
+            .into_iter()
+            .map(|compression| compression.compressed_path(path))
+    }
+
+    pub(crate) async fn decompress(&self, path: &Path) -> Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let compressed = read(path).await?;
+                gzip::decompress(&compressed).map_err(|reason| SqlnessError::Compression {
+                    path: path.to_path_buf(),
+                    reason,
+                })
+            }
+            Compression::Zstd => zstd(path, &["-dcq"]).await,
+        }
+    }
+
+    /// Compress file `input` to `output`.
+    pub(crate) async fn compress(&self, input: &Path, output: &Path) -> Result<()> {
+        match self {
+            Compression::Gzip => {
+                let compressed = gzip::compress(&read(input).await?);
+                tokio::fs::write(output, compressed)
+                    .await
+                    .map_err(SqlnessError::IO)
+            }
+            Compression::Zstd => {
+                let output =
+                    output
+                        .as_os_str()
+                        .to_str()
+                        .ok_or_else(|| SqlnessError::Compression {
+                            path: output.to_path_buf(),
+                            reason: "path is not valid UTF-8".to_string(),
+                        })?;
+                zstd(input, &["-qf", "-o", output]).await.map(|_| ())
+            }
+        }
+    }
+}
+
+async fn read(path: &Path) -> Result<Vec<u8>> {
+    tokio::fs::read(path)
+        .await
+        .map_err(|e| SqlnessError::ReadPath {
+            source: e,
+            path: path.to_path_buf(),
+        })
+}
+
+/// Run the `zstd` command with `args` on `path`, return its stdout.
+async fn zstd(path: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("zstd")
+        .args(args)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => SqlnessError::MissingProgram {
+                program: "zstd",
+                purpose: "(de)compress `.zst` results",
+            },
+            _ => SqlnessError::IO(e),
+        })?;
+    check_status(path, &output)?;
+
+    Ok(output.stdout)
+}
+
+fn check_status(path: &Path, output: &Output) -> Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(SqlnessError::Compression {
+        path: path.to_path_buf(),
+        reason: format!(
+            "zstd exited with {}, stderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("sqlness-compression-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("case.result");
+        let content = "SELECT 1;\n\n1\n\n".repeat(100);
+        tokio::fs::write(&path, &content).await.unwrap();
+
+        for compression in Compression::ALL {
+            let compressed = compression.compressed_path(&path);
+            assert_eq!(Compression::from_path(&compressed), Some(compression));
+            match compression.compress(&path, &compressed).await {
+                Ok(()) => {}
+                // not installed here
+                Err(SqlnessError::MissingProgram { .. }) => continue,
+                Err(e) => panic!("{}", e),
+            }
+            let decompressed = compression.decompress(&compressed).await.unwrap();
+            assert_eq!(decompressed, content.as_bytes());
+        }
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_files() {
+        let dir = std::env::temp_dir().join(format!("sqlness-invalid-gz-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("case.result.gz");
+        tokio::fs::write(&path, "not compressed").await.unwrap();
+
+        let result = Compression::Gzip.decompress(&path).await;
+        assert!(matches!(result, Err(SqlnessError::Compression { .. })));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
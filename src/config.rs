@@ -1,12 +1,31 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path},
+    sync::Arc,
+    time::Duration,
+};
+
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
+use tokio::{fs::File, io::AsyncReadExt};
+
+use crate::{
+    compression::Compression,
+    diff::{DiffAlgorithm, DiffStyle},
+    encoding::Encoding,
+    error::{Result, SqlnessError},
+    event::MessageFormat,
+    interceptor::{self, blocks::parse_duration},
+    regex::Regex,
+    splitter::{DelimiterSplitter, LineSplitter, QuerySplitter},
+};
 
 /// Configurations of [`Runner`].
 ///
 /// [`Runner`]: crate::Runner
-#[derive(Debug, Serialize, Deserialize, Builder)]
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
 pub struct Config {
     pub case_dir: String,
     /// Default value: `sql`
@@ -25,6 +44,14 @@ pub struct Config {
     #[builder(default = "Config::default_interceptor_prefix()")]
     #[serde(default = "Config::default_interceptor_prefix")]
     pub interceptor_prefix: String,
+    /// Lines starting with this are comments, which are not recorded in
+    /// output. Change it along with [`Self::interceptor_prefix`] for languages
+    /// using other comment syntax, e.g. `#` and `# SQLNESS`.
+    ///
+    /// Default value: `--`
+    #[builder(default = "Config::default_comment_prefix()")]
+    #[serde(default = "Config::default_comment_prefix")]
+    pub comment_prefix: String,
     /// Default value: `config.toml`
     #[builder(default = "Config::default_env_config_file()")]
     #[serde(default = "Config::default_env_config_file")]
@@ -33,13 +60,836 @@ pub struct Config {
     #[builder(default = "true")]
     #[serde(default = "Config::default_fail_fast")]
     pub fail_fast: bool,
+    /// Convert CRLF line endings to LF, in outputs and before comparison.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub normalize_line_endings: bool,
+    /// Remove trailing whitespaces of lines, in outputs and before comparison.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub trim_trailing_whitespace: bool,
+    /// Re-align ASCII tables (bordered by `+---+` and `|`) by display width of
+    /// cells, in outputs and before comparison, so wide characters like CJK
+    /// don't cause alignment differences.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub normalize_tables: bool,
+    /// Remove ANSI escape sequences (e.g. colors) from results of all queries.
+    ///
+    /// Default value: `true`
+    #[builder(default = "true")]
+    #[serde(default = "Config::default_strip_ansi")]
+    pub strip_ansi: bool,
+    /// Encoding of case, result and output files, for suites migrated from
+    /// tools using legacy encodings: `utf8`, `latin1` or `gbk`.
+    ///
+    /// Default value: `utf8`
+    #[builder(default)]
+    #[serde(default)]
+    pub file_encoding: Encoding,
+    /// Overwrite expected results of different cases with their outputs, after
+    /// the change of behavior is verified.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub update_results: bool,
+    /// With [`Self::update_results`], append changes of expected results to
+    /// this unified patch file instead of overwriting them, so they can be
+    /// reviewed and applied by `git apply`. The file is truncated when a run
+    /// starts. Compressed expected results are still overwritten.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub update_patch: Option<String>,
+    /// Compress expected results created by [`MissingResultPolicy::Create`].
+    /// Compressed expected results like `case.result.gz` are always read
+    /// regardless of this.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub result_compression: Option<Compression>,
+    /// Format of messages printed while running.
+    ///
+    /// Default value: `human`
+    #[builder(default)]
+    #[serde(default)]
+    pub message_format: MessageFormat,
+    /// Print GitHub Actions annotations (like `::error file=...,line=...::`)
+    /// for different queries and failed cases, so they show inline in pull
+    /// requests. Only takes effect when the `GITHUB_ACTIONS` env var is
+    /// `true`.
+    ///
+    /// Default value: `true`
+    #[builder(default = "true")]
+    #[serde(default = "Config::default_github_annotations")]
+    pub github_annotations: bool,
+    /// If specified, only run cases whose files (case, expected result or
+    /// fixtures) changed since this git reference, including uncommitted
+    /// changes. All cases of an environment run if its env config changed.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub changed_since: Option<String>,
+    /// Glob patterns (relative to [`Self::case_dir`]) of files and directories
+    /// excluded from discovery of environments and cases, like
+    ///
+    /// ```toml
+    /// exclude_patterns = ["target/", "*.bak.sql"]
+    /// ```
+    ///
+    /// Patterns in `.sqlnessignore` under [`Self::case_dir`] (one per line,
+    /// `#` for comments) are also applied.
+    ///
+    /// Default value: none
+    #[builder(default)]
+    #[serde(default)]
+    pub exclude_patterns: Vec<String>,
+    /// Directories (relative to [`Self::case_dir`]) holding SQL and data files
+    /// shared by cases, e.g. files loaded by `COPY_FROM` via relative paths
+    /// like `../common/t.csv`. They are never collected as environments or
+    /// cases, wherever they are located.
+    ///
+    /// ```toml
+    /// shared_dirs = ["common", "local/fixtures"]
+    /// ```
+    ///
+    /// Default value: none
+    #[builder(default)]
+    #[serde(default)]
+    pub shared_dirs: Vec<String>,
+    /// Enter symlinked directories when discovering cases. Symlinks to their
+    /// ancestors are skipped. Regardless of this, symlinked case files are
+    /// discovered, and a case file reachable via multiple paths runs once,
+    /// under the first path in [`Self::case_order`].
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Discover environments and cases in hidden files and directories, whose
+    /// names start with `.`, like `.git`.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub include_hidden: bool,
+    /// Order of cases (before priorities and dependencies) and environments.
+    /// Names are compared case-insensitively. `natural` compares runs of
+    /// digits by their numeric values, so `case2` runs before `case10`.
+    ///
+    /// Default value: `lexicographic`
+    #[builder(default)]
+    #[serde(default)]
+    pub case_order: CaseOrder,
+    /// Priorities of cases matching glob patterns (relative to
+    /// [`Self::case_dir`]), like
+    ///
+    /// ```toml
+    /// [[priority_rules]]
+    /// pattern = "*/smoke/"
+    /// priority = 10
+    /// ```
+    ///
+    /// Cases with higher priority run first, otherwise cases run in
+    /// [`Self::case_order`]. The last matching rule wins, and `PRIORITY <n>`
+    /// in case files takes precedence. Default priority is 0.
+    #[builder(default)]
+    #[serde(default)]
+    pub priority_rules: Vec<PriorityRule>,
+    /// Rules applied to results of all queries, like
+    ///
+    /// ```toml
+    /// [normalize]
+    /// replace = [
+    ///     { pattern = "elapsed:\\s\\d+ms", replacement = "elapsed: <elapsed>" },
+    /// ]
+    /// ```
+    ///
+    /// See the `REPLACE` interceptor for the syntax.
+    #[builder(default)]
+    #[serde(default)]
+    pub normalize: NormalizeConfig,
+    /// Interceptors applied to all queries of cases matching glob patterns
+    /// (relative to [`Self::case_dir`]), like
+    ///
+    /// ```toml
+    /// [[directory_rules]]
+    /// pattern = "*/explain/"
+    /// directives = ["SORT_RESULT", "STRIP_ANSI"]
+    /// ```
+    ///
+    /// Directives are written without the interceptor prefix, and applied
+    /// after those in front of each query.
+    #[builder(default)]
+    #[serde(default)]
+    pub directory_rules: Vec<DirectoryRule>,
+    /// `CODEOWNERS` style file declaring owners of cases, with lines like
+    /// `env/dml/ @storage-team`. Patterns are relative to [`Self::case_dir`],
+    /// and the last matching line wins. Owners in case front-matter take
+    /// precedence. Failures are grouped by owners in reports.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub owners_file: Option<String>,
+    /// Treat the whole [`Self::case_dir`] as one environment named `.`,
+    /// instead of taking its first-level directories as environments. Useful
+    /// to run cases against one already-running server.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub single_env: bool,
+    /// Settings of environments overriding top-level ones, since e.g. a
+    /// cluster environment often needs longer timeouts and fewer parallel
+    /// cases than a local one, like
+    ///
+    /// ```toml
+    /// [env.cluster]
+    /// env_start_timeout = "5m"
+    /// parallelism = 1
+    /// directives = ["SORT_RESULT"]
+    /// ```
+    ///
+    /// See [`EnvSettings`] for settings which can be overridden.
+    ///
+    /// Default value: none
+    #[builder(default)]
+    #[serde(default, rename = "env")]
+    pub envs: BTreeMap<String, EnvSettings>,
+    /// Whether cases of an environment share one connection, or each case
+    /// runs on a fresh one created by [`Database::reconnect`].
+    ///
+    /// Default value: `shared`
+    ///
+    /// [`Database::reconnect`]: crate::Database::reconnect
+    #[builder(default)]
+    #[serde(default)]
+    pub connection_mode: ConnectionMode,
+    /// Number of cases of an environment running at the same time. Cases
+    /// declared `SERIAL` or matching [`Self::serial_patterns`] run alone, and
+    /// cases wait for those they depend on. Can't be used with
+    /// [`ConnectionMode::PerCase`], which reconnects the shared database.
+    ///
+    /// When greater than 1, each case runs on a connection of its own opened
+    /// by [`Database::connection`], so session state set by `SESSION` or
+    /// `USE` doesn't leak between concurrent cases. Databases not supporting
+    /// it run cases on the shared connection, and cases with `SESSION` or
+    /// `USE` fail.
+    ///
+    /// Default value: `1`
+    ///
+    /// [`Database::connection`]: crate::Database::connection
+    #[builder(default = "Config::default_parallelism()")]
+    #[serde(default = "Config::default_parallelism")]
+    pub parallelism: usize,
+    /// Glob patterns (relative to [`Self::case_dir`]) of cases running alone
+    /// when [`Self::parallelism`] is greater than 1, like
+    ///
+    /// ```toml
+    /// serial_patterns = ["*/ddl/"]
+    /// ```
+    ///
+    /// Default value: none
+    #[builder(default)]
+    #[serde(default)]
+    pub serial_patterns: Vec<String>,
+    /// What to do with cases without expected result file.
+    ///
+    /// Default value: `fail`
+    #[builder(default)]
+    #[serde(default)]
+    pub missing_result: MissingResultPolicy,
+    /// Algorithm to compute diffs of unexpected results. `patience` is more
+    /// readable for reordered rows.
+    ///
+    /// Default value: `myers`
+    #[builder(default)]
+    #[serde(default)]
+    pub diff_algorithm: DiffAlgorithm,
+    /// `unified` to print removed and inserted lines one after another, or
+    /// `side_by_side` to print expected and actual lines in two columns,
+    /// which is easier to scan for tables.
+    ///
+    /// Default value: `unified`
+    #[builder(default)]
+    #[serde(default)]
+    pub diff_style: DiffStyle,
+    /// Highlight differing words within changed lines in diffs of unexpected
+    /// results, with ANSI reverse video escape sequences. Useful to spot a
+    /// changed value in long rows in terminals.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub highlight_words: bool,
+    /// Lines of unchanged context around changes in diffs of unexpected
+    /// results.
+    ///
+    /// Default value: `0`
+    #[builder(default)]
+    #[serde(default)]
+    pub diff_context: usize,
+    /// Maximum lines of the diff printed for each query with unexpected
+    /// result, the rest are omitted. `0` means no limit.
+    ///
+    /// Default value: `0`
+    #[builder(default)]
+    #[serde(default)]
+    pub max_diff_lines: usize,
+    /// Timeout of [`EnvController::start`], like `5m`. When exceeded, the
+    /// environment fails and the run continues with remaining environments.
+    ///
+    /// Default value: none, wait forever.
+    ///
+    /// [`EnvController::start`]: crate::EnvController::start
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub env_start_timeout: Option<String>,
+    /// Timeout of [`EnvController::stop`], like `30s`.
+    ///
+    /// Default value: none, wait forever.
+    ///
+    /// [`EnvController::stop`]: crate::EnvController::stop
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub env_stop_timeout: Option<String>,
+    /// Budget of the whole run, like `30m`, `90s` or `500ms`. When exceeded,
+    /// no more cases are started, the running case finishes, environments are
+    /// stopped, and cases not executed are reported.
+    ///
+    /// Default value: none, no budget.
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub max_run_duration: Option<String>,
+    /// Queries taking longer than this, like `500ms` or `2s`, are listed with
+    /// their cases, lines and durations after each environment and in
+    /// reports, to surface creeping regressions. Results are not affected.
+    ///
+    /// Default value: none, no query is slow.
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub slow_query_threshold: Option<String>,
+    /// Interval of sampling resource usage of servers with
+    /// [`EnvController::sample_resources`] while cases run, like `1s`. Peak
+    /// and average values of each environment and case are printed after the
+    /// environment and included in reports, so memory blowups can be
+    /// attributed to cases.
+    ///
+    /// Default value: none, resources are not sampled.
+    ///
+    /// [`EnvController::sample_resources`]: crate::EnvController::sample_resources
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub resource_sample_interval: Option<String>,
+    /// OpenTelemetry collector receiving spans of runs (run → env → case →
+    /// query) in OTLP/HTTP JSON encoding, like `http://localhost:4318`.
+    /// `/v1/traces` is used if the endpoint has no path. Only plain HTTP is
+    /// supported, and spans are sent once [`Runner::run`] finishes.
+    ///
+    /// Default value: none
+    ///
+    /// [`Runner::run`]: crate::Runner::run
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// File to write metrics of runs in Prometheus text format, e.g. for the
+    /// node exporter textfile collector, including cases by outcome and
+    /// durations of cases, environments and the whole run. It's written once
+    /// [`Runner::run`] finishes.
+    ///
+    /// Default value: none
+    ///
+    /// [`Runner::run`]: crate::Runner::run
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub metrics_file: Option<String>,
+    /// Prometheus pushgateway to push metrics of runs (see
+    /// [`Self::metrics_file`]) to, like `http://localhost:9091`.
+    /// `/metrics/job/sqlness` is used if the url has no path. Only plain HTTP
+    /// is supported.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub pushgateway_url: Option<String>,
+    /// File to write a self-contained HTML report of runs to, with a section
+    /// per environment, a filterable table of cases, timings and collapsible
+    /// diffs. It's written once [`Runner::run`] finishes.
+    ///
+    /// Default value: none
+    ///
+    /// [`Runner::run`]: crate::Runner::run
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub html_report: Option<String>,
+    /// File to write a compact Markdown summary of runs to, sized for pull
+    /// request comments posted by CI bots. It has counts of cases per
+    /// environment, and failing cases with truncated diffs.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub markdown_report: Option<String>,
+    /// Directory to write Allure results to, one test result per case with a
+    /// step per query. Diffs and tails of server logs (declared by the
+    /// `server_logs` array in env config file) are attached to failed cases.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub allure_results_dir: Option<String>,
     /// If specified, only run cases containing this string in their names.
     #[builder(default = "Config::default_test_filter()")]
     #[serde(default = "Config::default_test_filter")]
     pub test_filter: String,
+    /// Query to probe the server version after an environment starts, like
+    /// `SELECT version();`. The first version-like token in its result is
+    /// used to skip cases declaring a higher `MIN_VERSION`.
+    ///
+    /// Default value: none, `MIN_VERSION` is ignored.
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub version_probe: Option<String>,
+    /// Only collect and parse cases, print which environments and cases would
+    /// run without starting any environment.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Results with more lines than this are recorded as the line count and
+    /// MD5 hash of them, to keep golden files small. `0` disables it.
+    ///
+    /// Default value: `0`
+    #[builder(default)]
+    #[serde(default)]
+    pub hash_threshold: usize,
+    /// Run cases as benchmarks instead of tests: each query is executed
+    /// some warmup iterations and then measured iterations, results are not
+    /// compared, and latencies (min, p50, p95 and max) of queries are
+    /// printed, like
+    ///
+    /// ```toml
+    /// [benchmark]
+    /// warmups = 2
+    /// iterations = 20
+    /// report = "target/sqlness/benchmark.csv"
+    /// ```
+    ///
+    /// Queries are repeated regardless of their side effects, and `BLOCKS`
+    /// is ignored. Setup and teardown fixtures run once around each case.
+    ///
+    /// Default value: none
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub benchmark: Option<BenchmarkConfig>,
+    /// Append a line like `-- elapsed: 12ms` (with [`Self::comment_prefix`])
+    /// after the result of each query in outputs, to see where time went when
+    /// reviewing them. Elapsed times are masked as `<elapsed>` when comparing,
+    /// so they never cause differences.
+    ///
+    /// Default value: `false`
+    #[builder(default)]
+    #[serde(default)]
+    pub record_elapsed: bool,
+    /// Prepend `/* sqlness_query_id=<id> */` to queries, so they can be found
+    /// in server logs by the IDs in reports. Drivers can also propagate the
+    /// ID of [`ExecutionContext::query_id`].
+    ///
+    /// Default value: `false`
+    ///
+    /// [`ExecutionContext::query_id`]: crate::ExecutionContext::query_id
+    #[builder(default)]
+    #[serde(default)]
+    pub query_id_comment: bool,
+    /// Queries in case files end with a line ending with this delimiter. Set
+    /// it to `"\n"` to treat each line as a query, for languages like PromQL.
+    ///
+    /// Default value: `;`
+    #[builder(default = "Config::default_query_delimiter()")]
+    #[serde(default = "Config::default_query_delimiter")]
+    pub query_delimiter: String,
+    /// Custom [`QuerySplitter`], overrides [`Self::query_delimiter`]. Can only
+    /// be set via [`ConfigBuilder`].
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    pub query_splitter: Option<Arc<dyn QuerySplitter>>,
+}
+
+/// See [`Config::benchmark`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BenchmarkConfig {
+    /// Iterations of each query before measuring.
+    ///
+    /// Default value: `1`
+    #[serde(default = "BenchmarkConfig::default_warmups")]
+    pub warmups: usize,
+    /// Measured iterations of each query.
+    ///
+    /// Default value: `10`
+    #[serde(default = "BenchmarkConfig::default_iterations")]
+    pub iterations: usize,
+    /// File to write latencies of all queries to, in CSV.
+    ///
+    /// Default value: none
+    #[serde(default)]
+    pub report: Option<String>,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            warmups: Self::default_warmups(),
+            iterations: Self::default_iterations(),
+            report: None,
+        }
+    }
+}
+
+impl BenchmarkConfig {
+    fn default_warmups() -> usize {
+        1
+    }
+
+    fn default_iterations() -> usize {
+        10
+    }
+}
+
+/// Settings of an environment, see [`Config::envs`]. Those not specified
+/// are inherited from [`Config`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvSettings {
+    /// Overrides [`Config::test_filter`].
+    #[serde(default)]
+    pub test_filter: Option<String>,
+    /// Overrides [`Config::env_start_timeout`].
+    #[serde(default)]
+    pub env_start_timeout: Option<String>,
+    /// Overrides [`Config::env_stop_timeout`].
+    #[serde(default)]
+    pub env_stop_timeout: Option<String>,
+    /// Overrides [`Config::parallelism`].
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+    /// Interceptors applied to all queries of cases under the directory named
+    /// after the environment, after those of [`Config::directory_rules`].
+    #[serde(default)]
+    pub directives: Vec<String>,
+}
+
+/// See [`Config::priority_rules`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriorityRule {
+    pub pattern: String,
+    pub priority: i32,
+}
+
+/// See [`Config::normalize`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NormalizeConfig {
+    /// Replace matches of `pattern` in each line of results, in order.
+    #[serde(default)]
+    pub replace: Vec<ReplaceRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplaceRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub replacement: String,
+}
+
+/// See [`Config::directory_rules`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryRule {
+    pub pattern: String,
+    pub directives: Vec<String>,
+}
+
+/// A problem of [`Config`] found by [`Config::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub field: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.reason)
+    }
+}
+
+/// See [`Config::case_order`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseOrder {
+    /// Like `case1`, `case10`, `case2`.
+    #[default]
+    Lexicographic,
+    /// Like `case1`, `case2`, `case10`.
+    Natural,
+}
+
+/// See [`Config::connection_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionMode {
+    /// One connection for all cases of an environment.
+    #[default]
+    Shared,
+    /// A fresh connection for each case, so session state (e.g. variables,
+    /// temporary tables) doesn't leak between cases.
+    PerCase,
+}
+
+/// Policy for cases without expected result file, see
+/// [`Config::missing_result`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingResultPolicy {
+    /// Write the output as the expected result, with a warning.
+    Create,
+    /// Fail the case.
+    #[default]
+    Fail,
+    /// Skip the case without running it.
+    Skip,
 }
 
 impl Config {
+    /// Read config from a toml file.
+    pub async fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let read_config = |e| SqlnessError::ReadConfig {
+            source: e,
+            path: path.as_ref().to_path_buf(),
+        };
+        let mut config_file = File::open(path.as_ref()).await.map_err(read_config)?;
+
+        let mut config_buf = vec![];
+        config_file
+            .read_to_end(&mut config_buf)
+            .await
+            .map_err(read_config)?;
+        toml::from_slice(&config_buf).map_err(|e| SqlnessError::ParseToml {
+            source: e,
+            file: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Check the config before running, return all problems found at once
+    /// as [`SqlnessError::InvalidConfigs`].
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+        let mut problem = |field: &str, reason: String| {
+            problems.push(ConfigProblem {
+                field: field.to_string(),
+                reason,
+            })
+        };
+
+        let case_dir = Path::new(&self.case_dir);
+        if !case_dir.is_dir() {
+            let reason = if case_dir.exists() {
+                "not a directory"
+            } else {
+                "directory doesn't exist"
+            };
+            problem("case_dir", format!("{}: {}", reason, self.case_dir));
+        }
+
+        let extensions = [
+            ("test_case_extension", &self.test_case_extension),
+            ("output_result_extension", &self.output_result_extension),
+            ("expect_result_extension", &self.expect_result_extension),
+        ];
+        for (i, (field, extension)) in extensions.iter().enumerate() {
+            if extension.is_empty() {
+                problem(field, "must not be empty".to_string());
+            } else if let Some((other, _)) =
+                extensions[..i].iter().find(|(_, other)| other == extension)
+            {
+                problem(field, format!("`{}` collides with `{}`", extension, other));
+            }
+        }
+        for (field, value) in [
+            ("interceptor_prefix", &self.interceptor_prefix),
+            ("comment_prefix", &self.comment_prefix),
+            ("env_config_file", &self.env_config_file),
+        ] {
+            if value.is_empty() {
+                problem(field, "must not be empty".to_string());
+            }
+        }
+        if self.query_splitter.is_none() && self.query_delimiter.is_empty() {
+            problem("query_delimiter", "must not be empty".to_string());
+        }
+
+        for (field, value) in [
+            ("env_start_timeout", &self.env_start_timeout),
+            ("env_stop_timeout", &self.env_stop_timeout),
+            ("max_run_duration", &self.max_run_duration),
+            ("slow_query_threshold", &self.slow_query_threshold),
+            ("resource_sample_interval", &self.resource_sample_interval),
+        ] {
+            if let Some(Err(reason)) = value.as_deref().map(parse_duration) {
+                problem(field, reason);
+            }
+        }
+        if let Some(Ok(Duration::ZERO)) =
+            self.resource_sample_interval.as_deref().map(parse_duration)
+        {
+            problem(
+                "resource_sample_interval",
+                "must be greater than 0".to_string(),
+            );
+        }
+        for dir in &self.shared_dirs {
+            let path = Path::new(dir);
+            let escapes = path
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+            if dir.is_empty() || escapes {
+                problem(
+                    "shared_dirs",
+                    format!("must be a directory under case_dir: `{}`", dir),
+                );
+            }
+        }
+        for rule in &self.normalize.replace {
+            if let Err(reason) = Regex::new(&rule.pattern) {
+                problem("normalize.replace", reason);
+            }
+        }
+        for rule in &self.directory_rules {
+            for directive in &rule.directives {
+                match interceptor::parse(directive, case_dir) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => problem(
+                        "directory_rules",
+                        format!(
+                            "unknown interceptor `{}` in rule of `{}`",
+                            directive, rule.pattern
+                        ),
+                    ),
+                    Err(e) => problem("directory_rules", e.to_string()),
+                }
+            }
+        }
+        if let Some(owners_file) = &self.owners_file {
+            if !Path::new(owners_file).is_file() {
+                problem(
+                    "owners_file",
+                    format!("file doesn't exist: {}", owners_file),
+                );
+            }
+        }
+        if self
+            .benchmark
+            .as_ref()
+            .map(|b| b.iterations == 0)
+            .unwrap_or(false)
+        {
+            problem("benchmark.iterations", "must be greater than 0".to_string());
+        }
+        if self.parallelism == 0 {
+            problem("parallelism", "must be greater than 0".to_string());
+        }
+        if self.parallelism > 1 && self.connection_mode == ConnectionMode::PerCase {
+            problem(
+                "parallelism",
+                "can't be greater than 1 with `connection_mode = \"per_case\"`".to_string(),
+            );
+        }
+        for (env, settings) in &self.envs {
+            for (name, value) in [
+                ("env_start_timeout", &settings.env_start_timeout),
+                ("env_stop_timeout", &settings.env_stop_timeout),
+            ] {
+                if let Some(Err(reason)) = value.as_deref().map(parse_duration) {
+                    problem(&format!("env.{}.{}", env, name), reason);
+                }
+            }
+            let field = format!("env.{}.parallelism", env);
+            match settings.parallelism {
+                Some(0) => problem(&field, "must be greater than 0".to_string()),
+                Some(parallelism)
+                    if parallelism > 1 && self.connection_mode == ConnectionMode::PerCase =>
+                {
+                    problem(
+                        &field,
+                        "can't be greater than 1 with `connection_mode = \"per_case\"`".to_string(),
+                    )
+                }
+                _ => {}
+            }
+            for directive in &settings.directives {
+                match interceptor::parse(directive, case_dir) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => problem(
+                        &format!("env.{}.directives", env),
+                        format!("unknown interceptor `{}`", directive),
+                    ),
+                    Err(e) => problem(&format!("env.{}.directives", env), e.to_string()),
+                }
+            }
+        }
+        if self.update_results && self.dry_run {
+            problem(
+                "update_results",
+                "conflicts with `dry_run`, which doesn't run cases".to_string(),
+            );
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SqlnessError::InvalidConfigs { problems })
+        }
+    }
+
+    /// Config of environment `env`, with its settings in [`Self::envs`]
+    /// applied.
+    pub fn for_env(&self, env: &str) -> Config {
+        let mut config = self.clone();
+        if let Some(settings) = self.envs.get(env) {
+            if let Some(test_filter) = &settings.test_filter {
+                config.test_filter = test_filter.clone();
+            }
+            if settings.env_start_timeout.is_some() {
+                config.env_start_timeout = settings.env_start_timeout.clone();
+            }
+            if settings.env_stop_timeout.is_some() {
+                config.env_stop_timeout = settings.env_stop_timeout.clone();
+            }
+            if let Some(parallelism) = settings.parallelism {
+                config.parallelism = parallelism;
+            }
+        }
+
+        config
+    }
+
+    /// The [`QuerySplitter`] used to parse case files.
+    pub(crate) fn splitter(&self) -> Arc<dyn QuerySplitter> {
+        match &self.query_splitter {
+            Some(splitter) => splitter.clone(),
+            None if self.query_delimiter == "\n" => Arc::new(LineSplitter),
+            None => Arc::new(DelimiterSplitter::new(self.query_delimiter.clone())),
+        }
+    }
+
     fn default_test_case_extension() -> String {
         "sql".to_string()
     }
@@ -56,6 +906,10 @@ impl Config {
         "-- SQLNESS".to_string()
     }
 
+    fn default_comment_prefix() -> String {
+        "--".to_string()
+    }
+
     fn default_env_config_file() -> String {
         "config.toml".to_string()
     }
@@ -64,7 +918,143 @@ impl Config {
         true
     }
 
+    fn default_parallelism() -> usize {
+        1
+    }
+
+    fn default_strip_ansi() -> bool {
+        true
+    }
+
+    fn default_github_annotations() -> bool {
+        true
+    }
+
     fn default_test_filter() -> String {
         "".to_string()
     }
+
+    fn default_query_delimiter() -> String {
+        ";".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(config: &Config) -> Vec<(String, String)> {
+        match config.validate() {
+            Ok(()) => vec![],
+            Err(SqlnessError::InvalidConfigs { problems }) => problems
+                .into_iter()
+                .map(|problem| (problem.field, problem.reason))
+                .collect(),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    fn fields(config: &Config) -> Vec<String> {
+        problems(config)
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    fn builder() -> ConfigBuilder {
+        let mut builder = ConfigBuilder::default();
+        builder.case_dir(std::env::temp_dir().to_string_lossy().to_string());
+        builder
+    }
+
+    #[test]
+    fn valid_config() {
+        assert!(builder().build().unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn report_all_problems() {
+        let config = builder()
+            .case_dir("not-exist-sqlness-cases".to_string())
+            .output_result_extension("result".to_string())
+            .comment_prefix(String::new())
+            .env_start_timeout("soon".to_string())
+            .shared_dirs(vec!["../common".to_string()])
+            .parallelism(0)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            problems(&config),
+            vec![
+                (
+                    "case_dir".to_string(),
+                    "directory doesn't exist: not-exist-sqlness-cases".to_string()
+                ),
+                (
+                    "expect_result_extension".to_string(),
+                    "`result` collides with `output_result_extension`".to_string()
+                ),
+                (
+                    "comment_prefix".to_string(),
+                    "must not be empty".to_string()
+                ),
+                (
+                    "env_start_timeout".to_string(),
+                    parse_duration("soon").unwrap_err()
+                ),
+                (
+                    "shared_dirs".to_string(),
+                    "must be a directory under case_dir: `../common`".to_string()
+                ),
+                (
+                    "parallelism".to_string(),
+                    "must be greater than 0".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        let config = builder()
+            .directory_rules(vec![DirectoryRule {
+                pattern: "**".to_string(),
+                directives: vec!["NOT_EXIST".to_string(), "REPLACE".to_string()],
+            }])
+            .update_results(true)
+            .dry_run(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            fields(&config),
+            vec!["directory_rules", "directory_rules", "update_results"]
+        );
+    }
+
+    #[test]
+    fn reject_parallel_per_case_connections() {
+        let mut config = builder()
+            .connection_mode(ConnectionMode::PerCase)
+            .build()
+            .unwrap();
+        assert!(config.validate().is_ok());
+
+        config.parallelism = 4;
+        assert_eq!(fields(&config), vec!["parallelism"]);
+
+        config.parallelism = 1;
+        config.envs.insert(
+            "local".to_string(),
+            EnvSettings {
+                parallelism: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(fields(&config), vec!["env.local.parallelism"]);
+
+        config.connection_mode = ConnectionMode::Shared;
+        assert!(config.validate().is_ok());
+    }
 }
@@ -1,17 +1,222 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
 
 use async_trait::async_trait;
 
+/// Context of a query passed to [`Database::query`]. Queries not from cases,
+/// like `init_statements` of env config files, have an empty context.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionContext {
+    /// Path of the case file.
+    pub case_path: PathBuf,
+    /// Index (0-based) of the query in its case.
+    pub query_index: usize,
+    /// Unique ID of this execution, which is also included in reports.
+    /// Propagate it to the server, e.g. as a trace header, so failing queries
+    /// can be found in server logs and traces.
+    pub query_id: String,
+    /// Tags of the case, declared in its front-matter.
+    pub tags: Vec<String>,
+    /// Options of the query, declared by `ARG` directives like
+    /// `-- SQLNESS ARG protocol=http`.
+    pub options: BTreeMap<String, String>,
+}
+
 /// Query executor.
 ///
 /// [`Runner`] will call [`EnvController::start`] to create database to
 /// execute query.
 ///
+/// Implementations need to be `Send + Sync` as queries may be submitted from
+/// different tasks.
+///
 /// [`Runner`]: crate::Runner
 /// [`EnvController::start`]: crate::EnvController#tymethod.start
 #[async_trait]
-pub trait Database {
-    async fn query(&self, query: String) -> Box<dyn Display>;
+pub trait Database: Send + Sync {
+    /// Execute `query`, with the [`ExecutionContext`] of it, so drivers can
+    /// implement protocol-specific behaviors.
+    async fn query(&self, context: ExecutionContext, query: String) -> Box<dyn Display>;
+
+    /// Execute `query` and return typed rows, for drivers with structured
+    /// results like ADBC (Arrow record batches) or in-process engines. The
+    /// result is recorded as rendered by [`TypedResult`], and the `ASSERT`
+    /// interceptor checks its column types directly instead of asking
+    /// [`Database::describe_result`]. Return `Err` with the message of a
+    /// failed query, which is recorded as the result.
+    ///
+    /// The default implementation returns `None`, then [`Database::query`] is
+    /// called.
+    async fn query_typed(
+        &self,
+        _context: ExecutionContext,
+        _query: String,
+    ) -> Option<std::result::Result<TypedResult, String>> {
+        None
+    }
+
+    /// Extract the error code (e.g. SQLSTATE) from the rendered `result` of a
+    /// failed query, used by the `EXPECT_ERRCODE` interceptor. Return `None` if
+    /// the query succeeded.
+    ///
+    /// The default implementation returns `None`.
+    fn error_code(&self, _result: &str) -> Option<String> {
+        None
+    }
+
+    /// Describe the rendered `result` of a query, used by the `ASSERT`
+    /// interceptor. Return `None` if it can't be described, e.g. the query
+    /// failed.
+    ///
+    /// The default implementation returns `None`.
+    fn describe_result(&self, _result: &str) -> Option<ResultDescription> {
+        None
+    }
+
+    /// Return the schema of `table`, used by the `SCHEMA_SNAPSHOT` interceptor.
+    /// Implementations may build it from driver metadata instead.
+    ///
+    /// The default implementation executes a `SHOW CREATE TABLE <table>;`
+    /// statement.
+    async fn table_schema(&self, table: &str) -> Box<dyn Display> {
+        self.query(
+            ExecutionContext::default(),
+            format!("SHOW CREATE TABLE {};", table),
+        )
+        .await
+    }
+
+    /// Switch the current database (or schema, tenant etc.) for all
+    /// subsequent queries. Called by the `USE` interceptor.
+    ///
+    /// The default implementation executes a `USE <name>;` statement.
+    async fn use_database(&self, name: &str) {
+        self.query(ExecutionContext::default(), format!("USE {};", name))
+            .await;
+    }
+
+    /// Replace the underlying connection (session) with a fresh one, so no
+    /// session state leaks from previous cases. Called before each case when
+    /// [`Config::connection_mode`] is `per_case`.
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// [`Config::connection_mode`]: crate::Config::connection_mode
+    async fn reconnect(&self) {}
+
+    /// Open a new connection (session) to the same server, used by queries
+    /// declared with `CONNECTION <name>`. It's called on the first use of
+    /// `name` in each case, and the connection is dropped when the case ends.
+    ///
+    /// It's also called with the path of each case when
+    /// [`Config::parallelism`] is greater than 1, to run the case on its own
+    /// connection.
+    ///
+    /// The default implementation returns `None`, so such queries fail.
+    ///
+    /// [`Config::parallelism`]: crate::Config::parallelism
+    async fn connection(&self, _name: &str) -> Option<Box<dyn Database>> {
+        None
+    }
+
+    /// Features supported by this database, used to skip cases declaring
+    /// `REQUIRE feature=<name>`. Implementations may probe them via queries.
+    ///
+    /// Those are merged with the `features` declared in env config file. The
+    /// default implementation returns nothing.
+    async fn features(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Execute a statement reading data from the client, like Postgres's
+    /// `COPY ... FROM STDIN` or MySQL's `LOAD DATA LOCAL INFILE`. `data` is the
+    /// content of the local file specified by the `COPY_FROM` interceptor.
+    ///
+    /// The default implementation reports it's unsupported.
+    async fn copy_in(&self, _query: String, _data: Vec<u8>) -> Box<dyn Display> {
+        Box::new("Bulk load from client is not supported")
+    }
+
+    /// Execute a statement sending data to the client, like Postgres's
+    /// `COPY ... TO STDOUT`. Return the result and the data, which is written
+    /// to the local file specified by the `COPY_TO` or `RESULT_FILE`
+    /// interceptor.
+    ///
+    /// The default implementation reports it's unsupported.
+    async fn copy_out(&self, _query: String) -> (Box<dyn Display>, Vec<u8>) {
+        (Box::new("Bulk unload to client is not supported"), vec![])
+    }
+
+    /// Write `lines` in InfluxDB line protocol to the ingestion endpoint of
+    /// the server, for time-series engines. `lines` is the content of the
+    /// local file specified by the `WRITE_LINES` interceptor. Return the error
+    /// message if it fails, which is recorded in front of the query result.
+    ///
+    /// The default implementation reports it's unsupported.
+    async fn write_lines(&self, _lines: String) -> std::result::Result<(), String> {
+        Err("Line protocol ingestion is not supported".to_string())
+    }
+}
+
+/// Result of a query with typed columns, see [`Database::query_typed`].
+///
+/// It's rendered with a header of `<name>:<type>` columns, then one line per
+/// row. Values are separated by tabs, and null values are `NULL`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypedResult {
+    pub columns: Vec<TypedColumn>,
+    /// Values of rows, formatted by the driver, `None` for null values.
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+/// Column of a [`TypedResult`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypedColumn {
+    pub name: String,
+    /// Type name, in the form used by `ASSERT types=...`, e.g. Arrow's
+    /// `Int64` or `Utf8`.
+    pub data_type: String,
+}
+
+impl TypedResult {
+    pub fn describe(&self) -> ResultDescription {
+        ResultDescription {
+            rows: self.rows.len(),
+            column_types: self
+                .columns
+                .iter()
+                .map(|column| column.data_type.clone())
+                .collect(),
+        }
+    }
+}
+
+impl Display for TypedResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| format!("{}:{}", column.name, column.data_type))
+            .collect();
+        write!(f, "{}", header.join("\t"))?;
+        for row in &self.rows {
+            let values: Vec<_> = row
+                .iter()
+                .map(|value| value.as_deref().unwrap_or("NULL"))
+                .collect();
+            write!(f, "\n{}", values.join("\t"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Shape of a query result, see [`Database::describe_result`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResultDescription {
+    /// Number of rows.
+    pub rows: usize,
+    /// Type names of columns, in the form used by `ASSERT types=...`.
+    pub column_types: Vec<String>,
 }
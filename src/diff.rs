@@ -0,0 +1,463 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Line diffs between expected results and outputs.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
+/// Left columns of side-by-side diffs are padded to at most this width, longer
+/// lines push their right columns further.
+const SIDE_BY_SIDE_MAX_WIDTH: usize = 60;
+
+const HIGHLIGHT_START: &str = "\x1b[7m";
+const HIGHLIGHT_END: &str = "\x1b[27m";
+
+/// Algorithm to compute line diffs, see [`Config::diff_algorithm`].
+///
+/// [`Config::diff_algorithm`]: crate::Config::diff_algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAlgorithm {
+    /// The O(ND) algorithm by Myers, which finds a minimal diff.
+    #[default]
+    Myers,
+    /// Anchor the diff at lines unique in both sides, then diff between them
+    /// with Myers. More readable when rows are reordered.
+    Patience,
+}
+
+/// How diffs of unexpected results are printed, see [`Config::diff_style`].
+///
+/// [`Config::diff_style`]: crate::Config::diff_style
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffStyle {
+    /// Removed lines with `-` followed by inserted lines with `+`.
+    #[default]
+    Unified,
+    /// Expected lines on the left and actual lines on the right, marked like
+    /// `diff --side-by-side`: `|` for changed lines, `<` for removed ones and
+    /// `>` for inserted ones.
+    SideBySide,
+}
+
+/// Diff turning `old` into `new`, each line with its sign: ` ` for equal
+/// lines, `-` for removed ones and `+` for inserted ones.
+pub(crate) fn diff<'a>(
+    algorithm: DiffAlgorithm,
+    old: &[&'a str],
+    new: &[&'a str],
+) -> Vec<(char, &'a str)> {
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    match algorithm {
+        DiffAlgorithm::Myers => myers(old, new, &mut lines),
+        DiffAlgorithm::Patience => patience(old, new, &mut lines),
+    }
+
+    lines
+}
+
+/// Ranges of `lines` in hunks, which are changes with at most `context` equal
+/// lines around them. Hunks close to each other are merged.
+pub(crate) fn hunks(lines: &[(char, &str)], context: usize) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = vec![];
+    for (index, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (sign, _))| *sign != ' ')
+    {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+
+    hunks
+}
+
+/// Render `lines` of a diff in `style`. Differing words within changed lines
+/// are highlighted if `highlight` is true, see [`highlight_words`].
+pub(crate) fn render(lines: &[(char, &str)], style: DiffStyle, highlight: bool) -> Vec<String> {
+    let highlight_pair = |old: &str, new: &str| {
+        if highlight {
+            highlight_words(old, new)
+        } else {
+            (old.to_string(), new.to_string())
+        }
+    };
+
+    match style {
+        DiffStyle::Unified => {
+            let mut rendered = vec![];
+            for change in changes(lines) {
+                let (removed, inserted) = match change {
+                    Change::Equal(line) => {
+                        rendered.push(format!("  {}", line));
+                        continue;
+                    }
+                    Change::Replace(removed, inserted) => (removed, inserted),
+                };
+                let mut removed: Vec<_> = removed.iter().map(|line| line.to_string()).collect();
+                let mut inserted: Vec<_> = inserted.iter().map(|line| line.to_string()).collect();
+                for (old, new) in removed.iter_mut().zip(inserted.iter_mut()) {
+                    (*old, *new) = highlight_pair(old, new);
+                }
+                rendered.extend(removed.iter().map(|line| format!("- {}", line)));
+                rendered.extend(inserted.iter().map(|line| format!("+ {}", line)));
+            }
+            rendered
+        }
+        DiffStyle::SideBySide => side_by_side(lines, highlight_pair),
+    }
+}
+
+/// Consecutive lines of a diff.
+enum Change<'a> {
+    Equal(&'a str),
+    /// Removed lines, and the inserted lines following them.
+    Replace(Vec<&'a str>, Vec<&'a str>),
+}
+
+fn changes<'a>(lines: &[(char, &'a str)]) -> Vec<Change<'a>> {
+    let mut changes = vec![];
+    let mut index = 0;
+    while index < lines.len() {
+        let (sign, line) = lines[index];
+        if sign == ' ' {
+            changes.push(Change::Equal(line));
+            index += 1;
+            continue;
+        }
+
+        let removed: Vec<_> = lines[index..]
+            .iter()
+            .take_while(|(sign, _)| *sign == '-')
+            .map(|(_, line)| *line)
+            .collect();
+        let inserted: Vec<_> = lines[index + removed.len()..]
+            .iter()
+            .take_while(|(sign, _)| *sign == '+')
+            .map(|(_, line)| *line)
+            .collect();
+        index += removed.len() + inserted.len();
+        changes.push(Change::Replace(removed, inserted));
+    }
+
+    changes
+}
+
+/// Render `lines` of a diff in two columns, see [`DiffStyle::SideBySide`].
+/// Removed lines are paired with the inserted lines following them.
+fn side_by_side<F>(lines: &[(char, &str)], highlight_pair: F) -> Vec<String>
+where
+    F: Fn(&str, &str) -> (String, String),
+{
+    let mut rows: Vec<(&str, char, &str)> = vec![];
+    for change in changes(lines) {
+        match change {
+            Change::Equal(line) => rows.push((line, ' ', line)),
+            Change::Replace(removed, inserted) => {
+                for row in 0..removed.len().max(inserted.len()) {
+                    rows.push(match (removed.get(row), inserted.get(row)) {
+                        (Some(left), Some(right)) => (left, '|', right),
+                        (Some(left), None) => (left, '<', ""),
+                        (None, right) => ("", '>', right.copied().unwrap_or_default()),
+                    });
+                }
+            }
+        }
+    }
+
+    let width = rows
+        .iter()
+        .map(|(left, _, _)| left.width())
+        .max()
+        .unwrap_or_default()
+        .min(SIDE_BY_SIDE_MAX_WIDTH);
+    rows.into_iter()
+        .map(|(left, mark, right)| {
+            // padding is computed before highlighting adds escape sequences
+            let padding = " ".repeat(width.saturating_sub(left.width()));
+            let (left, right) = match mark {
+                '|' => highlight_pair(left, right),
+                _ => (left.to_string(), right.to_string()),
+            };
+            format!("{}{} {} {}", left, padding, mark, right)
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Wrap words differing between `old` and `new` with ANSI reverse video
+/// escape sequences, so a changed number in a long row stands out.
+fn highlight_words(old: &str, new: &str) -> (String, String) {
+    let old_words = words(old);
+    let new_words = words(new);
+    let mut old_highlighted = String::with_capacity(old.len());
+    let mut new_highlighted = String::with_capacity(new.len());
+    for (sign, word) in diff(DiffAlgorithm::Myers, &old_words, &new_words) {
+        let highlighted = format!("{}{}{}", HIGHLIGHT_START, word, HIGHLIGHT_END);
+        match sign {
+            '-' => old_highlighted.push_str(&highlighted),
+            '+' => new_highlighted.push_str(&highlighted),
+            _ => {
+                old_highlighted.push_str(word);
+                new_highlighted.push_str(word);
+            }
+        }
+    }
+
+    // adjacent highlighted words are merged
+    let merged = format!("{}{}", HIGHLIGHT_END, HIGHLIGHT_START);
+    (
+        old_highlighted.replace(&merged, ""),
+        new_highlighted.replace(&merged, ""),
+    )
+}
+
+/// Split `line` into words, which are runs of alphanumeric characters (plus
+/// `_` and `.` to keep numbers whole), runs of whitespaces, and single other
+/// characters.
+fn words(line: &str) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            1
+        } else if c.is_whitespace() {
+            2
+        } else {
+            0
+        }
+    };
+
+    let mut words = vec![];
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let next_class = chars.peek().map(|(_, next)| class(*next));
+        if class(c) == 0 || next_class != Some(class(c)) {
+            let end = index + c.len_utf8();
+            words.push(&line[start..end]);
+            start = end;
+        }
+    }
+
+    words
+}
+
+fn myers<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<(char, &'a str)>) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    // v[k + max] is the furthest x on diagonal k
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // v of diagonals -d..=d before each round d, to backtrack the path
+    let mut trace = vec![];
+    'rounds: for d in 0..=max {
+        trace.push(v[(max - d) as usize..=(max + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                break 'rounds;
+            }
+        }
+    }
+
+    let (mut x, mut y) = (n, m);
+    let mut reversed = vec![];
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d) as usize];
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { at(prev_k) };
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            reversed.push((' ', old[x as usize]));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                reversed.push(('+', new[y as usize]));
+            } else {
+                x -= 1;
+                reversed.push(('-', old[x as usize]));
+            }
+        }
+    }
+    lines.extend(reversed.into_iter().rev());
+}
+
+fn patience<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<(char, &'a str)>) {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    lines.extend(old[..prefix].iter().map(|line| (' ', *line)));
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let anchors = unique_common_lines(old_middle, new_middle);
+    if anchors.is_empty() {
+        myers(old_middle, new_middle, lines);
+    } else {
+        let (mut old_start, mut new_start) = (0, 0);
+        for (old_index, new_index) in anchors {
+            patience(
+                &old_middle[old_start..old_index],
+                &new_middle[new_start..new_index],
+                lines,
+            );
+            lines.push((' ', old_middle[old_index]));
+            old_start = old_index + 1;
+            new_start = new_index + 1;
+        }
+        patience(&old_middle[old_start..], &new_middle[new_start..], lines);
+    }
+
+    lines.extend(old[old.len() - suffix..].iter().map(|line| (' ', *line)));
+}
+
+/// Positions of lines appearing exactly once in both `old` and `new`, as the
+/// longest sequence in the same order on both sides.
+fn unique_common_lines(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    // line -> (count in old, position in old, count in new, position in new)
+    let mut occurrences: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (index, line) in old.iter().enumerate() {
+        let entry = occurrences.entry(line).or_default();
+        entry.0 += 1;
+        entry.1 = index;
+    }
+    for (index, line) in new.iter().enumerate() {
+        if let Some(entry) = occurrences.get_mut(line) {
+            entry.2 += 1;
+            entry.3 = index;
+        }
+    }
+    let mut pairs: Vec<_> = occurrences
+        .into_values()
+        .filter(|(old_count, _, new_count, _)| *old_count == 1 && *new_count == 1)
+        .map(|(_, old_index, _, new_index)| (old_index, new_index))
+        .collect();
+    pairs.sort_unstable();
+
+    // longest increasing subsequence of new positions, by patience sorting
+    let mut piles: Vec<usize> = vec![];
+    let mut previous = vec![None; pairs.len()];
+    for (index, (_, new_index)) in pairs.iter().enumerate() {
+        let pile = piles.partition_point(|top| pairs[*top].1 < *new_index);
+        if pile > 0 {
+            previous[index] = Some(piles[pile - 1]);
+        }
+        if pile == piles.len() {
+            piles.push(index);
+        } else {
+            piles[pile] = index;
+        }
+    }
+    let mut sequence = vec![];
+    let mut current = piles.last().copied();
+    while let Some(index) = current {
+        sequence.push(pairs[index]);
+        current = previous[index];
+    }
+    sequence.reverse();
+
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.lines().collect()
+    }
+
+    #[test]
+    fn diff_lines() {
+        for algorithm in [DiffAlgorithm::Myers, DiffAlgorithm::Patience] {
+            let diffed = diff(algorithm, &lines("a\nb\nc"), &lines("a\nx\nc\nd"));
+            assert_eq!(
+                diffed,
+                vec![(' ', "a"), ('-', "b"), ('+', "x"), (' ', "c"), ('+', "d")],
+                "{:?}",
+                algorithm
+            );
+            assert!(diff(algorithm, &lines("a\nb"), &lines("a\nb"))
+                .iter()
+                .all(|(sign, _)| *sign == ' '));
+        }
+    }
+
+    #[test]
+    fn patience_anchors_unique_lines() {
+        let old = lines("}\nfoo\n}\nbar");
+        let new = lines("}\nbar\n}\nfoo");
+        let diffed = diff(DiffAlgorithm::Patience, &old, &new);
+        let equal: Vec<_> = diffed
+            .iter()
+            .filter(|(sign, _)| *sign == ' ')
+            .map(|(_, line)| *line)
+            .collect();
+        assert_eq!(equal.len(), 2, "{:?}", diffed);
+    }
+
+    #[test]
+    fn hunks_with_context() {
+        let mut lines = vec![(' ', "a"); 20];
+        lines[3] = ('-', "b");
+        lines[5] = ('+', "c");
+        lines[15] = ('-', "d");
+
+        // changes within the context of each other are merged
+        assert_eq!(hunks(&lines, 1), vec![2..7, 14..17]);
+        assert_eq!(hunks(&lines, 0), vec![3..4, 5..6, 15..16]);
+        assert_eq!(hunks(&lines, 5), vec![0..20]);
+        assert!(hunks(&[(' ', "a")], 3).is_empty());
+    }
+
+    #[test]
+    fn render_unified() {
+        let lines = [(' ', "a"), ('-', "b c"), ('+', "b d")];
+        assert_eq!(
+            render(&lines, DiffStyle::Unified, false),
+            vec!["  a", "- b c", "+ b d"]
+        );
+        let highlighted = render(&lines, DiffStyle::Unified, true);
+        assert_eq!(
+            highlighted[1],
+            format!("- b {}c{}", HIGHLIGHT_START, HIGHLIGHT_END)
+        );
+    }
+}
@@ -0,0 +1,760 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Discovery of environments and cases under [`Config::case_dir`].
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use serde::Deserialize;
+use tokio::fs::read_dir;
+use tokio::process::Command;
+use walkdir::WalkDir;
+
+use crate::case::TestCase;
+use crate::config::{CaseOrder, Config};
+use crate::error::{Result, SqlnessError};
+use crate::glob;
+
+/// Name of the environment covering the whole [`Config::case_dir`], see
+/// [`Config::single_env`].
+pub const SINGLE_ENV: &str = ".";
+
+/// Case files named `<case>.setup.<ext>` and `<case>.teardown.<ext>` are
+/// fixtures run before and after `<case>`, instead of cases.
+pub(crate) const SETUP_SUFFIX: &str = "setup";
+pub(crate) const TEARDOWN_SUFFIX: &str = "teardown";
+
+/// File under [`Config::case_dir`] listing glob patterns of paths excluded from
+/// discovery, one per line, see [`Config::exclude_patterns`].
+pub const IGNORE_FILE: &str = ".sqlnessignore";
+
+/// Optional file under [`Config::case_dir`] declaring environments, so other
+/// directories (e.g. `common/`) aren't taken as environments, like
+///
+/// ```toml
+/// [[environment]]
+/// name = "standalone"
+///
+/// [[environment]]
+/// name = "cluster"
+/// # directory of cases relative to the case dir, default to the name
+/// dir = "distributed/cluster"
+/// # settings passed to `EnvController::start`, relative to the case dir,
+/// # default to `env_config_file` under the directory
+/// config = "distributed/cluster.toml"
+/// ```
+pub const MANIFEST_FILE: &str = "environments.toml";
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default, rename = "environment")]
+    environments: Vec<ManifestEnv>,
+}
+
+/// An environment declared in [`MANIFEST_FILE`].
+#[derive(Debug, Deserialize)]
+struct ManifestEnv {
+    name: String,
+    dir: Option<String>,
+    config: Option<String>,
+}
+
+impl ManifestEnv {
+    fn dir(&self, config: &Config) -> PathBuf {
+        Path::new(&config.case_dir).join(self.dir.as_deref().unwrap_or(&self.name))
+    }
+}
+
+/// Environments declared in [`MANIFEST_FILE`], `None` if it doesn't exist.
+async fn load_manifest(config: &Config) -> Result<Option<Vec<ManifestEnv>>> {
+    let path = Path::new(&config.case_dir).join(MANIFEST_FILE);
+    if config.single_env || !path.exists() {
+        return Ok(None);
+    }
+
+    let content = tokio::fs::read(&path)
+        .await
+        .map_err(|e| SqlnessError::ReadPath {
+            source: e,
+            path: path.clone(),
+        })?;
+    let manifest: Manifest = toml::from_slice(&content).map_err(|e| SqlnessError::ParseToml {
+        source: e,
+        file: path.clone(),
+    })?;
+    for (i, env) in manifest.environments.iter().enumerate() {
+        if manifest.environments[..i]
+            .iter()
+            .any(|other| other.name == env.name)
+        {
+            return Err(SqlnessError::InvalidConfig {
+                field: MANIFEST_FILE.to_string(),
+                reason: format!("environment `{}` is declared twice", env.name),
+            });
+        }
+    }
+
+    Ok(Some(manifest.environments))
+}
+
+/// Directory of cases of environment `env`.
+pub async fn env_dir(config: &Config, env: &str) -> Result<PathBuf> {
+    let manifest = load_manifest(config).await?;
+
+    Ok(declared_env_dir(config, manifest.as_deref(), env))
+}
+
+/// Directory of cases of environment `env`, which may be declared in
+/// `manifest`.
+fn declared_env_dir(config: &Config, manifest: Option<&[ManifestEnv]>, env: &str) -> PathBuf {
+    let declared = manifest
+        .into_iter()
+        .flatten()
+        .find(|declared| declared.name == env);
+
+    match declared {
+        Some(declared) => declared.dir(config),
+        None => Path::new(&config.case_dir).join(env),
+    }
+}
+
+/// Lexical directories of environments with [`Config::envs`] settings,
+/// resolved once for all cases parsed in a run, see
+/// [`EnvSettings::directives`].
+///
+/// [`EnvSettings::directives`]: crate::EnvSettings::directives
+#[derive(Debug, Default)]
+pub(crate) struct EnvDirs(Vec<(String, PathBuf)>);
+
+impl EnvDirs {
+    pub(crate) async fn resolve(config: &Config) -> Result<Self> {
+        if config.envs.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let manifest = load_manifest(config).await?;
+        let dirs = config
+            .envs
+            .keys()
+            .map(|env| {
+                let dir = declared_env_dir(config, manifest.as_deref(), env);
+                (env.clone(), lexical(&dir))
+            })
+            .collect();
+
+        Ok(Self(dirs))
+    }
+
+    /// Environments whose directories contain the case at `path`.
+    pub(crate) fn envs_of<'a>(&'a self, path: &Path) -> impl Iterator<Item = &'a str> {
+        let path = lexical(path);
+        self.0
+            .iter()
+            .filter(move |(_, dir)| path.starts_with(dir))
+            .map(|(env, _)| env.as_str())
+    }
+}
+
+/// Path of the config file of environment `env`, which may not exist. It's
+/// [`Config::env_config_file`] under [`env_dir`], unless declared otherwise
+/// in [`MANIFEST_FILE`].
+pub async fn env_config_path(config: &Config, env: &str) -> Result<PathBuf> {
+    let manifest = load_manifest(config).await?;
+    let declared = manifest
+        .iter()
+        .flatten()
+        .find(|declared| declared.name == env);
+
+    Ok(match declared {
+        Some(ManifestEnv {
+            config: Some(env_config),
+            ..
+        }) => Path::new(&config.case_dir).join(env_config),
+        Some(declared) => declared.dir(config).join(&config.env_config_file),
+        None => Path::new(&config.case_dir)
+            .join(env)
+            .join(&config.env_config_file),
+    })
+}
+
+/// Environment containing the case at `path`, the one with the deepest
+/// directory if environments are declared in [`MANIFEST_FILE`], otherwise the
+/// first directory under [`Config::case_dir`].
+pub async fn env_of_case(config: &Config, path: &Path) -> Result<Option<String>> {
+    if config.single_env {
+        return Ok(Some(SINGLE_ENV.to_string()));
+    }
+
+    if let Some(manifest) = load_manifest(config).await? {
+        let path = lexical(path);
+        return Ok(manifest
+            .iter()
+            .map(|declared| (declared, lexical(&declared.dir(config))))
+            .filter(|(_, dir)| path.starts_with(dir))
+            .max_by_key(|(_, dir)| dir.components().count())
+            .map(|(declared, _)| declared.name.clone()));
+    }
+
+    Ok(path
+        .strip_prefix(&config.case_dir)
+        .ok()
+        .and_then(|relative| relative.components().next())
+        .and_then(|env| env.as_os_str().to_str())
+        .map(str::to_string))
+}
+
+/// Collect environment names, which are declared in [`MANIFEST_FILE`], or
+/// the first-level directories of [`Config::case_dir`], or [`SINGLE_ENV`] if
+/// [`Config::single_env`] is set.
+pub async fn collect_envs(config: &Config) -> Result<Vec<String>> {
+    if config.single_env {
+        return Ok(vec![SINGLE_ENV.to_string()]);
+    }
+    if let Some(manifest) = load_manifest(config).await? {
+        return Ok(manifest.into_iter().map(|declared| declared.name).collect());
+    }
+
+    let ignore = Ignore::load(config).await?;
+    let mut dirs = read_dir(&config.case_dir).await?;
+    let mut result = vec![];
+
+    while let Some(dir) = dirs.next_entry().await? {
+        if dir.file_type().await?.is_dir() && !ignore.is_ignored(config, &dir.path(), true) {
+            let file_name = dir.file_name().to_str().unwrap().to_string();
+            result.push(file_name);
+        }
+    }
+    result.sort_by(|a, b| compare_names(config.case_order, a, b));
+
+    Ok(result)
+}
+
+/// Collect paths of cases under environment `env`, in the order they run,
+/// see [`TestCase::dependencies`] and [`Config::priority_rules`]. Setup and
+/// teardown fixtures of cases are excluded.
+///
+/// Returned paths have no extension, e.g. `case_dir/env/dml/basic`.
+pub async fn collect_case_paths(config: &Config, env: &str) -> Result<Vec<PathBuf>> {
+    let env_dirs = EnvDirs::resolve(config).await?;
+    let cases = collect_cases(config, env, &env_dirs).await?;
+
+    Ok(cases.into_iter().map(|case| case.path).collect())
+}
+
+/// A case found by [`collect_cases`].
+pub(crate) struct CollectedCase {
+    /// Path without extension, see [`collect_case_paths`].
+    pub path: PathBuf,
+    /// The case parsed during discovery, so it's parsed only once per run.
+    pub case: Result<TestCase>,
+}
+
+/// Like [`collect_case_paths`], along with the parsed cases, and with
+/// environment directories resolved once for all environments of a run.
+pub(crate) async fn collect_cases(
+    config: &Config,
+    env: &str,
+    env_dirs: &EnvDirs,
+) -> Result<Vec<CollectedCase>> {
+    let config = &config.for_env(env);
+    let root = env_dir(config, env).await?;
+
+    let test_case_extension = config.test_case_extension.as_str();
+    let ignore = Ignore::load(config).await?;
+    // with symlinks followed, directories linking to their ancestors are
+    // reported as errors by `WalkDir` instead of being entered, and skipped
+    let mut cases: Vec<_> = WalkDir::new(&root)
+        .follow_links(config.follow_symlinks)
+        .into_iter()
+        .filter_entry(|entry| {
+            // the root is checked along with its environment
+            entry.depth() == 0
+                || !ignore.is_ignored(config, entry.path(), entry.file_type().is_dir())
+        })
+        .filter_map(|entry| {
+            entry
+                .map_or(None, |entry| Some(entry.path().to_path_buf()))
+                .filter(|path| {
+                    path.extension()
+                        .map(|ext| ext == test_case_extension)
+                        .unwrap_or(false)
+                })
+        })
+        .map(|path| path.with_extension(""))
+        .filter(|path| !is_fixture(path))
+        .filter(|path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_str()
+                .unwrap_or_default()
+                .contains(&config.test_filter)
+        })
+        .collect();
+
+    if let Some(reference) = &config.changed_since {
+        let changed = changed_files(reference).await?;
+        let env_config = env_config_path(config, env).await?;
+        let env_changed = canonical(&env_config)
+            .map(|env_config| changed.contains(&env_config))
+            .unwrap_or(false);
+        if !env_changed {
+            cases.retain(|case| is_changed(case, &changed));
+        }
+    }
+
+    // sort the cases in an os-independent order.
+    cases.sort_by(|a, b| {
+        compare_names(
+            config.case_order,
+            &a.to_string_lossy(),
+            &b.to_string_lossy(),
+        )
+    });
+    // cases reachable via multiple paths (e.g. symlinks to a shared directory)
+    // run once, under the first path
+    let mut files = HashSet::with_capacity(cases.len());
+    cases.retain(|case| {
+        let file = case.with_extension(test_case_extension);
+        files.insert(canonical(&file).unwrap_or(file))
+    });
+
+    let mut prioritized = Vec::with_capacity(cases.len());
+    let mut parsed = HashMap::with_capacity(cases.len());
+    for path in cases {
+        let case =
+            TestCase::from_file_with(path.with_extension(test_case_extension), config, env_dirs)
+                .await;
+        let priority = case
+            .as_ref()
+            .ok()
+            .and_then(TestCase::priority)
+            .unwrap_or_else(|| rule_priority(config, &path));
+        let dependencies = case
+            .as_ref()
+            .map(|case| case.dependencies().iter().map(|p| lexical(p)).collect())
+            .unwrap_or_default();
+        prioritized.push((priority, path.clone(), dependencies));
+        parsed.insert(path, case);
+    }
+    // stable sort keeps lexicographic order for the same priority
+    prioritized.sort_by_key(|(priority, _, _)| Reverse(*priority));
+
+    let ordered = order_by_dependencies(
+        prioritized
+            .into_iter()
+            .map(|(_, path, dependencies)| (path, dependencies))
+            .collect(),
+    )?;
+
+    Ok(ordered
+        .into_iter()
+        .map(|path| {
+            // ordered paths are exactly the parsed ones
+            let case = parsed.remove(&path).unwrap();
+            CollectedCase { path, case }
+        })
+        .collect())
+}
+
+/// Rules excluding paths from discovery, see [`Config::exclude_patterns`].
+struct Ignore {
+    patterns: Vec<String>,
+    include_hidden: bool,
+    /// See [`Config::shared_dirs`].
+    shared_dirs: Vec<PathBuf>,
+}
+
+impl Ignore {
+    /// Collect patterns of config and [`IGNORE_FILE`] under [`Config::case_dir`].
+    async fn load(config: &Config) -> Result<Self> {
+        let mut patterns = config.exclude_patterns.clone();
+        let ignore_file = Path::new(&config.case_dir).join(IGNORE_FILE);
+        if ignore_file.exists() {
+            let content = tokio::fs::read_to_string(&ignore_file).await.map_err(|e| {
+                SqlnessError::ReadPath {
+                    source: e,
+                    path: ignore_file.clone(),
+                }
+            })?;
+            patterns.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string),
+            );
+        }
+
+        Ok(Self {
+            patterns,
+            include_hidden: config.include_hidden,
+            shared_dirs: config
+                .shared_dirs
+                .iter()
+                .map(|dir| lexical(&Path::new(&config.case_dir).join(dir)))
+                .collect(),
+        })
+    }
+
+    /// Whether the file or directory at `path` is excluded.
+    fn is_ignored(&self, config: &Config, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&config.case_dir).unwrap_or(path);
+        let hidden = relative
+            .components()
+            .any(|component| matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.')));
+        if hidden && !self.include_hidden {
+            return true;
+        }
+        let path = lexical(path);
+        if self.shared_dirs.iter().any(|dir| path.starts_with(dir)) {
+            return true;
+        }
+
+        let mut relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        // so `build/` matches the directory itself
+        if is_dir {
+            relative.push('/');
+        }
+        self.patterns
+            .iter()
+            .any(|pattern| glob::matches(pattern, &relative))
+    }
+}
+
+/// Compare names of cases or environments case-insensitively, see
+/// [`Config::case_order`].
+fn compare_names(order: CaseOrder, a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.to_lowercase(), b.to_lowercase());
+    match order {
+        CaseOrder::Lexicographic => a.cmp(&b),
+        CaseOrder::Natural => compare_natural(&a, &b).then_with(|| a.cmp(&b)),
+    }
+}
+
+/// Compare runs of digits by their numeric values and other characters one by
+/// one, so `case2` comes before `case10`.
+fn compare_natural(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (x, y) = match (a.chars().next(), b.chars().next()) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return a.len().cmp(&b.len()),
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let a_digits = a[..a_end].trim_start_matches('0');
+            let b_digits = b[..b_end].trim_start_matches('0');
+            let ordering = a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (&a[a_end..], &b[b_end..]);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+/// Priority of case at `path` from [`Config::priority_rules`].
+fn rule_priority(config: &Config, path: &Path) -> i32 {
+    let relative_path = relative_case_path(config, path);
+    config
+        .priority_rules
+        .iter()
+        .rev()
+        .find(|rule| glob::matches(&rule.pattern, &relative_path))
+        .map(|rule| rule.priority)
+        .unwrap_or_default()
+}
+
+/// Whether case at `path` matches [`Config::serial_patterns`].
+pub(crate) fn is_serial(config: &Config, path: &Path) -> bool {
+    let relative_path = relative_case_path(config, path);
+    config
+        .serial_patterns
+        .iter()
+        .any(|pattern| glob::matches(pattern, &relative_path))
+}
+
+/// Reorder `cases` so every case runs after its dependencies, while keeping
+/// the original order as much as possible. Dependencies not in `cases` are
+/// ignored.
+fn order_by_dependencies(cases: Vec<(PathBuf, Vec<PathBuf>)>) -> Result<Vec<PathBuf>> {
+    let mut pending: Vec<_> = cases
+        .into_iter()
+        .map(|(path, dependencies)| (lexical(&path), path, dependencies))
+        .collect();
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|(_, _, dependencies)| {
+            dependencies
+                .iter()
+                .all(|dependency| !pending.iter().any(|(key, _, _)| key == dependency))
+        });
+        match ready {
+            Some(index) => ordered.push(pending.remove(index).1),
+            None => {
+                return Err(SqlnessError::DependencyCycle {
+                    cases: pending.into_iter().map(|(_, path, _)| path).collect(),
+                })
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+/// Normalize `.` and `..` in `path` without touching the file system.
+pub(crate) fn lexical(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Path of the file of case at `path` relative to [`Config::case_dir`], `/`
+/// separated.
+pub(crate) fn relative_case_path(config: &Config, path: &Path) -> String {
+    let case_path = path.with_extension(&config.test_case_extension);
+    let relative = case_path
+        .strip_prefix(&config.case_dir)
+        .unwrap_or(&case_path);
+
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_fixture(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext == SETUP_SUFFIX || ext == TEARDOWN_SUFFIX)
+        .unwrap_or(false)
+}
+
+/// Files changed since git `reference`, including uncommitted and untracked
+/// ones. Paths are canonicalized.
+async fn changed_files(reference: &str) -> Result<Vec<PathBuf>> {
+    let root = git(&["rev-parse", "--show-toplevel"]).await?;
+    let root = PathBuf::from(root.trim());
+    let mut files = git(&["diff", "--name-only", reference, "--"]).await?;
+    // paths are relative to the current directory unless run from the root,
+    // which also lists untracked files outside of the current directory
+    let root_arg = root.to_string_lossy();
+    files.push_str(
+        &git(&[
+            "-C",
+            &root_arg,
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+        ])
+        .await?,
+    );
+
+    Ok(files
+        .lines()
+        .filter_map(|file| canonical(&root.join(file)))
+        .collect())
+}
+
+async fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).output().await?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "git {} failed, stderr: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether any file of `case` (e.g. `case.sql`, `case.result`,
+/// `case.setup.sql`) is changed. Deleted files are ignored.
+fn is_changed(case: &Path, changed: &[PathBuf]) -> bool {
+    let case = match case.parent().and_then(canonical) {
+        Some(dir) => dir.join(case.file_name().unwrap_or_default()),
+        None => return false,
+    };
+    let mut prefix = case.into_os_string();
+    prefix.push(".");
+    let prefix = prefix.to_string_lossy().into_owned();
+
+    changed
+        .iter()
+        .any(|file| file.to_string_lossy().starts_with(&prefix))
+}
+
+fn canonical(path: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec![
+            "case10", "case2", "case02", "case1", "case", "case1a", "case1b", "a10b2", "a10b10",
+            "b", "a",
+        ];
+        names.sort_by(|a, b| compare_natural(a, b).then_with(|| a.cmp(b)));
+        assert_eq!(
+            names,
+            vec![
+                "a", "a10b2", "a10b10", "b", "case", "case1", "case1a", "case1b", "case02",
+                "case2", "case10",
+            ]
+        );
+    }
+
+    #[test]
+    fn natural_order_edge_cases() {
+        // leading zeros don't count
+        assert_eq!(compare_natural("x007", "x7"), Ordering::Equal);
+        assert_eq!(compare_natural("x0", "x00"), Ordering::Equal);
+        // numbers longer than any integer type
+        assert_eq!(
+            compare_natural("x99999999999999999999999", "x100000000000000000000000"),
+            Ordering::Less
+        );
+        // a prefix comes first
+        assert_eq!(compare_natural("x1", "x1.sql"), Ordering::Less);
+        assert_eq!(compare_natural("", ""), Ordering::Equal);
+        assert_eq!(compare_natural("é2", "é10"), Ordering::Less);
+    }
+
+    fn case(path: &str, dependencies: &[&str]) -> (PathBuf, Vec<PathBuf>) {
+        (
+            PathBuf::from(path),
+            dependencies.iter().map(PathBuf::from).collect(),
+        )
+    }
+
+    #[test]
+    fn dependencies_first() {
+        let ordered = order_by_dependencies(vec![
+            case("a", &["c"]),
+            case("b", &[]),
+            case("c", &["b", "missing"]),
+            case("d", &[]),
+        ])
+        .unwrap();
+        assert_eq!(
+            ordered,
+            vec![
+                PathBuf::from("b"),
+                PathBuf::from("c"),
+                PathBuf::from("a"),
+                PathBuf::from("d")
+            ]
+        );
+    }
+
+    #[test]
+    fn dependency_cycles() {
+        let result = order_by_dependencies(vec![
+            case("a", &[]),
+            case("b", &["c"]),
+            case("c", &["d"]),
+            case("d", &["b"]),
+        ]);
+        match result {
+            Err(SqlnessError::DependencyCycle { cases }) => assert_eq!(
+                cases,
+                vec![PathBuf::from("b"), PathBuf::from("c"), PathBuf::from("d")]
+            ),
+            other => panic!("expect a dependency cycle, got {:?}", other),
+        }
+
+        let result = order_by_dependencies(vec![case("./a", &["a"])]);
+        assert!(matches!(result, Err(SqlnessError::DependencyCycle { .. })));
+    }
+
+    #[tokio::test]
+    async fn env_dirs_of_cases() {
+        let dir = std::env::temp_dir().join(format!("sqlness-env-dirs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(MANIFEST_FILE),
+            "[[environment]]\nname = \"local\"\ndir = \"nested/local\"\n",
+        )
+        .unwrap();
+        let mut config = crate::ConfigBuilder::default()
+            .case_dir(dir.display().to_string())
+            .build()
+            .unwrap();
+        for env in ["local", "remote"] {
+            config.envs.insert(env.to_string(), Default::default());
+        }
+
+        let env_dirs = EnvDirs::resolve(&config).await.unwrap();
+        let envs_of = |path: PathBuf| env_dirs.envs_of(&path).collect::<Vec<_>>();
+        assert_eq!(envs_of(dir.join("nested/local/a/case")), vec!["local"]);
+        assert_eq!(envs_of(dir.join("nested/./local/case")), vec!["local"]);
+        assert_eq!(envs_of(dir.join("remote/case")), vec!["remote"]);
+        assert!(envs_of(dir.join("local/case")).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cases_parsed_once() {
+        let dir = std::env::temp_dir().join(format!("sqlness-collect-{}", std::process::id()));
+        let env_dir = dir.join("env");
+        std::fs::create_dir_all(&env_dir).unwrap();
+        std::fs::write(
+            env_dir.join("a.sql"),
+            "-- SQLNESS DEPENDS_ON b\nSELECT 1;\n",
+        )
+        .unwrap();
+        std::fs::write(env_dir.join("b.sql"), "SELECT 2;\n").unwrap();
+        std::fs::write(
+            env_dir.join("c.sql"),
+            "-- SQLNESS PRIORITY high\nSELECT 3;\n",
+        )
+        .unwrap();
+        let config = crate::ConfigBuilder::default()
+            .case_dir(dir.display().to_string())
+            .build()
+            .unwrap();
+
+        let cases = collect_cases(&config, "env", &EnvDirs::default())
+            .await
+            .unwrap();
+        let paths: Vec<_> = cases.iter().map(|case| case.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![env_dir.join("b"), env_dir.join("a"), env_dir.join("c")]
+        );
+        assert_eq!(cases[1].case.as_ref().unwrap().dependencies().len(), 1);
+        assert!(cases[2].case.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Case trees embedded into binaries, see [`embed_cases!`].
+//!
+//! [`embed_cases!`]: crate::embed_cases
+
+use std::path::{Path, PathBuf};
+
+use tokio::fs::{create_dir_all, write};
+
+use crate::error::Result;
+use crate::hash::random_hex;
+
+/// Files of a case directory embedded by [`embed_cases!`], as paths relative
+/// to the directory (`/` separated) and contents.
+///
+/// Cases are run from files, so embedded ones are extracted to a directory
+/// first, which is then used as [`Config::case_dir`]:
+///
+/// ```rust, ignore, no_run
+/// static CASES: EmbeddedCases = sqlness::embed_cases!("tests/cases");
+///
+/// let case_dir = CASES.extract_to_temp_dir().await?;
+/// let config = ConfigBuilder::default()
+///     .case_dir(case_dir.display().to_string())
+///     .build()?;
+/// Runner::new_with_config(config, env).await?.run().await?;
+/// ```
+///
+/// [`embed_cases!`]: crate::embed_cases
+/// [`Config::case_dir`]: crate::Config::case_dir
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedCases {
+    files: &'static [(&'static str, &'static [u8])],
+}
+
+impl EmbeddedCases {
+    pub const fn new(files: &'static [(&'static str, &'static [u8])]) -> Self {
+        Self { files }
+    }
+
+    pub fn files(&self) -> &'static [(&'static str, &'static [u8])] {
+        self.files
+    }
+
+    /// Write embedded files into `dir`, overwriting existing ones.
+    pub async fn extract<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        for (relative, content) in self.files {
+            let path = dir.as_ref().join(relative);
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).await?;
+            }
+            write(&path, content).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write embedded files into a new directory under the temporary
+    /// directory, return its path. It's left for inspecting outputs after the
+    /// run.
+    pub async fn extract_to_temp_dir(&self) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("sqlness-{}", random_hex(16)));
+        self.extract(&dir).await?;
+
+        Ok(dir)
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SqlnessError};
+
+/// Unicode code points (`0` if unmapped) of GBK double-byte sequences, as
+/// little-endian `u16`s ordered by lead byte in `0x81..=0xFE` and then trail
+/// byte in `0x40..=0xFE` except `0x7F`. Generated by Python's `gbk` codec:
+///
+/// ```python
+/// for lead in range(0x81, 0xFF):
+///     for trail in range(0x40, 0xFF):
+///         if trail != 0x7F:
+///             try:
+///                 c = ord(bytes([lead, trail]).decode("gbk"))
+///             except UnicodeDecodeError:
+///                 c = 0
+///             out += c.to_bytes(2, "little")
+/// ```
+const GBK_TABLE: &[u8; 126 * 190 * 2] = include_bytes!("encoding/gbk.bin");
+
+/// Encoding of case and result files, see [`Config::file_encoding`]. Texts
+/// are converted to UTF-8 when read, and back when written.
+///
+/// [`Config::file_encoding`]: crate::Config::file_encoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    #[serde(alias = "utf-8")]
+    Utf8,
+    /// ISO-8859-1. Characters out of its range are written as `?`.
+    #[serde(alias = "latin-1", alias = "iso-8859-1")]
+    Latin1,
+    /// GBK (code page 936) for Simplified Chinese, ASCII compatible.
+    /// Characters out of its range are written as `?`.
+    #[serde(alias = "cp936")]
+    Gbk,
+}
+
+impl Encoding {
+    pub fn decode(&self, bytes: Vec<u8>) -> Result<String> {
+        match self {
+            Encoding::Utf8 => Ok(String::from_utf8(bytes)?),
+            // code points of Latin-1 are the same as the first 256 of Unicode
+            Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+            Encoding::Gbk => decode_gbk(&bytes),
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect(),
+            Encoding::Gbk => encode_gbk(text),
+        }
+    }
+}
+
+/// Index of a double-byte sequence in [`GBK_TABLE`].
+fn gbk_index(lead: u8, trail: u8) -> Option<usize> {
+    if !(0x81..=0xFE).contains(&lead) || !(0x40..=0xFE).contains(&trail) || trail == 0x7F {
+        return None;
+    }
+    let trail = if trail > 0x7F { trail - 1 } else { trail };
+
+    Some((lead - 0x81) as usize * 190 + (trail - 0x40) as usize)
+}
+
+fn gbk_char(index: usize) -> Option<char> {
+    let code = u16::from_le_bytes([GBK_TABLE[2 * index], GBK_TABLE[2 * index + 1]]);
+    (code != 0).then(|| char::from_u32(code as u32)).flatten()
+}
+
+fn decode_gbk(bytes: &[u8]) -> Result<String> {
+    let mut text = String::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let lead = bytes[pos];
+        if lead < 0x80 {
+            text.push(lead as char);
+            pos += 1;
+            continue;
+        }
+        let c = bytes
+            .get(pos + 1)
+            .and_then(|trail| gbk_index(lead, *trail))
+            .and_then(gbk_char)
+            .ok_or_else(|| SqlnessError::InvalidText {
+                encoding: "GBK",
+                reason: format!("invalid sequence at byte {}", pos),
+            })?;
+        text.push(c);
+        pos += 2;
+    }
+
+    Ok(text)
+}
+
+fn encode_gbk(text: &str) -> Vec<u8> {
+    static ENCODER: OnceLock<HashMap<char, [u8; 2]>> = OnceLock::new();
+    let encoder = ENCODER.get_or_init(|| {
+        let mut encoder = HashMap::new();
+        for lead in 0x81..=0xFE {
+            for trail in 0x40..=0xFE {
+                if let Some(c) = gbk_index(lead, trail).and_then(gbk_char) {
+                    encoder.insert(c, [lead, trail]);
+                }
+            }
+        }
+        encoder
+    });
+
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match encoder.get(&c) {
+            _ if c.is_ascii() => bytes.push(c as u8),
+            Some(encoded) => bytes.extend(encoded),
+            None => bytes.push(b'?'),
+        }
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin1() {
+        let bytes = vec![b'c', b'a', b'f', 0xE9];
+        assert_eq!(Encoding::Latin1.decode(bytes.clone()).unwrap(), "café");
+        assert_eq!(Encoding::Latin1.encode("café"), bytes);
+        assert_eq!(Encoding::Latin1.encode("中"), b"?");
+    }
+
+    #[test]
+    fn gbk() {
+        let bytes = b"SELECT '\xd6\xd0\xce\xc4';".to_vec();
+        assert_eq!(
+            Encoding::Gbk.decode(bytes.clone()).unwrap(),
+            "SELECT '中文';"
+        );
+        assert_eq!(Encoding::Gbk.encode("SELECT '中文';"), bytes);
+        // the last byte of GBK range
+        assert_eq!(Encoding::Gbk.decode(vec![0xFE, 0x4F]).unwrap(), "\u{fa29}");
+        assert_eq!(
+            Encoding::Gbk.encode("\u{fa29}éß😀"),
+            [0xFE, 0x4F, 0xA8, 0xA6, b'?', b'?']
+        );
+    }
+
+    #[test]
+    fn invalid_gbk() {
+        for bytes in [vec![0xD6], vec![0xD6, 0x7F], vec![0xFF, 0x40], vec![0x80]] {
+            assert!(Encoding::Gbk.decode(bytes.clone()).is_err(), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn utf8() {
+        assert_eq!(
+            Encoding::Utf8.decode("中文".as_bytes().to_vec()).unwrap(),
+            "中文"
+        );
+        assert!(Encoding::Utf8.decode(vec![0xFF]).is_err());
+    }
+}
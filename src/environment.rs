@@ -1,6 +1,9 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
-use std::path::Path;
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+};
 
 use async_trait::async_trait;
 
@@ -31,4 +34,112 @@ pub trait EnvController {
 
     /// Stop one [`Database`].
     async fn stop(&self, env: &str, database: Self::DB);
+
+    /// Sample resource usage of the server behind `database`, like RSS of its
+    /// process. It's called every [`Config::resource_sample_interval`] while
+    /// cases run and once after each case, and peak and average values are
+    /// included in reports. Return `None` if unavailable.
+    ///
+    /// It blocks the running case, so keep it cheap, like reading
+    /// `/proc/<pid>/status`. The default implementation samples nothing.
+    ///
+    /// [`Config::resource_sample_interval`]: crate::Config::resource_sample_interval
+    fn sample_resources(&self, _env: &str, _database: &Self::DB) -> Option<ResourceUsage> {
+        None
+    }
+}
+
+/// Resource usage of a server at some moment, see
+/// [`EnvController::sample_resources`]. Metrics not collected are left `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// Resident set size in bytes.
+    pub rss_bytes: Option<u64>,
+    /// CPU usage in percent of one core, e.g. `250.0` for 2.5 cores.
+    pub cpu_percent: Option<f64>,
+    /// Disk space used in bytes, like size of the data directory.
+    pub disk_bytes: Option<u64>,
+}
+
+/// [`EnvController`] for servers managed outside of sqlness, like a running
+/// staging cluster. It doesn't start or stop anything, but connects to the
+/// server with `connect`, which is given the `dsn` in env config file (e.g.
+/// `dsn = "mysql://127.0.0.1:3306"`).
+///
+/// ```rust, ignore, no_run
+/// let env = ExternalEnv::new(|conn: Connection| async move {
+///     MyDB::connect(&conn.dsn.unwrap()).await
+/// });
+/// let runner = Runner::try_new(config_path, env).await?;
+/// ```
+pub struct ExternalEnv<F> {
+    connect: F,
+    default_dsn: Option<String>,
+}
+
+/// Connection info of an environment, passed to [`ExternalEnv`].
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub env: String,
+    /// The `dsn` in env config file, or the default one of [`ExternalEnv`].
+    pub dsn: Option<String>,
+    pub config: Option<PathBuf>,
+}
+
+impl<F> ExternalEnv<F> {
+    pub fn new(connect: F) -> Self {
+        Self {
+            connect,
+            default_dsn: None,
+        }
+    }
+
+    /// Use `dsn` for environments without `dsn` in their config files.
+    pub fn with_default_dsn(mut self, dsn: String) -> Self {
+        self.default_dsn = Some(dsn);
+        self
+    }
+
+    async fn read_dsn(config: &Path) -> Option<String> {
+        let content = match tokio::fs::read(config).await {
+            Ok(content) => content,
+            Err(e) => {
+                println!("Unable to read {}, error: {}", config.display(), e);
+                return None;
+            }
+        };
+        match toml::from_slice::<toml::Value>(&content) {
+            Ok(value) => value.get("dsn")?.as_str().map(str::to_string),
+            Err(e) => {
+                println!("Unable to parse {}, error: {}", config.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<F, Fut, DB> EnvController for ExternalEnv<F>
+where
+    F: Fn(Connection) -> Fut + Send + Sync,
+    Fut: Future<Output = DB> + Send,
+    DB: Database + 'static,
+{
+    type DB = DB;
+
+    async fn start(&self, env: &str, config: Option<&Path>) -> Self::DB {
+        let dsn = match config {
+            Some(config) => Self::read_dsn(config).await,
+            None => None,
+        };
+        let connection = Connection {
+            env: env.to_string(),
+            dsn: dsn.or_else(|| self.default_dsn.clone()),
+            config: config.map(Path::to_path_buf),
+        };
+
+        (self.connect)(connection).await
+    }
+
+    async fn stop(&self, _env: &str, _database: Self::DB) {}
 }
@@ -1,9 +1,12 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
 use std::path::PathBuf;
+use std::time::Duration;
 
 use thiserror::Error;
 
+use crate::config::ConfigProblem;
+
 #[derive(Error, Debug)]
 pub enum SqlnessError {
     #[error("Unable to read from path {path}")]
@@ -24,8 +27,148 @@ pub enum SqlnessError {
     #[error("Cannot parse the output/result file. Not valid UTF-8 encoding")]
     ReadResult(#[from] std::string::FromUtf8Error),
 
+    #[error("Invalid {encoding} text, {reason}")]
+    InvalidText {
+        encoding: &'static str,
+        reason: String,
+    },
+
+    #[error("Case {path} is not under any environment")]
+    CaseNotFound { path: PathBuf },
+
+    #[error("Cases have cyclic dependencies: {cases:?}")]
+    DependencyCycle { cases: Vec<PathBuf> },
+
+    #[error("Expected result file {path} is missing")]
+    MissingResult { path: PathBuf },
+
+    #[error("Invalid interceptor `{directive}`, reason: {reason}")]
+    InvalidInterceptor { directive: String, reason: String },
+
+    #[error("Failed to parse case {path} at line {line}, error: {source}")]
+    ParseCase {
+        path: PathBuf,
+        line: usize,
+        source: Box<SqlnessError>,
+    },
+
+    #[error("Query failed, reason: {reason}, query: {query}")]
+    QueryFailed { query: String, reason: String },
+
+    #[error("Failed to read {path} to compare results, error: {source}")]
+    CompareIo {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[error("Failed to (de)compress {path}, reason: {reason}")]
+    Compression { path: PathBuf, reason: String },
+
+    #[error("Program `{program}` is not found, it's required to {purpose}")]
+    MissingProgram {
+        program: &'static str,
+        purpose: &'static str,
+    },
+
+    #[error("Failed to import file {file}, reason: {reason}")]
+    ImportFailed { file: PathBuf, reason: String },
+
+    #[error("Unable to read config file {path}")]
+    ReadConfig {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[error("Invalid config `{field}`, reason: {reason}")]
+    InvalidConfig { field: String, reason: String },
+
+    #[error("Invalid config, {} problems found:\n{}", .problems.len(), format_problems(.problems))]
+    InvalidConfigs { problems: Vec<ConfigProblem> },
+
+    #[error("Environment {env} failed to start, reason: {reason}")]
+    EnvStartFailed { env: String, reason: String },
+
+    #[error("Environment {env} failed to {action} within {duration:?}")]
+    EnvTimeout {
+        env: String,
+        action: &'static str,
+        duration: Duration,
+    },
+
+    #[error("Run time budget exceeded, {count} cases are not executed")]
+    BudgetExceeded { count: usize },
+
+    #[error("Case {path} changes session state with SESSION or USE, which leaks into cases running concurrently on the shared connection. Implement `Database::connection` or run cases with `parallelism = 1`")]
+    SharedSession { path: PathBuf },
+
+    #[error("Case {path} has no different query to shrink")]
+    NothingToShrink { path: PathBuf },
+
+    #[error("Run cancelled")]
+    Cancelled,
+
     #[error("Run failed. {count} cases can't pass")]
     RunFailed { count: usize },
+
+    #[error("Run failed. {count} cases failed with errors")]
+    ExecutionFailed { count: usize },
+}
+
+impl SqlnessError {
+    /// Exit code of differences found.
+    pub const EXIT_DIFF: i32 = 1;
+    /// Exit code of errors when running cases, like I/O errors or invalid
+    /// cases.
+    pub const EXIT_EXECUTION_ERROR: i32 = 2;
+    /// Exit code of environments failed to start.
+    pub const EXIT_ENV_START_FAILED: i32 = 3;
+    /// Exit code of invalid or unreadable config.
+    pub const EXIT_CONFIG_ERROR: i32 = 4;
+
+    /// Exit code of the class of this failure, so CI pipelines and wrappers
+    /// can tell them apart without parsing logs. Greater codes are more
+    /// severe, see [`Runner::run`].
+    ///
+    /// [`Runner::run`]: crate::Runner::run
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SqlnessError::RunFailed { .. } => Self::EXIT_DIFF,
+            SqlnessError::EnvStartFailed { .. } => Self::EXIT_ENV_START_FAILED,
+            SqlnessError::ReadConfig { .. }
+            | SqlnessError::InvalidConfig { .. }
+            | SqlnessError::InvalidConfigs { .. }
+            | SqlnessError::ParseToml { .. } => Self::EXIT_CONFIG_ERROR,
+            _ => Self::EXIT_EXECUTION_ERROR,
+        }
+    }
+
+    /// The more severe one of `self` and `other`, by [`Self::exit_code`].
+    /// Counts of failures of the same kind are summed.
+    pub(crate) fn merge(self, other: SqlnessError) -> SqlnessError {
+        match (self, other) {
+            (SqlnessError::RunFailed { count: a }, SqlnessError::RunFailed { count: b }) => {
+                SqlnessError::RunFailed { count: a + b }
+            }
+            (
+                SqlnessError::ExecutionFailed { count: a },
+                SqlnessError::ExecutionFailed { count: b },
+            ) => SqlnessError::ExecutionFailed { count: a + b },
+            (
+                SqlnessError::BudgetExceeded { count: a },
+                SqlnessError::BudgetExceeded { count: b },
+            ) => SqlnessError::BudgetExceeded { count: a + b },
+            (this, other) if other.exit_code() > this.exit_code() => other,
+            (this, _) => this,
+        }
+    }
 }
 
 pub(crate) type Result<T> = std::result::Result<T, SqlnessError>;
+
+fn format_problems(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("    {}", problem))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,273 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Machine readable events of a run, see [`MessageFormat::Json`] and
+//! [`MessageFormat::Plain`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{case::CaseMetadata, compare::QueryDiff, metadata::RunMetadata};
+
+/// Format of messages printed by [`Runner`].
+///
+/// [`Runner`]: crate::Runner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    /// Human readable text on stdout.
+    #[default]
+    Human,
+    /// One JSON object per line on stdout for each event, like
+    /// `{"event":"case_finished","env":"local","case":"local/select","outcome":"pass","elapsed_ms":3}`.
+    /// Human readable text is printed to stderr instead.
+    Json,
+    /// One line per case result on stdout, like `pass\tlocal\tlocal/select\t3`,
+    /// whose tab separated fields are the outcome, environment, case and
+    /// elapsed milliseconds. Cases not executed have outcome `not_executed`.
+    /// Nothing else is printed, so it can be piped into tools like `awk` and
+    /// `grep`.
+    Plain,
+}
+
+/// Events emitted in [`MessageFormat::Json`].
+pub(crate) enum Event<'a> {
+    RunStarted {
+        metadata: &'a RunMetadata,
+    },
+    EnvStarted {
+        env: &'a str,
+        /// Probed by [`Config::version_probe`].
+        ///
+        /// [`Config::version_probe`]: crate::Config::version_probe
+        server_version: Option<String>,
+    },
+    EnvFinished {
+        env: &'a str,
+        elapsed_ms: u128,
+        diff: usize,
+        skipped: usize,
+        errors: usize,
+    },
+    CaseStarted {
+        env: &'a str,
+        case: &'a str,
+    },
+    /// Output of a case is different from its expected result.
+    CaseDiff {
+        case: &'a str,
+        output: &'a str,
+        expected: &'a str,
+    },
+    CaseFinished {
+        env: &'a str,
+        case: &'a str,
+        /// One of `pass`, `diff`, `skipped` and `error`.
+        outcome: &'a str,
+        elapsed_ms: u128,
+        /// Reason of skipped cases, or the error.
+        reason: Option<&'a str>,
+        metadata: &'a CaseMetadata,
+        /// From front-matter, or [`Config::owners_file`].
+        ///
+        /// [`Config::owners_file`]: crate::Config::owners_file
+        owners: &'a [String],
+    },
+    /// A query of a case has different result, with hunks of the diff.
+    QueryDiff {
+        case: &'a str,
+        diff: &'a QueryDiff,
+    },
+    /// Cases not started because [`Config::max_run_duration`] is exceeded.
+    ///
+    /// [`Config::max_run_duration`]: crate::Config::max_run_duration
+    CasesNotExecuted {
+        env: &'a str,
+        cases: &'a [String],
+    },
+}
+
+impl Event<'_> {
+    pub(crate) fn to_json(&self) -> String {
+        let fields = match self {
+            Event::RunStarted { metadata } => {
+                let mut fields = vec![("event", string("run_started"))];
+                fields.extend(
+                    metadata
+                        .fields()
+                        .into_iter()
+                        .map(|(key, value)| (key, string(&value))),
+                );
+                fields
+            }
+            Event::EnvStarted {
+                env,
+                server_version,
+            } => {
+                let mut fields = vec![("event", string("env_started")), ("env", string(env))];
+                if let Some(version) = server_version {
+                    fields.push(("server_version", string(version)));
+                }
+                fields
+            }
+            Event::EnvFinished {
+                env,
+                elapsed_ms,
+                diff,
+                skipped,
+                errors,
+            } => vec![
+                ("event", string("env_finished")),
+                ("env", string(env)),
+                ("elapsed_ms", elapsed_ms.to_string()),
+                ("diff", diff.to_string()),
+                ("skipped", skipped.to_string()),
+                ("errors", errors.to_string()),
+            ],
+            Event::CaseStarted { env, case } => vec![
+                ("event", string("case_started")),
+                ("env", string(env)),
+                ("case", string(case)),
+            ],
+            Event::CaseDiff {
+                case,
+                output,
+                expected,
+            } => vec![
+                ("event", string("case_diff")),
+                ("case", string(case)),
+                ("output", string(output)),
+                ("expected", string(expected)),
+            ],
+            Event::CaseFinished {
+                env,
+                case,
+                outcome,
+                elapsed_ms,
+                reason,
+                metadata,
+                owners,
+            } => {
+                let mut fields = vec![
+                    ("event", string("case_finished")),
+                    ("env", string(env)),
+                    ("case", string(case)),
+                    ("outcome", string(outcome)),
+                    ("elapsed_ms", elapsed_ms.to_string()),
+                ];
+                if let Some(reason) = reason {
+                    fields.push(("reason", string(reason)));
+                }
+                if !owners.is_empty() {
+                    fields.push(("owners", array(owners)));
+                }
+                if !metadata.tags.is_empty() {
+                    fields.push(("tags", array(&metadata.tags)));
+                }
+                if !metadata.issues.is_empty() {
+                    fields.push(("issues", array(&metadata.issues)));
+                }
+                fields
+            }
+            Event::QueryDiff { case, diff } => {
+                let mut fields = vec![
+                    ("event", string("query_diff")),
+                    ("case", string(case)),
+                    ("index", diff.index.to_string()),
+                ];
+                if let Some(name) = &diff.name {
+                    fields.push(("name", string(name)));
+                }
+                if let Some(query_id) = &diff.query_id {
+                    fields.push(("query_id", string(query_id)));
+                }
+                if let Some((start, end)) = diff.expected_lines {
+                    fields.push(("expected_lines", format!("[{},{}]", start, end)));
+                }
+                if let Some((start, end)) = diff.actual_lines {
+                    fields.push(("actual_lines", format!("[{},{}]", start, end)));
+                }
+                if let Some(expected) = &diff.expected {
+                    fields.push(("expected", string(expected)));
+                }
+                if let Some(actual) = &diff.actual {
+                    fields.push(("actual", string(actual)));
+                }
+                let hunks: Vec<_> = diff
+                    .hunks
+                    .iter()
+                    .map(|hunk| {
+                        object(vec![
+                            ("expected_line", hunk.expected_line.to_string()),
+                            ("actual_line", hunk.actual_line.to_string()),
+                            ("lines", array(&hunk.lines)),
+                        ])
+                    })
+                    .collect();
+                fields.push(("hunks", format!("[{}]", hunks.join(","))));
+                fields
+            }
+            Event::CasesNotExecuted { env, cases } => vec![
+                ("event", string("cases_not_executed")),
+                ("env", string(env)),
+                ("cases", array(cases)),
+            ],
+        };
+
+        object(fields)
+    }
+}
+
+impl Event<'_> {
+    /// Lines of case results in [`MessageFormat::Plain`], empty for other
+    /// events.
+    pub(crate) fn to_plain(&self) -> Vec<String> {
+        match self {
+            Event::CaseFinished {
+                env,
+                case,
+                outcome,
+                elapsed_ms,
+                ..
+            } => vec![format!("{}\t{}\t{}\t{}", outcome, env, case, elapsed_ms)],
+            Event::CasesNotExecuted { env, cases } => cases
+                .iter()
+                .map(|case| format!("not_executed\t{}\t{}\t0", env, case))
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// JSON object of `fields`, whose values are already JSON.
+pub(crate) fn object(fields: Vec<(&str, String)>) -> String {
+    let fields: Vec<_> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}:{}", string(key), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// JSON array of strings.
+fn array(items: &[String]) -> String {
+    let items: Vec<_> = items.iter().map(|item| string(item)).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Quote and escape `s` as a JSON string.
+pub(crate) fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
@@ -0,0 +1,200 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Differential testing of generated cases, enabled by the `fuzz` feature.
+//!
+//! A [`CaseGenerator`] produces random cases as [`TestCaseBuilder`]s, like a
+//! proptest strategy or a sqlsmith-style query generator does. Each case runs
+//! against an oracle database and the database under test, and cases with
+//! different results are persisted as regular case files, with results of the
+//! oracle as expected results, so they keep running in the suite once fixed.
+//!
+//! ```rust, ignore, no_run
+//! let report = Differential::new(&config, "cases/fuzz/found")
+//!     .cases(1000)
+//!     .seed(42)
+//!     .run(&mut |rng: &mut SeededRng| {
+//!         let table = rng.pick(&["t1", "t2"]);
+//!         TestCaseBuilder::new(format!("select-{}", rng.seed()))
+//!             .query_with(&["SORT_RESULT"], format!("SELECT * FROM {};", table))
+//!     }, &target, &oracle)
+//!     .await?;
+//! assert!(report.failures.is_empty(), "{:?}", report.failures);
+//! ```
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs::{create_dir_all, write};
+
+use crate::case_builder::TestCaseBuilder;
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::Result;
+
+/// Generator of random cases.
+pub trait CaseGenerator {
+    /// Generate a case with randomness from `rng`. Names of cases are used as
+    /// paths (relative to the failure directory) of failing cases, so they
+    /// should be unique, e.g. by including [`SeededRng::seed`].
+    fn generate(&mut self, rng: &mut SeededRng) -> TestCaseBuilder;
+}
+
+impl<F> CaseGenerator for F
+where
+    F: FnMut(&mut SeededRng) -> TestCaseBuilder,
+{
+    fn generate(&mut self, rng: &mut SeededRng) -> TestCaseBuilder {
+        self(rng)
+    }
+}
+
+/// Deterministic random number generator (SplitMix64), so a failing case can
+/// be generated again from its seed.
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    seed: u64,
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// Seed this generator is created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `0..bound`. Panics if `bound` is 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "bound must be greater than 0");
+        self.next_u64() % bound
+    }
+
+    /// True with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    /// Random element of `items`. Panics if it's empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// Differential run of generated cases, see the [module](self) docs.
+pub struct Differential<'a> {
+    config: &'a Config,
+    failure_dir: PathBuf,
+    cases: usize,
+    seed: u64,
+}
+
+/// Result of a [`Differential`] run.
+#[derive(Debug, Clone, Default)]
+pub struct DifferentialReport {
+    /// Number of generated cases.
+    pub executed: usize,
+    /// Seeds and paths of persisted case files of failing cases.
+    pub failures: Vec<(u64, PathBuf)>,
+    /// Different queries and their diffs of failing cases.
+    pub report: String,
+}
+
+impl<'a> Differential<'a> {
+    /// Failing cases are written under `failure_dir`, which is usually an
+    /// environment directory under [`Config::case_dir`].
+    pub fn new(config: &'a Config, failure_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            config,
+            failure_dir: failure_dir.into(),
+            cases: 100,
+            seed: 0,
+        }
+    }
+
+    /// Number of cases to generate. Default is 100.
+    pub fn cases(mut self, cases: usize) -> Self {
+        self.cases = cases;
+        self
+    }
+
+    /// Seed of the first case, following cases use subsequent seeds. Default
+    /// is 0.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Generate cases by `generator`, and run each of them against `oracle`
+    /// and `target`. Cases whose results of `target` differ from those of
+    /// `oracle` are persisted.
+    pub async fn run(
+        &self,
+        generator: &mut dyn CaseGenerator,
+        target: &dyn Database,
+        oracle: &dyn Database,
+    ) -> Result<DifferentialReport> {
+        let mut report = DifferentialReport::default();
+        for i in 0..self.cases {
+            let seed = self.seed.wrapping_add(i as u64);
+            let case = generator.generate(&mut SeededRng::new(seed));
+            let expected = case.run(oracle, self.config).await?.output;
+            let case = case.expected(expected.clone());
+            let output = case.run(target, self.config).await?;
+            report.executed += 1;
+            if !output.is_different {
+                continue;
+            }
+
+            let path = self.persist(&case, seed, &expected).await?;
+            writeln!(
+                report.report,
+                "Case {} (seed {}) failed",
+                path.display(),
+                seed
+            )
+            .unwrap();
+            report.report.push_str(&output.report);
+            report.failures.push((seed, path));
+        }
+
+        Ok(report)
+    }
+
+    /// Write `case` and its `expected` result under the failure directory,
+    /// return the path of the case file.
+    async fn persist(&self, case: &TestCaseBuilder, seed: u64, expected: &str) -> Result<PathBuf> {
+        let name = match case.name() {
+            "" => format!("seed-{}", seed),
+            name => name.to_string(),
+        };
+        let path = self
+            .failure_dir
+            .join(Path::new(&name))
+            .with_extension(&self.config.test_case_extension);
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).await?;
+        }
+        let encoding = self.config.file_encoding;
+        write(&path, encoding.encode(&case.to_case_file(self.config))).await?;
+        write(
+            path.with_extension(&self.config.expect_result_extension),
+            encoding.encode(expected),
+        )
+        .await?;
+
+        Ok(path)
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Glob patterns matching paths of cases, in the style of `.gitignore` and
+//! `CODEOWNERS`.
+
+/// Whether `path` (relative, `/` separated) matches `pattern`.
+///
+/// - `*` matches any characters except `/`, `?` matches one of them.
+/// - `**` matches any characters including `/`.
+/// - A pattern without `/` (except a trailing one) matches at any depth.
+/// - A pattern ending with `/` matches everything under the directory.
+/// - A leading `/` anchors the pattern to the root, which is also the default
+///   for patterns containing `/`.
+pub(crate) fn matches(pattern: &str, path: &str) -> bool {
+    let mut pattern = pattern.to_string();
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    let pattern = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if !pattern.trim_end_matches("/**").contains('/') => format!("**/{}", pattern),
+        None => pattern,
+    };
+
+    // a directory pattern also matches files under it
+    let pattern: Vec<_> = pattern.chars().collect();
+    let path: Vec<_> = path.chars().collect();
+    match_chars(&pattern, &path) || {
+        let mut under_dir = pattern.clone();
+        under_dir.extend("/**".chars());
+        match_chars(&under_dir, &path)
+    }
+}
+
+fn match_chars(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        // `**/` also matches nothing, like `a/**/b` matching `a/b`
+        ['*', '*', '/', rest @ ..] => {
+            match_chars(rest, path)
+                || (0..path.len())
+                    .filter(|&i| path[i] == '/')
+                    .any(|i| match_chars(rest, &path[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| match_chars(rest, &path[i..])),
+        ['*', rest @ ..] => {
+            // stop at the first `/`
+            let end = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+            (0..=end).any(|i| match_chars(rest, &path[i..]))
+        }
+        ['?', rest @ ..] => matches!(path, [c, ..] if *c != '/') && match_chars(rest, &path[1..]),
+        [c, rest @ ..] => matches!(path, [p, ..] if p == c) && match_chars(rest, &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_stops_at_slash() {
+        assert!(matches("/a/*.sql", "a/b.sql"));
+        assert!(!matches("/a/*.sql", "a/b/c.sql"));
+        assert!(matches("/a/*", "a/"));
+        // but a matched directory includes files under it
+        assert!(matches("/a*", "ab/c.sql"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        assert!(matches("/case?.sql", "case1.sql"));
+        assert!(!matches("/case?.sql", "case.sql"));
+        assert!(!matches("/case?.sql", "case12.sql"));
+        assert!(!matches("/a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(matches("/a/**/b.sql", "a/b.sql"));
+        assert!(matches("/a/**/b.sql", "a/x/y/b.sql"));
+        assert!(!matches("/a/**/b.sql", "c/a/b.sql"));
+        assert!(matches("/a/**", "a/x/y.sql"));
+        assert!(matches("**/b.sql", "x/y/b.sql"));
+    }
+
+    #[test]
+    fn unanchored_and_directory_patterns() {
+        // without `/` it matches at any depth
+        assert!(matches("*.sql", "a/b/c.sql"));
+        assert!(matches("slow", "a/slow/c.sql"));
+        // with `/` it's anchored to the root
+        assert!(matches("a/b", "a/b/c.sql"));
+        assert!(!matches("a/b", "x/a/b/c.sql"));
+        // a trailing `/` matches everything under the directory
+        assert!(matches("b/", "a/b/c/d.sql"));
+        assert!(!matches("b/", "a/bc/d.sql"));
+    }
+}
@@ -0,0 +1,523 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Gzip files (RFC 1952) of deflate streams (RFC 1951), for compressed
+//! expected results. Compression finds repeated strings with hash chains and
+//! writes them with fixed Huffman codes, which is simple and good enough for
+//! text. Decompression supports all block types, and files of multiple members
+//! like concatenated gzip files.
+
+/// Max distance of matches.
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Max earlier positions tried when looking for the longest match.
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+
+/// Base lengths of length symbols 257 to 285, and their extra bits.
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances of distance symbols 0 to 29, and their extra bits.
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order of code lengths of the code length alphabet in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Magic, compression method (deflate), flags, mtime, extra flags and OS
+/// (unknown) of gzip headers.
+const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+const FLAG_HEADER_CRC: u8 = 2;
+const FLAG_EXTRA: u8 = 4;
+const FLAG_NAME: u8 = 8;
+const FLAG_COMMENT: u8 = 16;
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        bytes: HEADER.to_vec(),
+        buffer: 0,
+        count: 0,
+    };
+    // one final block of fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (length, distance) = longest_match(data, pos, &head, &prev);
+        if length >= MIN_MATCH {
+            writer.write_length(length);
+            writer.write_distance(distance);
+        } else {
+            writer.write_symbol(data[pos] as u16);
+        }
+        let end = pos + length.max(1);
+        for pos in pos..end {
+            if pos + MIN_MATCH > data.len() {
+                break;
+            }
+            let hash = hash(&data[pos..]);
+            prev[pos % WINDOW_SIZE] = head[hash];
+            head[hash] = pos;
+        }
+        pos = end;
+    }
+    writer.write_symbol(END_OF_BLOCK);
+    writer.flush();
+
+    let mut bytes = writer.bytes;
+    bytes.extend(crc32(data).to_le_bytes());
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes
+}
+
+pub(crate) fn decompress(data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let mut output = vec![];
+    let mut rest = data;
+    loop {
+        let header_len = header_len(rest)?;
+        let mut reader = BitReader {
+            data: &rest[header_len..],
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        };
+        let member = inflate(&mut reader)?;
+        let trailer = reader.bytes(8)?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != crc32(&member) || size != member.len() as u32 {
+            return Err("checksum mismatch".to_string());
+        }
+        output.extend(member);
+
+        rest = &rest[header_len + reader.pos..];
+        if rest.is_empty() {
+            return Ok(output);
+        }
+    }
+}
+
+/// Length of the gzip header at the start of `data`.
+fn header_len(data: &[u8]) -> std::result::Result<usize, String> {
+    if data.len() < HEADER.len() || data[..3] != HEADER[..3] {
+        return Err("not a gzip file".to_string());
+    }
+    let flags = data[3];
+    let mut len = HEADER.len();
+    if flags & FLAG_EXTRA != 0 {
+        let extra = data
+            .get(len..len + 2)
+            .ok_or_else(|| "truncated header".to_string())?;
+        len += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            // zero-terminated
+            len += data
+                .get(len..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(|| "truncated header".to_string())?
+                + 1;
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        len += 2;
+    }
+    if len > data.len() {
+        return Err("truncated header".to_string());
+    }
+
+    Ok(len)
+}
+
+fn hash(data: &[u8]) -> usize {
+    ((data[0] as usize) << 10 ^ (data[1] as usize) << 5 ^ data[2] as usize) & ((1 << HASH_BITS) - 1)
+}
+
+/// Longest earlier match of `data[pos..]` within the window, as its length
+/// and distance.
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max_len = MAX_MATCH.min(data.len() - pos);
+    let (mut best_len, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+            break;
+        }
+        let len = data[candidate..]
+            .iter()
+            .zip(&data[pos..pos + max_len])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best_len {
+            (best_len, best_distance) = (len, pos - candidate);
+            if len == max_len {
+                break;
+            }
+        }
+        candidate = prev[candidate % WINDOW_SIZE];
+    }
+
+    (best_len, best_distance)
+}
+
+/// CRC-32 of gzip trailers.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(n as u32, |c, _| {
+            if c & 1 == 1 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            }
+        });
+    }
+
+    !data.iter().fold(!0u32, |crc, byte| {
+        table[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Writes bits from the least significant one, as deflate streams are packed.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed from the most significant bit.
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    /// Write a literal/length symbol with the fixed Huffman code.
+    fn write_symbol(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_length(&mut self, length: usize) {
+        let index = LENGTH_BASES
+            .iter()
+            .rposition(|base| *base as usize <= length)
+            .unwrap();
+        self.write_symbol(257 + index as u16);
+        self.write_bits(
+            (length - LENGTH_BASES[index] as usize) as u32,
+            LENGTH_EXTRA_BITS[index],
+        );
+    }
+
+    fn write_distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASES
+            .iter()
+            .rposition(|base| *base as usize <= distance)
+            .unwrap();
+        self.write_code(index as u32, 5);
+        self.write_bits(
+            (distance - DISTANCE_BASES[index] as usize) as u32,
+            DISTANCE_EXTRA_BITS[index],
+        );
+    }
+
+    /// Write remaining bits, padded to a byte.
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer = 0;
+            self.count = 0;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position of the next byte not loaded to `buffer`.
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> std::result::Result<u32, String> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| "unexpected end of data".to_string())?;
+            self.buffer |= (byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+
+        Ok(value)
+    }
+
+    /// Skip remaining bits of the current byte, and read `len` bytes.
+    fn bytes(&mut self, len: usize) -> std::result::Result<&'a [u8], String> {
+        self.buffer = 0;
+        self.count = 0;
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| "unexpected end of data".to_string())?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+}
+
+/// Canonical Huffman code, as counts of codes of each length and symbols
+/// ordered by their codes.
+struct Huffman {
+    counts: [usize; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<_> = (0..lengths.len() as u16)
+            .filter(|symbol| lengths[*symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+
+        Self { counts, symbols }
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+
+        (Self::new(&lengths), Self::new(&[5; 30]))
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> std::result::Result<u16, String> {
+        // codes of length `len` are from `first`, taking symbols from `index`
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for count in &self.counts[1..] {
+            code |= reader.bits(1)? as usize;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+fn inflate(reader: &mut BitReader) -> std::result::Result<Vec<u8>, String> {
+    let mut output = vec![];
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                let header = reader.bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("invalid stored block length".to_string());
+                }
+                output.extend(reader.bytes(len as usize)?);
+            }
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                inflate_block(reader, &literals, &distances, &mut output)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_block(reader, &literals, &distances, &mut output)?;
+            }
+            _ => return Err("invalid block type".to_string()),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// Read the Huffman codes at the start of a dynamic block.
+fn dynamic_codes(reader: &mut BitReader) -> std::result::Result<(Huffman, Huffman), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, repeat) = match code_lengths.decode(reader)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => {
+                let prev = *lengths
+                    .last()
+                    .ok_or_else(|| "repeat without a previous length".to_string())?;
+                (prev, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(len, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("too many code lengths".to_string());
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    output: &mut Vec<u8>,
+) -> std::result::Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+
+        let index = (symbol - 257) as usize;
+        if index >= LENGTH_BASES.len() {
+            return Err("invalid length symbol".to_string());
+        }
+        let length = LENGTH_BASES[index] as usize + reader.bits(LENGTH_EXTRA_BITS[index])? as usize;
+        let index = distances.decode(reader)? as usize;
+        if index >= DISTANCE_BASES.len() {
+            return Err("invalid distance symbol".to_string());
+        }
+        let distance =
+            DISTANCE_BASES[index] as usize + reader.bits(DISTANCE_EXTRA_BITS[index])? as usize;
+        if distance > output.len() {
+            return Err("distance too far back".to_string());
+        }
+        for _ in 0..length {
+            output.push(output[output.len() - distance]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text =
+            "SELECT * FROM t;\n\n+----+-------+\n| id | value |\n+----+-------+\n".repeat(1000);
+        let mut binary = vec![];
+        let mut seed = 1u32;
+        for _ in 0..100_000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            binary.push((seed >> 16) as u8);
+        }
+        for data in [&b""[..], b"a", b"aaaaaaaaaa", text.as_bytes(), &binary] {
+            let compressed = compress(data);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+        assert!(compress(text.as_bytes()).len() < text.len() / 20);
+    }
+
+    #[test]
+    fn decompress_gzip_output() {
+        // `printf 'hello hello hello\n' | gzip -n`, a block of fixed codes
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00, 0x3b, 0x7c, 0x8a, 0xdf, 0x12, 0x00, 0x00,
+            0x00,
+        ];
+        assert_eq!(decompress(&compressed).unwrap(), b"hello hello hello\n");
+
+        // squares modulo 97 of 0 to 11 by `gzip -9n`, a block of dynamic codes
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x05, 0xc1, 0xb1, 0x11,
+            0x00, 0x30, 0x08, 0x03, 0xb1, 0xfe, 0xa7, 0xc1, 0x60, 0x7c, 0x61, 0xff, 0xc5, 0x22,
+            0x15, 0xc2, 0x1c, 0x0a, 0xbd, 0x4c, 0xf0, 0x11, 0xf3, 0xc4, 0xd0, 0xe6, 0x03, 0xce,
+            0x37, 0xcc, 0x38, 0x1f, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            decompress(&compressed).unwrap(),
+            b"0\n1\n4\n9\n16\n25\n36\n49\n64\n81\n3\n24\n"
+        );
+
+        // compression level 0, a stored block
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x07, 0x00, 0xf8,
+            0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x0a, 0xe2, 0x9c, 0x53, 0xa5, 0x07, 0x00,
+            0x00, 0x00,
+        ];
+        assert_eq!(decompress(&compressed).unwrap(), b"stored\n");
+
+        // two members
+        let mut concatenated = compress(b"a\n");
+        concatenated.extend(compress(b"b\n"));
+        assert_eq!(decompress(&concatenated).unwrap(), b"a\nb\n");
+    }
+
+    #[test]
+    fn invalid_data() {
+        assert!(decompress(b"not gzip").is_err());
+        let mut compressed = compress(b"some text");
+        let len = compressed.len();
+        compressed[len - 5] ^= 1;
+        assert!(decompress(&compressed).is_err());
+        assert!(decompress(&compressed[..len - 4]).is_err());
+    }
+}
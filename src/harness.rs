@@ -0,0 +1,263 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! A test harness for test targets with `harness = false`, which registers each
+//! discovered case as a test, see [`run_harness`].
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use crate::error::Result;
+use crate::{config::Config, discovery, environment::EnvController, Runner};
+
+/// Exit code of failed tests, same as libtest.
+const FAILURE_CODE: u8 = 101;
+
+/// Command line arguments of libtest that are respected.
+#[derive(Default)]
+struct Arguments {
+    filters: Vec<String>,
+    skips: Vec<String>,
+    exact: bool,
+    list: bool,
+    /// Only run ignored tests, and no case is ignored.
+    ignored: bool,
+    /// Only print names when listing, like `--format terse`.
+    terse: bool,
+    quiet: bool,
+}
+
+impl Arguments {
+    fn parse(args: impl Iterator<Item = String>) -> std::result::Result<Self, String> {
+        let mut arguments = Self::default();
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Argument to option '{}' missing", flag))
+            };
+            match flag.as_str() {
+                "--exact" => arguments.exact = true,
+                "--list" => arguments.list = true,
+                "--ignored" => arguments.ignored = true,
+                "-q" | "--quiet" => arguments.quiet = true,
+                "--skip" => arguments.skips.push(value()?),
+                "--format" => arguments.terse = value()? == "terse",
+                // Cases are run one at a time, and their output isn't captured.
+                "--include-ignored" | "--nocapture" | "--show-output" | "--test" | "--bench" => {}
+                "--test-threads" | "--color" | "-Z" => {
+                    value()?;
+                }
+                _ if flag.starts_with('-') => {
+                    return Err(format!("Unrecognized option: '{}'", arg))
+                }
+                _ => arguments.filters.push(arg),
+            }
+        }
+
+        Ok(arguments)
+    }
+
+    /// Whether test `name` is selected by filters and not skipped.
+    fn is_selected(&self, name: &str) -> bool {
+        let matches = |pattern: &String| {
+            if self.exact {
+                name == pattern
+            } else {
+                name.contains(pattern.as_str())
+            }
+        };
+
+        (self.filters.is_empty() || self.filters.iter().any(matches))
+            && !self.skips.iter().any(matches)
+    }
+}
+
+/// Run cases under [`Config::case_dir`] as tests, with the command line
+/// interface of libtest, so they can be filtered by `cargo test <filter>`
+/// (with `--exact` and `--skip`), and listed by `cargo nextest`.
+///
+/// Tests are named by paths of cases without extension, like
+/// `local/dml/basic`. Each test runs its case with [`Runner::run_case`] and an
+/// env controller created by `new_env`, which starts and stops the environment.
+///
+/// ```rust, ignore, no_run
+/// // tests/sqlness.rs, declared with `harness = false` in Cargo.toml
+/// #[tokio::main]
+/// async fn main() -> std::process::ExitCode {
+///     let config = Config::from_file("tests/sqlness.toml").await.unwrap();
+///     sqlness::run_harness(config, MyEnv::new).await
+/// }
+/// ```
+pub async fn run_harness<E, F>(config: Config, new_env: F) -> ExitCode
+where
+    E: EnvController,
+    F: Fn() -> E,
+{
+    let arguments = match Arguments::parse(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(FAILURE_CODE);
+        }
+    };
+    let cases = match collect_cases(&config).await {
+        Ok(cases) => cases,
+        Err(e) => {
+            eprintln!("error: failed to collect cases, {}", e);
+            return ExitCode::from(FAILURE_CODE);
+        }
+    };
+    let total = cases.len();
+    let selected: Vec<_> = cases
+        .into_iter()
+        .filter(|(name, _)| !arguments.ignored && arguments.is_selected(name))
+        .collect();
+
+    if arguments.list {
+        for (name, _) in &selected {
+            println!("{}: test", name);
+        }
+        if !arguments.terse {
+            println!("\n{} tests, 0 benchmarks", selected.len());
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let timer = Instant::now();
+    println!("\nrunning {} tests", selected.len());
+    let mut failures = vec![];
+    for (name, path) in &selected {
+        let runner = match Runner::new_with_config(config.clone(), new_env()).await {
+            Ok(runner) => runner,
+            Err(e) => {
+                eprintln!("error: failed to create runner, {}", e);
+                return ExitCode::from(FAILURE_CODE);
+            }
+        };
+        let result = runner.run_case(path).await;
+        if arguments.quiet {
+            print!("{}", if result.is_ok() { "." } else { "F" });
+        } else {
+            println!(
+                "test {} ... {}",
+                name,
+                if result.is_ok() { "ok" } else { "FAILED" }
+            );
+        }
+        if let Err(e) = result {
+            failures.push((name, e));
+        }
+    }
+    if arguments.quiet {
+        println!();
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:\n");
+        for (name, e) in &failures {
+            println!("---- {} ----\n{}\n", name, e);
+        }
+        println!("\nfailures:");
+        for (name, _) in &failures {
+            println!("    {}", name);
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed; 0 ignored; 0 measured; {} filtered out; \
+         finished in {:.2}s\n",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        selected.len() - failures.len(),
+        failures.len(),
+        total - selected.len(),
+        timer.elapsed().as_secs_f64()
+    );
+
+    if failures.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(FAILURE_CODE)
+    }
+}
+
+/// Names and paths of all cases, in the order they run.
+async fn collect_cases(config: &Config) -> Result<Vec<(String, PathBuf)>> {
+    let mut cases = vec![];
+    for env in discovery::collect_envs(config).await? {
+        for path in discovery::collect_case_paths(config, &env).await? {
+            let name = discovery::relative_case_path(config, &path);
+            let name = name
+                .strip_suffix(&format!(".{}", config.test_case_extension))
+                .unwrap_or(&name)
+                .to_string();
+            cases.push((name, path));
+        }
+    }
+
+    Ok(cases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<Arguments, String> {
+        Arguments::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_arguments() {
+        let arguments = parse(&[
+            "dml",
+            "--skip",
+            "slow",
+            "--skip=flaky",
+            "--test-threads",
+            "4",
+            "--color=never",
+            "--nocapture",
+            "--format",
+            "terse",
+            "--list",
+            "-q",
+        ])
+        .unwrap();
+        assert_eq!(arguments.filters, vec!["dml"]);
+        assert_eq!(arguments.skips, vec!["slow", "flaky"]);
+        assert!(arguments.list && arguments.terse && arguments.quiet);
+        assert!(!arguments.exact && !arguments.ignored);
+
+        assert_eq!(
+            parse(&["--skip"]).err().unwrap(),
+            "Argument to option '--skip' missing"
+        );
+        assert_eq!(
+            parse(&["--shuffle"]).err().unwrap(),
+            "Unrecognized option: '--shuffle'"
+        );
+    }
+
+    #[test]
+    fn select_tests() {
+        let arguments = parse(&[]).unwrap();
+        assert!(arguments.is_selected("local/dml/basic"));
+
+        let arguments = parse(&["dml", "ddl/", "--skip", "slow"]).unwrap();
+        assert!(arguments.is_selected("local/dml/basic"));
+        assert!(arguments.is_selected("local/ddl/create"));
+        assert!(!arguments.is_selected("local/dml/slow_insert"));
+        assert!(!arguments.is_selected("local/select/basic"));
+
+        let arguments = parse(&["--exact", "local/dml/basic"]).unwrap();
+        assert!(arguments.is_selected("local/dml/basic"));
+        assert!(!arguments.is_selected("local/dml/basic2"));
+    }
+}
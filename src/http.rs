@@ -0,0 +1,59 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Minimal HTTP/1.1 client to send reports to collectors, like OpenTelemetry
+//! collectors and Prometheus pushgateways. Only plain HTTP is supported.
+
+use std::io;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Send `body` to `url` like `http://host:port/path`, with `default_path` used
+/// if `url` has no path. Responses other than 2xx are errors.
+pub(crate) async fn send(
+    method: &str,
+    url: &str,
+    default_path: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        io::Error::other(format!(
+            "unsupported url `{}`, expect `http://host:port`",
+            url
+        ))
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) if index + 1 < rest.len() => rest.split_at(index),
+        Some(index) => (&rest[..index], default_path),
+        None => (rest, default_path),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(address).await?;
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        authority,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!("{} responded {:?}", url, status))),
+    }
+}
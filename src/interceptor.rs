@@ -0,0 +1,235 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Interceptors are directives placed in front of a query, in the form of
+//! `<interceptor_prefix> <NAME> <ARGS>` (e.g. `-- SQLNESS SESSION SET a=1`).
+//! They can alter how the query is executed or how its result is recorded.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    config::Config,
+    discovery::{self, EnvDirs},
+    error::{Result, SqlnessError},
+    glob,
+};
+
+mod arg;
+mod assert;
+pub(crate) mod blocks;
+mod connection;
+mod copy;
+mod expect_errcode;
+mod hash_threshold;
+mod query_id_comment;
+mod replace;
+mod result_file;
+pub(crate) mod schema_snapshot;
+mod session;
+mod snapshot;
+mod sort_result;
+mod strip_ansi;
+mod use_database;
+mod write_lines;
+
+/// Per-query state filled by [`Interceptor`]s before the query is executed.
+#[derive(Debug, Default)]
+pub(crate) struct QueryContext {
+    /// Unique ID of this execution, see [`crate::ExecutionContext::query_id`].
+    pub query_id: String,
+    /// Statements executed before the query. Their results are not recorded.
+    pub session_statements: Vec<String>,
+    /// Database to switch to before the query.
+    pub database: Option<String>,
+    /// Named connection to execute the query in, see
+    /// [`crate::Database::connection`].
+    pub connection: Option<String>,
+    /// Assert the query doesn't return within this duration.
+    pub block_timeout: Option<Duration>,
+    /// Release previously blocked queries after this query returns.
+    pub unblocks: bool,
+    /// Local files of line protocol written before the query, see
+    /// [`crate::Database::write_lines`].
+    pub write_lines: Vec<PathBuf>,
+    /// Local file streamed to the query, see [`crate::Database::copy_in`].
+    pub copy_from: Option<PathBuf>,
+    /// Local file to write data sent by the query, see
+    /// [`crate::Database::copy_out`].
+    pub copy_to: Option<PathBuf>,
+    /// File to write data exported by the query to, instead of recording it,
+    /// see [`crate::Database::copy_out`].
+    pub result_file: Option<ResultFile>,
+    /// Assert the query fails with this error code.
+    pub expected_error_code: Option<String>,
+    /// Assertions on the shape of the result.
+    pub assertion: Option<assert::ResultAssertion>,
+    /// Tables whose schemas are recorded after the query.
+    pub schema_snapshots: Vec<String>,
+    /// Tables dumped after the query.
+    pub table_snapshots: Vec<snapshot::TableSnapshot>,
+    /// Arguments forwarded to the driver, see
+    /// [`crate::ExecutionContext::options`].
+    pub options: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResultFile {
+    /// Path as written in the directive, which is recorded in output.
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub(crate) trait Interceptor {
+    /// Called before the query is submitted to the database.
+    fn before_execute(&self, _query: &mut String, _context: &mut QueryContext) {}
+
+    /// Called with the result of the query before it's written to output.
+    fn after_execute(&self, _result: &mut String) {}
+
+    /// Whether it changes the state of the connection for subsequent
+    /// queries, like `SESSION` and `USE`, see [`Config::parallelism`].
+    fn changes_session(&self) -> bool {
+        false
+    }
+}
+
+pub(crate) type InterceptorRef = Arc<dyn Interceptor + Send + Sync>;
+
+/// Names of all interceptors, as written in directives.
+pub(crate) const NAMES: [&str; 17] = [
+    session::PREFIX,
+    use_database::PREFIX,
+    blocks::BLOCKS_PREFIX,
+    blocks::UNBLOCKS_PREFIX,
+    copy::COPY_FROM_PREFIX,
+    copy::COPY_TO_PREFIX,
+    result_file::PREFIX,
+    replace::PREFIX,
+    sort_result::PREFIX,
+    schema_snapshot::PREFIX,
+    snapshot::PREFIX,
+    strip_ansi::PREFIX,
+    assert::PREFIX,
+    write_lines::PREFIX,
+    expect_errcode::PREFIX,
+    arg::PREFIX,
+    connection::PREFIX,
+];
+
+/// Split a directive (with prefix stripped) into its name and arguments,
+/// separated by the first whitespace.
+pub(crate) fn split_directive(directive: &str) -> (&str, &str) {
+    let directive = directive.trim();
+    directive
+        .split_once(char::is_whitespace)
+        .map(|(name, args)| (name, args.trim()))
+        .unwrap_or((directive, ""))
+}
+
+/// Parse one interceptor line with `prefix` already stripped. Relative paths
+/// in arguments are resolved against `base_dir`, the directory of the case.
+///
+/// Returns `None` for unknown interceptors, which are kept in the output as
+/// plain comments.
+pub(crate) fn parse(directive: &str, base_dir: &Path) -> Result<Option<InterceptorRef>> {
+    let (name, args) = split_directive(directive);
+
+    let interceptor = match name {
+        session::PREFIX => session::SessionInterceptor::try_new(args),
+        use_database::PREFIX => use_database::UseDatabaseInterceptor::try_new(args),
+        blocks::BLOCKS_PREFIX => blocks::BlocksInterceptor::try_new(args),
+        blocks::UNBLOCKS_PREFIX => blocks::UnblocksInterceptor::try_new(args),
+        copy::COPY_FROM_PREFIX => copy::CopyFromInterceptor::try_new(args, base_dir),
+        copy::COPY_TO_PREFIX => copy::CopyToInterceptor::try_new(args, base_dir),
+        result_file::PREFIX => result_file::ResultFileInterceptor::try_new(args, base_dir),
+        replace::PREFIX => replace::ReplaceInterceptor::try_new(args),
+        sort_result::PREFIX => sort_result::SortResultInterceptor::try_new(args),
+        schema_snapshot::PREFIX => schema_snapshot::SchemaSnapshotInterceptor::try_new(args),
+        snapshot::PREFIX => snapshot::SnapshotInterceptor::try_new(args),
+        strip_ansi::PREFIX => strip_ansi::StripAnsiInterceptor::try_new(args),
+        assert::PREFIX => assert::AssertInterceptor::try_new(args),
+        write_lines::PREFIX => write_lines::WriteLinesInterceptor::try_new(args, base_dir),
+        expect_errcode::PREFIX => expect_errcode::ExpectErrcodeInterceptor::try_new(args),
+        arg::PREFIX => arg::ArgInterceptor::try_new(args),
+        connection::PREFIX => connection::ConnectionInterceptor::try_new(args),
+        _ => return Ok(None),
+    }
+    .map_err(|reason| SqlnessError::InvalidInterceptor {
+        directive: directive.to_string(),
+        reason,
+    })?;
+
+    Ok(Some(interceptor))
+}
+
+/// Interceptors declared by [`Config::directory_rules`] matching case at
+/// `case_path`, and by [`EnvSettings::directives`] of its environment,
+/// applied to all its queries after those declared in front of each query.
+/// The environment is the one whose directory in `env_dirs` contains the case.
+///
+/// [`EnvSettings::directives`]: crate::EnvSettings::directives
+pub(crate) fn from_directory_rules(
+    config: &Config,
+    env_dirs: &EnvDirs,
+    case_path: &Path,
+    base_dir: &Path,
+) -> Result<Vec<InterceptorRef>> {
+    let relative_path = discovery::relative_case_path(config, case_path);
+    let mut interceptors = vec![];
+    for rule in &config.directory_rules {
+        if !glob::matches(&rule.pattern, &relative_path) {
+            continue;
+        }
+        for directive in &rule.directives {
+            let interceptor =
+                parse(directive, base_dir)?.ok_or_else(|| SqlnessError::InvalidInterceptor {
+                    directive: directive.clone(),
+                    reason: format!("unknown interceptor in rule of `{}`", rule.pattern),
+                })?;
+            interceptors.push(interceptor);
+        }
+    }
+    for env in env_dirs.envs_of(case_path) {
+        for directive in &config.envs[env].directives {
+            let interceptor =
+                parse(directive, base_dir)?.ok_or_else(|| SqlnessError::InvalidInterceptor {
+                    directive: directive.clone(),
+                    reason: format!("unknown interceptor in settings of env `{}`", env),
+                })?;
+            interceptors.push(interceptor);
+        }
+    }
+
+    Ok(interceptors)
+}
+
+/// Interceptors enabled by config, applied to all queries after those declared
+/// in front of each query.
+pub(crate) fn from_config(config: &Config) -> Result<Vec<InterceptorRef>> {
+    let mut interceptors: Vec<InterceptorRef> = vec![];
+    for rule in &config.normalize.replace {
+        let interceptor = replace::ReplaceInterceptor::with_rule(&rule.pattern, &rule.replacement)
+            .map_err(|reason| SqlnessError::InvalidConfig {
+                field: "normalize.replace".to_string(),
+                reason,
+            })?;
+        interceptors.push(interceptor);
+    }
+    if config.strip_ansi {
+        interceptors.push(Arc::new(strip_ansi::StripAnsiInterceptor));
+    }
+    if config.hash_threshold > 0 {
+        interceptors.push(Arc::new(hash_threshold::HashThresholdInterceptor::new(
+            config.hash_threshold,
+        )));
+    }
+    if config.query_id_comment {
+        interceptors.push(Arc::new(query_id_comment::QueryIdCommentInterceptor));
+    }
+
+    Ok(interceptors)
+}
@@ -0,0 +1,88 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "ARG";
+
+/// Forward `key=value` arguments to the driver, via
+/// [`ExecutionContext::options`]. E.g. to run the same statement over
+/// different protocols:
+///
+/// ```sql
+/// -- SQLNESS ARG protocol=http
+/// SELECT 1;
+///
+/// -- SQLNESS ARG protocol=grpc timeout=5s
+/// SELECT 1;
+/// ```
+///
+/// Arguments declared in front of the query take precedence over those of
+/// directory rules and environment settings, which act as defaults.
+///
+/// [`ExecutionContext::options`]: crate::ExecutionContext::options
+pub(crate) struct ArgInterceptor {
+    args: Vec<(String, String)>,
+}
+
+impl ArgInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() {
+            return Err("expect arguments like key=value".to_string());
+        }
+        let args = args
+            .split_whitespace()
+            .map(|arg| match arg.split_once('=') {
+                Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+                _ => Err(format!("expect argument like key=value, got `{}`", arg)),
+            })
+            .collect::<std::result::Result<_, _>>()?;
+
+        Ok(Arc::new(Self { args }))
+    }
+}
+
+impl Interceptor for ArgInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        // interceptors in front of the query run first
+        for (key, value) in &self.args {
+            context
+                .options
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_declared_wins() {
+        let mut context = QueryContext::default();
+        for args in ["protocol=grpc timeout=5s", "protocol=http empty="] {
+            ArgInterceptor::try_new(args)
+                .unwrap()
+                .before_execute(&mut String::new(), &mut context);
+        }
+
+        let options: Vec<_> = context
+            .options
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            options,
+            vec![("empty", ""), ("protocol", "grpc"), ("timeout", "5s")]
+        );
+    }
+
+    #[test]
+    fn invalid_args() {
+        for args in ["", "protocol", "=http", "a=1 b"] {
+            assert!(ArgInterceptor::try_new(args).is_err(), "{}", args);
+        }
+    }
+}
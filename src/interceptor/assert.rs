@@ -0,0 +1,171 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::{
+    database::ResultDescription,
+    interceptor::{Interceptor, InterceptorRef, QueryContext},
+};
+
+pub(crate) const PREFIX: &str = "ASSERT";
+
+/// Assert the shape of the result, as described by
+/// [`Database::describe_result`]. E.g.
+///
+/// ```sql
+/// -- SQLNESS ASSERT rows=100 cols=3 types=Int64,Utf8,Float64
+/// SELECT * FROM t;
+/// ```
+///
+/// Failed assertions are recorded before the result. With `result=false` the
+/// result itself is not recorded, only whether assertions passed.
+///
+/// [`Database::describe_result`]: crate::Database::describe_result
+pub(crate) struct AssertInterceptor {
+    assertion: ResultAssertion,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResultAssertion {
+    rows: Option<usize>,
+    cols: Option<usize>,
+    types: Option<Vec<String>>,
+    /// Whether to record the result.
+    pub record_result: bool,
+}
+
+impl AssertInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        let mut assertion = ResultAssertion {
+            record_result: true,
+            ..Default::default()
+        };
+        for arg in args.split_whitespace() {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("expect `<key>=<value>`, got `{}`", arg))?;
+            let parse_number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("expect a number for `{}`, got `{}`", key, value))
+            };
+            match key {
+                "rows" => assertion.rows = Some(parse_number()?),
+                "cols" => assertion.cols = Some(parse_number()?),
+                "types" => {
+                    assertion.types = Some(value.split(',').map(str::to_string).collect());
+                }
+                "result" => {
+                    assertion.record_result = value
+                        .parse()
+                        .map_err(|_| format!("expect true or false, got `{}`", value))?;
+                }
+                _ => return Err(format!("unknown assertion `{}`", key)),
+            }
+        }
+        if assertion.rows.is_none() && assertion.cols.is_none() && assertion.types.is_none() {
+            return Err("expect at least one of rows, cols and types".to_string());
+        }
+
+        Ok(Arc::new(Self { assertion }))
+    }
+}
+
+impl Interceptor for AssertInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.assertion = Some(self.assertion.clone());
+    }
+}
+
+impl ResultAssertion {
+    /// Check the result, return messages of failed assertions.
+    pub(crate) fn check(&self, description: Option<&ResultDescription>) -> Vec<String> {
+        let description = match description {
+            Some(description) => description,
+            None => return vec!["Assertion failed: result is not described".to_string()],
+        };
+
+        let mut failures = vec![];
+        if let Some(rows) = self.rows {
+            if rows != description.rows {
+                failures.push(format!(
+                    "Assertion failed: rows={}, got {}",
+                    rows, description.rows
+                ));
+            }
+        }
+        if let Some(cols) = self.cols {
+            if cols != description.column_types.len() {
+                failures.push(format!(
+                    "Assertion failed: cols={}, got {}",
+                    cols,
+                    description.column_types.len()
+                ));
+            }
+        }
+        if let Some(types) = &self.types {
+            if types != &description.column_types {
+                failures.push(format!(
+                    "Assertion failed: types={}, got {}",
+                    types.join(","),
+                    description.column_types.join(",")
+                ));
+            }
+        }
+
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assertion(args: &str) -> ResultAssertion {
+        let mut context = QueryContext::default();
+        AssertInterceptor::try_new(args)
+            .unwrap()
+            .before_execute(&mut String::new(), &mut context);
+        context.assertion.unwrap()
+    }
+
+    #[test]
+    fn check_result() {
+        let description = ResultDescription {
+            rows: 2,
+            column_types: vec!["Int64".to_string(), "Utf8".to_string()],
+        };
+
+        let passed = assertion("rows=2 cols=2 types=Int64,Utf8");
+        assert!(passed.record_result);
+        assert!(passed.check(Some(&description)).is_empty());
+
+        let failed = assertion("rows=1 types=Int64 result=false");
+        assert!(!failed.record_result);
+        assert_eq!(
+            failed.check(Some(&description)),
+            vec![
+                "Assertion failed: rows=1, got 2",
+                "Assertion failed: types=Int64, got Int64,Utf8",
+            ]
+        );
+        assert_eq!(
+            failed.check(None),
+            vec!["Assertion failed: result is not described"]
+        );
+    }
+
+    #[test]
+    fn invalid_args() {
+        for args in [
+            "",
+            "result=false",
+            "rows",
+            "rows=two",
+            "result=no",
+            "size=1",
+        ] {
+            assert!(AssertInterceptor::try_new(args).is_err(), "{}", args);
+        }
+    }
+}
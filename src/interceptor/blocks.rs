@@ -0,0 +1,97 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const BLOCKS_PREFIX: &str = "BLOCKS";
+pub(crate) const UNBLOCKS_PREFIX: &str = "UNBLOCKS";
+
+/// Assert the query doesn't return within the given duration, e.g. because
+/// it's waiting for a lock. The query keeps running in background until a
+/// following query with `UNBLOCKS` returns, or the case ends.
+///
+/// ```sql
+/// -- SQLNESS BLOCKS 500ms
+/// UPDATE t SET v = 1 WHERE id = 1;
+/// -- SQLNESS UNBLOCKS
+/// COMMIT;
+/// ```
+///
+/// Blocked queries and their releasers are submitted concurrently, so they
+/// should be executed in different sessions, declared via `CONNECTION`.
+pub(crate) struct BlocksInterceptor {
+    timeout: Duration,
+}
+
+impl BlocksInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        let timeout = parse_duration(args)?;
+
+        Ok(Arc::new(Self { timeout }))
+    }
+}
+
+impl Interceptor for BlocksInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.block_timeout = Some(self.timeout);
+    }
+}
+
+/// Wait for all blocked queries after this query returns, and record their
+/// results.
+pub(crate) struct UnblocksInterceptor;
+
+impl UnblocksInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if !args.is_empty() {
+            return Err("expect no argument".to_string());
+        }
+
+        Ok(Arc::new(Self))
+    }
+}
+
+impl Interceptor for UnblocksInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.unblocks = true;
+    }
+}
+
+/// Parse durations like `500ms`, `3s` or `1m`.
+pub(crate) fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in duration `{}`", s))?;
+    let (value, unit) = s.split_at(unit_start);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", s))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "s" => Ok(Duration::from_secs(value)),
+        "m" => value
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("duration `{}` is too large", s)),
+        _ => Err(format!("unknown unit `{}` in duration `{}`", unit, s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration(" 3s "), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("3").is_err());
+        assert!(parse_duration("3h").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration(&format!("{}m", u64::MAX)).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "CONNECTION";
+
+/// Execute the query in the named connection (session), opened by
+/// [`Database::connection`] on its first use in the case. E.g. to block on a
+/// lock held by another session:
+///
+/// ```sql
+/// -- SQLNESS CONNECTION a
+/// BEGIN;
+/// -- SQLNESS CONNECTION a
+/// UPDATE t SET v = 1 WHERE id = 1;
+///
+/// -- SQLNESS CONNECTION b
+/// -- SQLNESS BLOCKS 500ms
+/// UPDATE t SET v = 2 WHERE id = 1;
+///
+/// -- SQLNESS CONNECTION a
+/// -- SQLNESS UNBLOCKS
+/// COMMIT;
+/// ```
+///
+/// Queries without it are executed by the database of the environment.
+///
+/// [`Database::connection`]: crate::Database::connection
+pub(crate) struct ConnectionInterceptor {
+    name: String,
+}
+
+impl ConnectionInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() || args.contains(char::is_whitespace) {
+            return Err("expect exactly one connection name".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            name: args.to_string(),
+        }))
+    }
+}
+
+impl Interceptor for ConnectionInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.connection = Some(self.name.clone());
+    }
+}
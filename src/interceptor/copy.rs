@@ -0,0 +1,111 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const COPY_FROM_PREFIX: &str = "COPY_FROM";
+pub(crate) const COPY_TO_PREFIX: &str = "COPY_TO";
+
+/// Stream a local file to statements reading data from the client, via
+/// [`Database::copy_in`]. The path is relative to the case file.
+///
+/// ```sql
+/// -- SQLNESS COPY_FROM data/t.csv
+/// COPY t FROM STDIN WITH (FORMAT csv);
+/// ```
+///
+/// [`Database::copy_in`]: crate::Database::copy_in
+pub(crate) struct CopyFromInterceptor {
+    path: PathBuf,
+}
+
+impl CopyFromInterceptor {
+    pub(crate) fn try_new(
+        args: &str,
+        base_dir: &Path,
+    ) -> std::result::Result<InterceptorRef, String> {
+        Ok(Arc::new(Self {
+            path: parse_path(args, base_dir)?,
+        }))
+    }
+}
+
+impl Interceptor for CopyFromInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.copy_from = Some(self.path.clone());
+    }
+}
+
+/// Write data sent by statements like `COPY ... TO STDOUT` to a local file,
+/// via [`Database::copy_out`]. The path is relative to the case file.
+///
+/// ```sql
+/// -- SQLNESS COPY_TO data/t.out.csv
+/// COPY t TO STDOUT WITH (FORMAT csv);
+/// ```
+///
+/// [`Database::copy_out`]: crate::Database::copy_out
+pub(crate) struct CopyToInterceptor {
+    path: PathBuf,
+}
+
+impl CopyToInterceptor {
+    pub(crate) fn try_new(
+        args: &str,
+        base_dir: &Path,
+    ) -> std::result::Result<InterceptorRef, String> {
+        Ok(Arc::new(Self {
+            path: parse_path(args, base_dir)?,
+        }))
+    }
+}
+
+impl Interceptor for CopyToInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.copy_to = Some(self.path.clone());
+    }
+}
+
+fn parse_path(args: &str, base_dir: &Path) -> std::result::Result<PathBuf, String> {
+    if args.is_empty() {
+        return Err("missing file path".to_string());
+    }
+
+    Ok(base_dir.join(args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_relative_to_case() {
+        let base_dir = Path::new("cases/copy");
+        let mut context = QueryContext::default();
+        CopyFromInterceptor::try_new("data/t.csv", base_dir)
+            .unwrap()
+            .before_execute(&mut String::new(), &mut context);
+        CopyToInterceptor::try_new("data/t.out.csv", base_dir)
+            .unwrap()
+            .before_execute(&mut String::new(), &mut context);
+
+        assert_eq!(
+            context.copy_from,
+            Some(PathBuf::from("cases/copy/data/t.csv"))
+        );
+        assert_eq!(
+            context.copy_to,
+            Some(PathBuf::from("cases/copy/data/t.out.csv"))
+        );
+    }
+
+    #[test]
+    fn missing_path() {
+        assert!(CopyFromInterceptor::try_new("", Path::new("cases")).is_err());
+        assert!(CopyToInterceptor::try_new("", Path::new("cases")).is_err());
+    }
+}
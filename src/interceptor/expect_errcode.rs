@@ -0,0 +1,61 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "EXPECT_ERRCODE";
+
+/// Assert the query fails with the given error code (e.g. SQLSTATE), as
+/// reported by [`Database::error_code`]. E.g.
+///
+/// ```sql
+/// -- SQLNESS EXPECT_ERRCODE 42P01
+/// SELECT * FROM not_exist;
+/// ```
+///
+/// When the code matches, only the code is recorded instead of the error
+/// message, which is more stable across versions.
+///
+/// [`Database::error_code`]: crate::Database::error_code
+pub(crate) struct ExpectErrcodeInterceptor {
+    code: String,
+}
+
+impl ExpectErrcodeInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() || args.contains(char::is_whitespace) {
+            return Err("expect exactly one error code".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            code: args.to_string(),
+        }))
+    }
+}
+
+impl Interceptor for ExpectErrcodeInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.expected_error_code = Some(self.code.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expect_code() {
+        let mut context = QueryContext::default();
+        ExpectErrcodeInterceptor::try_new("42P01")
+            .unwrap()
+            .before_execute(&mut String::new(), &mut context);
+        assert_eq!(context.expected_error_code.as_deref(), Some("42P01"));
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(ExpectErrcodeInterceptor::try_new("").is_err());
+        assert!(ExpectErrcodeInterceptor::try_new("42P01 42703").is_err());
+    }
+}
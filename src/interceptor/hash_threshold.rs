@@ -0,0 +1,58 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use crate::{hash::md5_hex, interceptor::Interceptor};
+
+/// Replace results with more than `threshold` lines by the line count and MD5
+/// hash of them, like `30 rows hashing to 3c13dee48d9356ae19af2515e05e6b54`.
+///
+/// Results are rendered text, so lines are counted instead of values like
+/// sqllogictest does.
+///
+/// Enabled for all queries by [`Config::hash_threshold`].
+///
+/// [`Config::hash_threshold`]: crate::Config::hash_threshold
+pub(crate) struct HashThresholdInterceptor {
+    threshold: usize,
+}
+
+impl HashThresholdInterceptor {
+    pub(crate) fn new(threshold: usize) -> Self {
+        Self { threshold }
+    }
+}
+
+impl Interceptor for HashThresholdInterceptor {
+    fn after_execute(&self, result: &mut String) {
+        let count = result.lines().count();
+        if count <= self.threshold {
+            return;
+        }
+
+        let mut values = String::with_capacity(result.len() + 1);
+        for line in result.lines() {
+            values.push_str(line);
+            values.push('\n');
+        }
+        *result = format!("{} rows hashing to {}", count, md5_hex(values.as_bytes()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_large_results() {
+        let interceptor = HashThresholdInterceptor::new(2);
+        let mut result = "a\nb".to_string();
+        interceptor.after_execute(&mut result);
+        assert_eq!(result, "a\nb");
+
+        // same hash with or without the trailing newline
+        for result in ["a\nb\nc", "a\nb\nc\n"] {
+            let mut result = result.to_string();
+            interceptor.after_execute(&mut result);
+            assert_eq!(result, "3 rows hashing to 40c53c58fdafacc83cfff6ee3d2f6d69");
+        }
+    }
+}
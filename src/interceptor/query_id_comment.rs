@@ -0,0 +1,17 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use crate::interceptor::{Interceptor, QueryContext};
+
+/// Prepend the query ID as a comment like `/* sqlness_query_id=... */`, so
+/// it shows in server logs.
+///
+/// Enabled for all queries by [`Config::query_id_comment`].
+///
+/// [`Config::query_id_comment`]: crate::Config::query_id_comment
+pub(crate) struct QueryIdCommentInterceptor;
+
+impl Interceptor for QueryIdCommentInterceptor {
+    fn before_execute(&self, query: &mut String, context: &mut QueryContext) {
+        *query = format!("/* sqlness_query_id={} */{}", context.query_id, query);
+    }
+}
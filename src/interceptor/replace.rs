@@ -0,0 +1,91 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef};
+use crate::regex::Regex;
+
+pub(crate) const PREFIX: &str = "REPLACE";
+
+/// Replace matches of a regular expression in each line of the result, to
+/// mask nondeterministic parts like elapsed time. The pattern can't contain
+/// spaces (use `\s` instead), and the rest of the arguments is the
+/// replacement, in which `$1` refers to a group:
+///
+/// ```sql
+/// -- SQLNESS REPLACE elapsed:\s\d+ms elapsed: <elapsed>
+/// EXPLAIN ANALYZE SELECT * FROM t;
+/// ```
+///
+/// Rules for all queries can be declared by [`Config::normalize`].
+///
+/// [`Config::normalize`]: crate::Config::normalize
+pub(crate) struct ReplaceInterceptor {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl ReplaceInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        let (pattern, replacement) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        if pattern.is_empty() {
+            return Err("missing pattern".to_string());
+        }
+
+        Self::with_rule(pattern, replacement)
+    }
+
+    pub(crate) fn with_rule(
+        pattern: &str,
+        replacement: &str,
+    ) -> std::result::Result<InterceptorRef, String> {
+        Ok(Arc::new(Self {
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_string(),
+        }))
+    }
+}
+
+impl Interceptor for ReplaceInterceptor {
+    fn after_execute(&self, result: &mut String) {
+        let lines: Vec<_> = result
+            .split('\n')
+            .map(|line| self.pattern.replace_all(line, &self.replacement))
+            .collect();
+        *result = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(args: &str, result: &str) -> String {
+        let mut result = result.to_string();
+        ReplaceInterceptor::try_new(args)
+            .unwrap()
+            .after_execute(&mut result);
+        result
+    }
+
+    #[test]
+    fn replace_in_each_line() {
+        assert_eq!(
+            replace(
+                r"elapsed:\s\d+ms elapsed: <elapsed>",
+                "a elapsed: 12ms\nb elapsed: 3ms"
+            ),
+            "a elapsed: <elapsed>\nb elapsed: <elapsed>"
+        );
+        // groups, and the rest of arguments is the replacement
+        assert_eq!(replace(r"(\w+)@\w+ $1 at host", "root@db1"), "root at host");
+        // without replacement, matches are removed
+        assert_eq!(replace(r"\s+$", "a  \nb\t"), "a\nb");
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(ReplaceInterceptor::try_new("").is_err());
+        assert!(ReplaceInterceptor::try_new("(a x").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{path::Path, sync::Arc};
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext, ResultFile};
+
+pub(crate) const PREFIX: &str = "RESULT_FILE";
+
+/// Write data exported by the statement to a file instead of inlining it, and
+/// record its size and MD5 checksum after the result. The path is relative to
+/// the case file. Useful for statements exporting large or binary payloads,
+/// like `SELECT ... INTO OUTFILE`.
+///
+/// The data is sent by [`Database::copy_out`], like for `COPY_TO`, which
+/// takes precedence if both are given.
+///
+/// ```sql
+/// -- SQLNESS RESULT_FILE output/t.bin
+/// COPY (SELECT * FROM t) TO STDOUT WITH (FORMAT binary);
+/// ```
+///
+/// [`Database::copy_out`]: crate::Database::copy_out
+pub(crate) struct ResultFileInterceptor {
+    file: ResultFile,
+}
+
+impl ResultFileInterceptor {
+    pub(crate) fn try_new(
+        args: &str,
+        base_dir: &Path,
+    ) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() {
+            return Err("missing file path".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            file: ResultFile {
+                name: args.to_string(),
+                path: base_dir.join(args),
+            },
+        }))
+    }
+}
+
+impl Interceptor for ResultFileInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.result_file = Some(self.file.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_relative_to_case() {
+        let mut context = QueryContext::default();
+        ResultFileInterceptor::try_new("output/t.bin", Path::new("cases/export"))
+            .unwrap()
+            .before_execute(&mut String::new(), &mut context);
+
+        let file = context.result_file.unwrap();
+        // the name is recorded as written, independent of where cases live
+        assert_eq!(file.name, "output/t.bin");
+        assert_eq!(file.path, Path::new("cases/export/output/t.bin"));
+    }
+
+    #[test]
+    fn missing_path() {
+        assert!(ResultFileInterceptor::try_new("", Path::new("cases")).is_err());
+    }
+}
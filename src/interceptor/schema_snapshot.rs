@@ -0,0 +1,50 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "SCHEMA_SNAPSHOT";
+
+/// Record schemas of tables after the query, as returned by
+/// [`Database::table_schema`]. E.g.
+///
+/// ```sql
+/// -- SQLNESS SCHEMA_SNAPSHOT t1 t2
+/// ALTER TABLE t1 ADD COLUMN c INT;
+/// ```
+///
+/// Schemas are recorded in a canonical form, see [`canonicalize`].
+///
+/// [`Database::table_schema`]: crate::Database::table_schema
+pub(crate) struct SchemaSnapshotInterceptor {
+    tables: Vec<String>,
+}
+
+impl SchemaSnapshotInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        let tables: Vec<_> = args.split_whitespace().map(str::to_string).collect();
+        if tables.is_empty() {
+            return Err("expect at least one table".to_string());
+        }
+
+        Ok(Arc::new(Self { tables }))
+    }
+}
+
+impl Interceptor for SchemaSnapshotInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.schema_snapshots.extend(self.tables.iter().cloned());
+    }
+}
+
+/// Trim lines, collapse consecutive whitespaces and remove empty lines, so
+/// insignificant formatting changes of servers don't break cases.
+pub(crate) fn canonicalize(schema: &str) -> String {
+    schema
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,65 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "SESSION";
+
+/// Run a statement before the query without recording its result. Used to
+/// configure the session, e.g.
+///
+/// ```sql
+/// -- SQLNESS SESSION SET time_zone='UTC';
+/// SELECT now();
+/// ```
+pub(crate) struct SessionInterceptor {
+    statement: String,
+}
+
+impl SessionInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() {
+            return Err("missing statement".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            statement: args.to_string(),
+        }))
+    }
+}
+
+impl Interceptor for SessionInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.session_statements.push(self.statement.clone());
+    }
+
+    fn changes_session(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_statements() {
+        let mut context = QueryContext::default();
+        for statement in ["SET time_zone='UTC';", "SET search_path TO s;"] {
+            let interceptor = SessionInterceptor::try_new(statement).unwrap();
+            assert!(interceptor.changes_session());
+            interceptor.before_execute(&mut String::new(), &mut context);
+        }
+
+        assert_eq!(
+            context.session_statements,
+            vec!["SET time_zone='UTC';", "SET search_path TO s;"]
+        );
+    }
+
+    #[test]
+    fn missing_statement() {
+        assert!(SessionInterceptor::try_new("").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "SNAPSHOT";
+
+/// Dump a table after the query, to verify side effects of DML without
+/// writing verification queries by hand. E.g.
+///
+/// ```sql
+/// -- SQLNESS SNAPSHOT table=t order_by=id
+/// UPDATE t SET v = v + 1 WHERE id > 10;
+/// ```
+///
+/// Rows are ordered by `order_by` (comma separated columns) so the dump is
+/// deterministic.
+pub(crate) struct SnapshotInterceptor {
+    table: String,
+    order_by: String,
+}
+
+impl SnapshotInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        let mut table = None;
+        let mut order_by = None;
+        for arg in args.split_whitespace() {
+            match arg.split_once('=') {
+                Some(("table", value)) if !value.is_empty() => table = Some(value),
+                Some(("order_by", value)) if !value.is_empty() => order_by = Some(value),
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+
+        match (table, order_by) {
+            (Some(table), Some(order_by)) => Ok(Arc::new(Self {
+                table: table.to_string(),
+                order_by: order_by.to_string(),
+            })),
+            _ => Err("expect `table=<table> order_by=<columns>`".to_string()),
+        }
+    }
+}
+
+impl Interceptor for SnapshotInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.table_snapshots.push(TableSnapshot {
+            table: self.table.clone(),
+            query: format!("SELECT * FROM {} ORDER BY {};", self.table, self.order_by),
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TableSnapshot {
+    pub table: String,
+    /// Query dumping the table.
+    pub query: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_query() {
+        let mut context = QueryContext::default();
+        SnapshotInterceptor::try_new("table=t order_by=id,v")
+            .unwrap()
+            .before_execute(&mut String::new(), &mut context);
+
+        assert_eq!(context.table_snapshots.len(), 1);
+        assert_eq!(context.table_snapshots[0].table, "t");
+        assert_eq!(
+            context.table_snapshots[0].query,
+            "SELECT * FROM t ORDER BY id,v;"
+        );
+    }
+
+    #[test]
+    fn invalid_args() {
+        for args in [
+            "",
+            "table=t",
+            "order_by=id",
+            "table= order_by=id",
+            "table=t order_by=id limit=1",
+        ] {
+            assert!(SnapshotInterceptor::try_new(args).is_err(), "{}", args);
+        }
+    }
+}
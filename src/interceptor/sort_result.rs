@@ -0,0 +1,87 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef};
+
+pub(crate) const PREFIX: &str = "SORT_RESULT";
+
+/// Sort lines of the result, for queries whose row order is undefined.
+///
+/// Two optional arguments are the number of lines to keep in place at the head
+/// (e.g. table header) and at the tail of the result:
+///
+/// ```sql
+/// -- SQLNESS SORT_RESULT 3 1
+/// SELECT * FROM t;
+/// ```
+pub(crate) struct SortResultInterceptor {
+    ignore_head: usize,
+    ignore_tail: usize,
+}
+
+impl SortResultInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        let mut numbers = args.split_whitespace().map(|arg| {
+            arg.parse::<usize>()
+                .map_err(|_| format!("expect number of lines, got `{}`", arg))
+        });
+        let ignore_head = numbers.next().transpose()?.unwrap_or(0);
+        let ignore_tail = numbers.next().transpose()?.unwrap_or(0);
+        if numbers.next().is_some() {
+            return Err("expect at most two arguments".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            ignore_head,
+            ignore_tail,
+        }))
+    }
+}
+
+impl Interceptor for SortResultInterceptor {
+    fn after_execute(&self, result: &mut String) {
+        let mut lines: Vec<_> = result.lines().collect();
+        if lines.len() <= self.ignore_head + self.ignore_tail {
+            return;
+        }
+
+        let tail_start = lines.len() - self.ignore_tail;
+        lines[self.ignore_head..tail_start].sort_unstable();
+        *result = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(args: &str, result: &str) -> String {
+        let mut result = result.to_string();
+        SortResultInterceptor::try_new(args)
+            .unwrap()
+            .after_execute(&mut result);
+        result
+    }
+
+    #[test]
+    fn sort_lines() {
+        assert_eq!(sort("", "c\na\nb"), "a\nb\nc");
+        // header kept in place
+        assert_eq!(sort("1", "id\n3\n1\n2"), "id\n1\n2\n3");
+        // header and footer kept in place
+        assert_eq!(
+            sort("2 1", "id\n--\n3\n1\n(2 rows)"),
+            "id\n--\n1\n3\n(2 rows)"
+        );
+        // too few lines to sort
+        assert_eq!(sort("2 1", "b\na"), "b\na");
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(SortResultInterceptor::try_new("x").is_err());
+        assert!(SortResultInterceptor::try_new("-1").is_err());
+        assert!(SortResultInterceptor::try_new("1 2 3").is_err());
+    }
+}
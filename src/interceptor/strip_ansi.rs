@@ -0,0 +1,99 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef};
+
+pub(crate) const PREFIX: &str = "STRIP_ANSI";
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// Remove ANSI escape sequences (e.g. colors) from the result.
+///
+/// Enabled for all queries by [`Config::strip_ansi`].
+///
+/// [`Config::strip_ansi`]: crate::Config::strip_ansi
+pub(crate) struct StripAnsiInterceptor;
+
+impl StripAnsiInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if !args.is_empty() {
+            return Err("expect no arguments".to_string());
+        }
+
+        Ok(Arc::new(Self))
+    }
+}
+
+impl Interceptor for StripAnsiInterceptor {
+    fn after_execute(&self, result: &mut String) {
+        if result.contains(ESC) {
+            *result = strip(result);
+        }
+    }
+}
+
+/// Remove CSI sequences like `ESC [ 31 m`, OSC sequences like
+/// `ESC ] ... BEL` and other two-char escapes.
+fn strip(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            output.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI, ends with a char in range `@` to `~`
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC, ends with BEL or `ESC \`
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == BEL {
+                        break;
+                    }
+                    if c == ESC && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_sequences() {
+        // colors
+        assert_eq!(strip("\x1b[1;31merror\x1b[0m: x"), "error: x");
+        // cursor movement and erasing
+        assert_eq!(strip("a\x1b[2Kb\x1b[1A"), "ab");
+        // hyperlinks, terminated by BEL or `ESC \`
+        assert_eq!(strip("\x1b]8;;http://a\x07link\x1b]8;;\x1b\\"), "link");
+        // two-char escapes
+        assert_eq!(strip("\x1b=x\x1b>"), "x");
+        assert_eq!(strip("plain"), "plain");
+    }
+
+    #[test]
+    fn unterminated_sequences() {
+        assert_eq!(strip("a\x1b[31"), "a");
+        assert_eq!(strip("a\x1b]title"), "a");
+        assert_eq!(strip("a\x1b"), "a");
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::sync::Arc;
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "USE";
+
+/// Switch the current database for this and all subsequent queries, via
+/// [`Database::use_database`]. E.g.
+///
+/// ```sql
+/// -- SQLNESS USE tenant_a
+/// SELECT * FROM t;
+/// ```
+///
+/// [`Database::use_database`]: crate::Database::use_database
+pub(crate) struct UseDatabaseInterceptor {
+    database: String,
+}
+
+impl UseDatabaseInterceptor {
+    pub(crate) fn try_new(args: &str) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() || args.contains(char::is_whitespace) {
+            return Err("expect exactly one database name".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            database: args.to_string(),
+        }))
+    }
+}
+
+impl Interceptor for UseDatabaseInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.database = Some(self.database.clone());
+    }
+
+    fn changes_session(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_database() {
+        let interceptor = UseDatabaseInterceptor::try_new("tenant_a").unwrap();
+        assert!(interceptor.changes_session());
+
+        let mut context = QueryContext::default();
+        interceptor.before_execute(&mut String::new(), &mut context);
+        assert_eq!(context.database.as_deref(), Some("tenant_a"));
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(UseDatabaseInterceptor::try_new("").is_err());
+        assert!(UseDatabaseInterceptor::try_new("a b").is_err());
+    }
+}
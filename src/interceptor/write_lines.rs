@@ -0,0 +1,74 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::interceptor::{Interceptor, InterceptorRef, QueryContext};
+
+pub(crate) const PREFIX: &str = "WRITE_LINES";
+
+/// Write a local file of InfluxDB line protocol to the ingestion endpoint of
+/// the server before the query, via [`Database::write_lines`]. The path is
+/// relative to the case file.
+///
+/// ```sql
+/// -- SQLNESS WRITE_LINES data/cpu.lp
+/// SELECT * FROM cpu ORDER BY time;
+/// ```
+///
+/// [`Database::write_lines`]: crate::Database::write_lines
+pub(crate) struct WriteLinesInterceptor {
+    path: PathBuf,
+}
+
+impl WriteLinesInterceptor {
+    pub(crate) fn try_new(
+        args: &str,
+        base_dir: &Path,
+    ) -> std::result::Result<InterceptorRef, String> {
+        if args.is_empty() {
+            return Err("missing file path".to_string());
+        }
+
+        Ok(Arc::new(Self {
+            path: base_dir.join(args),
+        }))
+    }
+}
+
+impl Interceptor for WriteLinesInterceptor {
+    fn before_execute(&self, _query: &mut String, context: &mut QueryContext) {
+        context.write_lines.push(self.path.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_paths() {
+        let base_dir = Path::new("cases/influx");
+        let mut context = QueryContext::default();
+        for args in ["data/cpu.lp", "data/mem.lp"] {
+            WriteLinesInterceptor::try_new(args, base_dir)
+                .unwrap()
+                .before_execute(&mut String::new(), &mut context);
+        }
+
+        assert_eq!(
+            context.write_lines,
+            vec![
+                PathBuf::from("cases/influx/data/cpu.lp"),
+                PathBuf::from("cases/influx/data/mem.lp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_path() {
+        assert!(WriteLinesInterceptor::try_new("", Path::new("cases")).is_err());
+    }
+}
@@ -22,6 +22,31 @@
 //! }
 //! ```
 //!
+//! Alternatively, [`test_cases!`] generates a test for each case, so they can
+//! be run and filtered by `cargo test` like other tests:
+//!
+//! ```rust, ignore, no_run
+//! sqlness::test_cases!("tests/cases", MyEnv::new());
+//! ```
+//!
+//! Or [`run_harness`] runs discovered cases as tests in a test target with
+//! `harness = false`, accepting the same arguments as libtest:
+//!
+//! ```rust, ignore, no_run
+//! #[tokio::main]
+//! async fn main() -> std::process::ExitCode {
+//!     sqlness::run_harness(config, MyEnv::new).await
+//! }
+//! ```
+//!
+//! To keep test binaries relocatable, [`embed_cases!`] embeds a case directory
+//! into the binary as [`EmbeddedCases`], which are extracted to run.
+//!
+//! Cases can also be constructed in memory by [`TestCaseBuilder`], and run
+//! against a [`Database`] directly, e.g. in fuzzers and property tests. With
+//! the `fuzz` feature, the `fuzz` module runs generated cases against an
+//! oracle database and persists failing ones as case files.
+//!
 //! [`Display`]: std::fmt::Display
 //!
 //! ## Directory organization
@@ -52,16 +77,175 @@
 //! different environments). All deeper layers are treated as the same. E.g.,
 //! both `sqlness/local/dml/basic.sql` and `sqlness/local/dml/another-dir/basic.sql`
 //! will be run under the `local` in the same pass.
+//!
+//! To keep other directories (e.g. shared `common/`) from being taken as
+//! environments, environments can be declared explicitly by
+//! [`discovery::MANIFEST_FILE`] under the root dir, along with their
+//! directories and env config files.
+//!
+//! Expected results are read from `<case>.result`. When results legitimately
+//! differ across platforms, a platform-specific file like `<case>.result.windows`
+//! can be placed beside it and will be preferred on that platform (the suffix is
+//! the value of [`std::env::consts::OS`]). Large expected results can be
+//! compressed as `<case>.result.gz` or `<case>.result.zst`, see
+//! [`Compression`].
+//!
+//! A case file can start with a front-matter of metadata (owner, description,
+//! issues and tags), which is carried into reports, see [`CaseMetadata`].
+//!
+//! Statements to set up every new connection (e.g. time zone or
+//! `search_path`) can be declared by the `init_statements` array in env config
+//! file, like `init_statements = ["SET time_zone = 'UTC'"]`. Their results are
+//! not recorded. See also [`Config::connection_mode`].
+//!
+//! Log files of the server can be declared by the `server_logs` array in env
+//! config file (relative to it), like `server_logs = ["data/server.log"]`.
+//! Their tails are attached to failed cases in reports, see
+//! [`Config::allure_results_dir`].
+//!
+//! Fixtures of a case can be placed beside it as `<case>.setup.sql` and
+//! `<case>.teardown.sql`. They are run before and after the case, and their
+//! results are not recorded.
+//!
+//! ## Interceptors
+//!
+//! Lines starting with the `interceptor_prefix` (default `-- SQLNESS`) are
+//! directives applied to the query following them. Long arguments can be
+//! continued on following lines starting with the prefix and a `+` (e.g.
+//! `-- SQLNESS+`), which are joined with a space. Supported interceptors:
+//!
+//! - `NAME <name>`: label the query. When results differ, queries are reported
+//!   with their names and line numbers.
+//! - `SESSION <statement>`: execute `<statement>` before the query without
+//!   recording its result, e.g. `-- SQLNESS SESSION SET time_zone='UTC'`.
+//! - `USE <database>`: switch to `<database>` (or schema, tenant) for this and
+//!   all subsequent queries, see [`Database::use_database`].
+//! - `BLOCKS <duration>`: assert the query doesn't return within `<duration>`
+//!   (like `500ms`, `2s`). It keeps running until a following query marked with
+//!   `UNBLOCKS` returns, or the case ends. Useful to test lock waits.
+//! - `UNBLOCKS`: wait for blocked queries after this query returns, and record
+//!   their results.
+//! - `CONNECTION <name>`: execute the query in the named connection (session),
+//!   opened on its first use in the case by [`Database::connection`], so
+//!   blocking and releasing queries can run in different sessions.
+//! - `COPY_FROM <file>`: stream `<file>` (relative to the case file) to the
+//!   query, which reads data from the client like `COPY ... FROM STDIN`. See
+//!   [`Database::copy_in`].
+//! - `COPY_TO <file>`: write data sent by the query, like `COPY ... TO STDOUT`,
+//!   to `<file>`. See [`Database::copy_out`].
+//! - `WRITE_LINES <file>`: write `<file>` (relative to the case file) of
+//!   InfluxDB line protocol to the ingestion endpoint before the query, for
+//!   time-series engines. See [`Database::write_lines`].
+//! - `RESULT_FILE <file>`: write data exported by the query to `<file>`
+//!   (relative to the case file) and record its size and MD5 checksum instead,
+//!   for large or binary payloads. See [`Database::copy_out`].
+//! - `SORT_RESULT [<ignore_head>] [<ignore_tail>]`: sort lines of the result,
+//!   except the first `<ignore_head>` and last `<ignore_tail>` lines (both
+//!   default to 0). Useful for queries without a defined row order.
+//! - `EXPECT_ERRCODE <code>`: assert the query fails with error code `<code>`
+//!   (e.g. SQLSTATE `42P01`), and record only the code instead of the error
+//!   message. Codes are extracted by [`Database::error_code`].
+//! - `ASSERT [rows=<n>] [cols=<n>] [types=<type>,...] [result=false]`: assert
+//!   the shape of the result, as described by [`Database::describe_result`],
+//!   or by column types of [`Database::query_typed`] if supported.
+//!   Failed assertions are recorded before the result, and `result=false`
+//!   records only the outcome of assertions instead of the result.
+//! - `SCHEMA_SNAPSHOT <table> [<table> ...]`: record schemas of tables after
+//!   the query, in a canonical form (whitespaces collapsed, empty lines
+//!   removed). Schemas are returned by [`Database::table_schema`].
+//! - `SNAPSHOT table=<table> order_by=<column>[,<column> ...]`: dump the table
+//!   ordered by the columns after the query, to verify side effects of DML.
+//! - `REPLACE <pattern> [<replacement>]`: replace matches of regular expression
+//!   `<pattern>` in each line of the result, e.g.
+//!   `-- SQLNESS REPLACE \d+ms <elapsed>`. Rules for all queries can be declared
+//!   by [`Config::normalize`].
+//! - `STRIP_ANSI`: remove ANSI escape sequences (e.g. colors) from the result.
+//!   Enabled for all queries by default, see [`Config::strip_ansi`].
+//! - `ARG <key>=<value> [<key>=<value> ...]`: forward arguments to the driver
+//!   for this query, e.g. `-- SQLNESS ARG protocol=http`, so one case can run
+//!   a statement over multiple protocols. See [`ExecutionContext::options`].
+//!
+//! Interceptors can also be applied to all cases under a directory, see
+//! [`Config::directory_rules`].
+//!
+//! Some directives apply to the whole case instead of a query:
+//!
+//! - `MIN_VERSION <version>`: skip this case if the server version is lower than
+//!   `<version>`. The server version is probed with [`Config::version_probe`].
+//! - `REQUIRE feature=<name> [feature=<name> ...]`: skip this case if any of the
+//!   features is unsupported. Supported features are declared by the `features`
+//!   array in env config file (e.g. `features = ["json"]`), plus those returned
+//!   by [`Database::features`].
+//! - `PRIORITY <n>`: cases with higher priority run first (default 0), e.g.
+//!   smoke cases. Priorities can also be assigned by [`Config::priority_rules`].
+//! - `DEPENDS_ON <case> [<case> ...]`: run `<case>` (path without extension,
+//!   relative to this case, e.g. `./setup_schema`) before this case. Cases are
+//!   ordered by their dependencies first, then priorities and paths. Cyclic
+//!   dependencies are rejected, and dependencies not collected in the same
+//!   environment (e.g. filtered out) are ignored.
+//! - `SERIAL`: run this case alone when cases run in parallel, e.g. for
+//!   DDL-heavy cases. See [`Config::parallelism`], which also explains how
+//!   concurrent cases get connections of their own.
 
+mod annotation;
+mod benchmark;
+mod blocking;
+mod cancel;
 mod case;
+mod case_builder;
+pub mod compare;
+mod compression;
 mod config;
 mod database;
+mod diff;
+pub mod discovery;
+mod embedded;
+mod encoding;
 mod environment;
 mod error;
+mod event;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+mod glob;
+mod gzip;
+mod harness;
+mod hash;
+mod http;
+mod interceptor;
+mod metadata;
+mod metrics;
+mod normalize;
+mod owners;
+mod patch;
+mod query_id;
+mod recorder;
+mod regex;
+mod report;
+mod resources;
 mod runner;
+mod shrink;
+mod splitter;
+mod trace;
+mod version;
 
-pub use config::{Config, ConfigBuilder};
-pub use database::Database;
-pub use environment::EnvController;
+pub use blocking::{BlockingAdapter, BlockingDatabase};
+pub use cancel::CancellationToken;
+pub use case::{CaseMetadata, ResultBlock, TestCase};
+pub use case_builder::{CaseOutput, TestCaseBuilder};
+pub use compression::Compression;
+pub use config::{
+    BenchmarkConfig, CaseOrder, Config, ConfigBuilder, ConfigProblem, ConnectionMode,
+    DirectoryRule, EnvSettings, MissingResultPolicy, NormalizeConfig, PriorityRule, ReplaceRule,
+};
+pub use database::{Database, ExecutionContext, ResultDescription, TypedColumn, TypedResult};
+pub use diff::{DiffAlgorithm, DiffStyle};
+pub use embedded::EmbeddedCases;
+pub use encoding::Encoding;
+pub use environment::{Connection, EnvController, ExternalEnv, ResourceUsage};
 pub use error::SqlnessError;
+pub use event::MessageFormat;
+pub use harness::run_harness;
+pub use recorder::Recorder;
 pub use runner::Runner;
+pub use splitter::{DelimiterSplitter, LineSplitter, QuerySplitter};
+pub use sqlness_macros::{embed_cases, test_cases};
@@ -0,0 +1,81 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Metadata of a run, included in reports and the `run_started` JSON message
+//! so archived results are self-describing.
+
+use tokio::process::Command;
+
+use crate::config::Config;
+
+pub(crate) struct RunMetadata {
+    /// Commit of the git repository in working directory.
+    pub git_sha: Option<String>,
+    /// Whether the git repository has uncommitted changes.
+    pub git_dirty: Option<bool>,
+    pub runner_version: &'static str,
+    pub host: String,
+    /// `os/arch` of the host.
+    pub platform: String,
+    /// Config in toml.
+    pub config: String,
+}
+
+impl RunMetadata {
+    pub(crate) async fn collect(config: &Config) -> Self {
+        let git_sha = git(&["rev-parse", "HEAD"])
+            .await
+            .map(|sha| sha.trim().to_string());
+        let git_dirty = git(&["status", "--porcelain"])
+            .await
+            .map(|status| !status.trim().is_empty());
+
+        Self {
+            git_sha,
+            git_dirty,
+            runner_version: env!("CARGO_PKG_VERSION"),
+            host: hostname().await,
+            platform: format!("{}/{}", std::env::consts::OS, std::env::consts::ARCH),
+            // through `Value` so tables are written after plain values, which
+            // toml requires regardless of the order of fields
+            config: toml::Value::try_from(config)
+                .and_then(|config| toml::to_string(&config))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Fields in display order, with values in text.
+    pub(crate) fn fields(&self) -> Vec<(&'static str, String)> {
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+        vec![
+            ("git_sha", or_unknown(self.git_sha.clone())),
+            (
+                "git_dirty",
+                or_unknown(self.git_dirty.map(|d| d.to_string())),
+            ),
+            ("runner_version", self.runner_version.to_string()),
+            ("host", self.host.clone()),
+            ("platform", self.platform.clone()),
+            ("config", self.config.clone()),
+        ]
+    }
+}
+
+/// Output of a git command, `None` if it fails, e.g. not in a repository.
+async fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout).ok()
+}
+
+async fn hostname() -> String {
+    if let Ok(host) = std::env::var("HOSTNAME") {
+        return host;
+    }
+    match tokio::fs::read_to_string("/etc/hostname").await {
+        Ok(host) if !host.trim().is_empty() => host.trim().to_string(),
+        _ => "unknown".to_string(),
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Metrics of a run in Prometheus text format, written for the node exporter
+//! textfile collector or pushed to a pushgateway, see [`Config::metrics_file`]
+//! and [`Config::pushgateway_url`].
+//!
+//! [`Config::metrics_file`]: crate::Config::metrics_file
+//! [`Config::pushgateway_url`]: crate::Config::pushgateway_url
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::fs::{rename, write};
+
+use crate::http;
+
+/// Path of the pushgateway API, appended to urls without a path.
+const PUSHGATEWAY_PATH: &str = "/metrics/job/sqlness";
+
+#[derive(Default)]
+struct Recorded {
+    /// (env, outcome) -> count
+    cases: BTreeMap<(String, String), usize>,
+    /// (env, case) -> elapsed
+    case_durations: BTreeMap<(String, String), Duration>,
+    env_durations: BTreeMap<String, Duration>,
+}
+
+/// Collector of metrics of a run.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl Metrics {
+    /// Record a finished case, `outcome` is the same as in events, e.g. `pass`.
+    pub(crate) fn record_case(&self, env: &str, case: &str, outcome: &str, elapsed: Duration) {
+        let mut recorded = self.recorded.lock().unwrap();
+        *recorded
+            .cases
+            .entry((env.to_string(), outcome.to_string()))
+            .or_default() += 1;
+        recorded
+            .case_durations
+            .insert((env.to_string(), case.to_string()), elapsed);
+    }
+
+    pub(crate) fn record_env(&self, env: &str, elapsed: Duration) {
+        self.recorded
+            .lock()
+            .unwrap()
+            .env_durations
+            .insert(env.to_string(), elapsed);
+    }
+
+    /// Render metrics of a run which took `elapsed` and succeeded or not.
+    pub(crate) fn render(&self, elapsed: Duration, success: bool) -> String {
+        let recorded = self.recorded.lock().unwrap();
+        let mut text = String::new();
+        header(
+            &mut text,
+            "sqlness_cases",
+            "Cases of the last run by outcome.",
+        );
+        for ((env, outcome), count) in &recorded.cases {
+            sample(
+                &mut text,
+                "sqlness_cases",
+                &[("env", env), ("outcome", outcome)],
+                count,
+            );
+        }
+        header(
+            &mut text,
+            "sqlness_case_duration_seconds",
+            "Duration of cases in the last run.",
+        );
+        for ((env, case), elapsed) in &recorded.case_durations {
+            sample(
+                &mut text,
+                "sqlness_case_duration_seconds",
+                &[("env", env), ("case", case)],
+                elapsed.as_secs_f64(),
+            );
+        }
+        header(
+            &mut text,
+            "sqlness_env_duration_seconds",
+            "Duration of environments in the last run.",
+        );
+        for (env, elapsed) in &recorded.env_durations {
+            sample(
+                &mut text,
+                "sqlness_env_duration_seconds",
+                &[("env", env)],
+                elapsed.as_secs_f64(),
+            );
+        }
+        header(
+            &mut text,
+            "sqlness_run_duration_seconds",
+            "Duration of the last run.",
+        );
+        sample(
+            &mut text,
+            "sqlness_run_duration_seconds",
+            &[],
+            elapsed.as_secs_f64(),
+        );
+        header(
+            &mut text,
+            "sqlness_run_success",
+            "Whether the last run succeeded.",
+        );
+        sample(&mut text, "sqlness_run_success", &[], success as u8);
+        header(
+            &mut text,
+            "sqlness_last_run_timestamp_seconds",
+            "Unix time the last run finished.",
+        );
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        sample(&mut text, "sqlness_last_run_timestamp_seconds", &[], now);
+
+        text
+    }
+
+    /// Write `text` to `path` atomically, so the textfile collector never
+    /// reads partial files.
+    pub(crate) async fn write_file(path: &str, text: &str) -> std::io::Result<()> {
+        let mut temp_path = Path::new(path).as_os_str().to_owned();
+        temp_path.push(".tmp");
+        write(&temp_path, text).await?;
+        rename(&temp_path, path).await
+    }
+
+    /// Replace metrics of the sqlness job in the pushgateway at `url`.
+    pub(crate) async fn push(url: &str, text: &str) -> std::io::Result<()> {
+        http::send("PUT", url, PUSHGATEWAY_PATH, "text/plain", text).await
+    }
+}
+
+fn header(text: &mut String, name: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} gauge", name).unwrap();
+}
+
+fn sample(text: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    text.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect();
+        write!(text, "{{{}}}", labels.join(",")).unwrap();
+    }
+    writeln!(text, " {}", value).unwrap();
+}
+
+/// Escape label value as required by the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
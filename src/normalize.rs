@@ -0,0 +1,259 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Normalization of outputs and expected results, applied when writing outputs
+//! and before comparison, to avoid false diffs.
+
+use std::time::Duration;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::config::Config;
+
+/// Normalize `text` according to `config`.
+pub(crate) fn normalize(config: &Config, text: &str) -> String {
+    let mut text = if config.normalize_line_endings {
+        text.replace("\r\n", "\n")
+    } else {
+        text.to_string()
+    };
+    if config.trim_trailing_whitespace {
+        text = text
+            .split('\n')
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    if config.normalize_tables {
+        text = realign_tables(&text);
+    }
+
+    text
+}
+
+/// Placeholder of elapsed times when comparing, see [`Config::record_elapsed`].
+const ELAPSED_PLACEHOLDER: &str = "<elapsed>";
+
+/// Line recording `elapsed` time of a query, see [`Config::record_elapsed`].
+pub(crate) fn elapsed_line(comment_prefix: &str, elapsed: Duration) -> String {
+    format!("{} elapsed: {}ms", comment_prefix, elapsed.as_millis())
+}
+
+/// Replace elapsed times recorded in `text` with a placeholder, if
+/// [`Config::record_elapsed`] is set.
+pub(crate) fn mask_elapsed(config: &Config, text: &str) -> String {
+    if !config.record_elapsed {
+        return text.to_string();
+    }
+
+    let prefix = format!("{} elapsed: ", config.comment_prefix);
+    text.split('\n')
+        .map(|line| {
+            let is_elapsed = line
+                .strip_prefix(&prefix)
+                .and_then(|elapsed| elapsed.strip_suffix("ms"))
+                .map(|millis| !millis.is_empty() && millis.chars().all(|c| c.is_ascii_digit()))
+                .unwrap_or(false);
+            if is_elapsed {
+                format!("{}{}", prefix, ELAPSED_PLACEHOLDER)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Re-render ASCII tables (like `+---+` borders and `| a |` rows) with column
+/// widths by display width, so wide characters (e.g. CJK) don't cause
+/// alignment differences. Tables with inconsistent columns are kept as is.
+fn realign_tables(text: &str) -> String {
+    let lines: Vec<_> = text.split('\n').collect();
+    let mut output = Vec::with_capacity(lines.len());
+    let mut start = 0;
+    while start < lines.len() {
+        let end = start
+            + lines[start..]
+                .iter()
+                .take_while(|line| parse_table_line(line).is_some())
+                .count();
+        if end == start {
+            output.push(lines[start].to_string());
+            start += 1;
+            continue;
+        }
+
+        let table: Vec<_> = lines[start..end]
+            .iter()
+            .map(|line| parse_table_line(line).unwrap())
+            .collect();
+        match render_table(&table) {
+            Some(rendered) => output.extend(rendered),
+            None => output.extend(lines[start..end].iter().map(|line| line.to_string())),
+        }
+        start = end;
+    }
+
+    output.join("\n")
+}
+
+enum TableLine<'a> {
+    /// Border line, with its fill char like `-` or `=`.
+    Border(char),
+    Row(Vec<&'a str>),
+}
+
+fn parse_table_line(line: &str) -> Option<TableLine<'_>> {
+    let line = line.trim_end();
+    if line.len() >= 2 && line.starts_with('+') && line.ends_with('+') {
+        let fill = if line.contains('=') { '=' } else { '-' };
+        return line
+            .chars()
+            .all(|c| c == '+' || c == fill)
+            .then_some(TableLine::Border(fill));
+    }
+    if line.len() >= 2 && line.starts_with('|') && line.ends_with('|') {
+        let cells = line[1..line.len() - 1].split('|').map(str::trim).collect();
+        return Some(TableLine::Row(cells));
+    }
+
+    None
+}
+
+fn render_table(table: &[TableLine]) -> Option<Vec<String>> {
+    let mut widths: Vec<usize> = vec![];
+    for line in table {
+        if let TableLine::Row(cells) = line {
+            if widths.is_empty() {
+                widths = vec![0; cells.len()];
+            }
+            if cells.len() != widths.len() {
+                return None;
+            }
+            for (width, cell) in widths.iter_mut().zip(cells) {
+                *width = (*width).max(cell.width());
+            }
+        }
+    }
+    if widths.is_empty() {
+        return None;
+    }
+
+    let rendered = table
+        .iter()
+        .map(|line| match line {
+            TableLine::Border(fill) => widths.iter().fold("+".to_string(), |line, width| {
+                line + &fill.to_string().repeat(width + 2) + "+"
+            }),
+            TableLine::Row(cells) => widths
+                .iter()
+                .zip(cells)
+                .fold("|".to_string(), |line, (width, cell)| {
+                    line + " " + cell + &" ".repeat(width - cell.width()) + " |"
+                }),
+        })
+        .collect();
+
+    Some(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigBuilder;
+
+    #[test]
+    fn normalize_whitespace() {
+        let text = "a  \r\nb\t\r\n\r\nc";
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(normalize(&config, text), text);
+
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .normalize_line_endings(true)
+            .build()
+            .unwrap();
+        assert_eq!(normalize(&config, text), "a  \nb\t\n\nc");
+
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .normalize_line_endings(true)
+            .trim_trailing_whitespace(true)
+            .build()
+            .unwrap();
+        assert_eq!(normalize(&config, text), "a\nb\n\nc");
+    }
+
+    #[test]
+    fn realign_wide_chars() {
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .normalize_tables(true)
+            .build()
+            .unwrap();
+        let text = "\
++----+------+
+| id | name |
++====+======+
+| 1  | 你好 |
+| 22 | a    |
++----+------+
+Affected Rows: 2";
+        assert_eq!(
+            normalize(&config, text),
+            "\
++----+------+
+| id | name |
++====+======+
+| 1  | 你好 |
+| 22 | a    |
++----+------+
+Affected Rows: 2"
+        );
+
+        let misaligned = "\
++---+---+
+| id | name |
++---+---+
+| 1 | 你好 |
++---+---+";
+        assert_eq!(
+            normalize(&config, misaligned),
+            "\
++----+------+
+| id | name |
++----+------+
+| 1  | 你好 |
++----+------+"
+        );
+
+        // inconsistent columns are kept as is
+        let text = "| a | b |\n| c |";
+        assert_eq!(normalize(&config, text), text);
+    }
+
+    #[test]
+    fn mask_elapsed_lines() {
+        let text = format!(
+            "{}\nSELECT 1;\n-- elapsed: 12msx\n-- elapsed: ms",
+            elapsed_line("--", Duration::from_millis(12))
+        );
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(mask_elapsed(&config, &text), text);
+
+        let config = ConfigBuilder::default()
+            .case_dir("cases".to_string())
+            .record_elapsed(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            mask_elapsed(&config, &text),
+            "-- elapsed: <elapsed>\nSELECT 1;\n-- elapsed: 12msx\n-- elapsed: ms"
+        );
+    }
+}
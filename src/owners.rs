@@ -0,0 +1,59 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Owners of cases, declared in a `CODEOWNERS` style file, see
+//! [`Config::owners_file`].
+//!
+//! [`Config::owners_file`]: crate::Config::owners_file
+
+use std::path::Path;
+
+use crate::{
+    config::Config,
+    error::{Result, SqlnessError},
+    glob,
+};
+
+/// Rules like `env/dml/ @storage-team @alice`. The last matching rule wins.
+#[derive(Debug, Default)]
+pub(crate) struct Owners {
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl Owners {
+    pub(crate) async fn load(config: &Config) -> Result<Self> {
+        let path = match &config.owners_file {
+            Some(path) => Path::new(path),
+            None => return Ok(Self::default()),
+        };
+        let content =
+            tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| SqlnessError::ReadPath {
+                    source: e,
+                    path: path.to_path_buf(),
+                })?;
+
+        let rules = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let pattern = fields.next()?.to_string();
+                Some((pattern, fields.map(str::to_string).collect()))
+            })
+            .collect();
+
+        Ok(Self { rules })
+    }
+
+    /// Owners of the case file at `path`, relative to [`Config::case_dir`].
+    pub(crate) fn lookup(&self, path: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| glob::matches(pattern, path))
+            .map(|(_, owners)| owners.clone())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Unified diff of expected results, see [`Config::update_patch`].
+//!
+//! [`Config::update_patch`]: crate::Config::update_patch
+
+use std::ops::Range;
+use std::path::Path;
+
+use crate::diff::{diff, hunks, DiffAlgorithm};
+
+/// Lines of context around changes.
+const CONTEXT_LINES: usize = 3;
+
+/// Unified diff turning `old` into `new`, both of file `path`. Return an empty
+/// string if they are the same.
+pub(crate) fn unified_diff(path: &Path, old: &str, new: &str) -> String {
+    let old_lines: Vec<_> = old.lines().collect();
+    let new_lines: Vec<_> = new.lines().collect();
+    let lines = diff(DiffAlgorithm::Myers, &old_lines, &new_lines);
+    let hunks = hunks(&lines, CONTEXT_LINES);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let path = path.display();
+    let mut patch = format!("--- a/{}\n+++ b/{}\n", path, path);
+    for Range { start, end } in hunks {
+        let count = |lines: &[(char, &str)], excluded: char| {
+            lines.iter().filter(|(sign, _)| *sign != excluded).count()
+        };
+        let (old_len, new_len) = (
+            count(&lines[start..end], '+'),
+            count(&lines[start..end], '-'),
+        );
+        // an empty range starts at the line before it
+        let old_start = count(&lines[..start], '+') + usize::from(old_len > 0);
+        let new_start = count(&lines[..start], '-') + usize::from(new_len > 0);
+        patch.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        ));
+        for (sign, line) in &lines[start..end] {
+            patch.push(*sign);
+            patch.push_str(line);
+            patch.push('\n');
+        }
+    }
+
+    patch
+}
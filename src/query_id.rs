@@ -0,0 +1,28 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Unique IDs of executed queries, to correlate them with server logs.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Generate a query ID like `18c2b6e3a01-42`, which is the start time of this
+/// process (in milliseconds, hex) and a sequence number.
+pub(crate) fn next() -> String {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    let run_id = RUN_ID.get_or_init(|| {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        format!("{:x}", millis)
+    });
+
+    format!("{}-{}", run_id, SEQUENCE.fetch_add(1, Ordering::Relaxed))
+}
@@ -0,0 +1,134 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::path::Path;
+
+use tokio::{
+    fs::OpenOptions,
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+};
+
+use crate::{
+    case::TestCase, config::Config, database::Database, discovery::EnvDirs, error::Result,
+    normalize::normalize,
+};
+
+const QUIT_COMMAND: &str = "\\q";
+const PROMPT: &str = "sqlness> ";
+
+/// Build cases from an interactive session.
+///
+/// Queries are read from stdin and executed against the database one by one.
+/// Each query is appended to the case file, and its result to the expected
+/// result file, so the recorded case passes when run by [`Runner`].
+///
+/// Interceptor lines typed before a query are applied to it, just like in case
+/// files.
+///
+/// ```rust, ignore, no_run
+/// async fn record_case() {
+///     let recorder = Recorder::new(config, db);
+///     recorder.record("sqlness/local/dml/basic").await.unwrap();
+/// }
+/// ```
+///
+/// [`Runner`]: crate::Runner
+pub struct Recorder<D: Database> {
+    config: Config,
+    db: D,
+}
+
+impl<D: Database> Recorder<D> {
+    pub fn new(config: Config, db: D) -> Self {
+        Self { config, db }
+    }
+
+    /// Record queries typed in stdin into case at `path` (without extension),
+    /// until EOF or `\q`.
+    pub async fn record<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.record_from(BufReader::new(io::stdin()), path).await
+    }
+
+    /// Same as [`Self::record`], but read queries from `input`.
+    pub async fn record_from<R, P>(&self, input: R, path: P) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut case_file =
+            Self::open_append(path.with_extension(&self.config.test_case_extension)).await?;
+        let mut result_file =
+            Self::open_append(path.with_extension(&self.config.expect_result_extension)).await?;
+        let mut stdout = io::stdout();
+
+        let splitter = self.config.splitter();
+        let env_dirs = EnvDirs::resolve(&self.config).await?;
+        let mut pending = String::new();
+        let mut lines = input.lines();
+        loop {
+            stdout.write_all(PROMPT.as_bytes()).await?;
+            stdout.flush().await?;
+            let line = match lines.next_line().await? {
+                Some(line) if line.trim() != QUIT_COMMAND => line,
+                _ => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            pending.push_str(&line);
+            pending.push('\n');
+            // directives and comments don't end a query
+            let is_comment = line.starts_with(&self.config.interceptor_prefix)
+                || line.starts_with(&self.config.comment_prefix);
+            if is_comment || !splitter.is_query_end(&line) {
+                continue;
+            }
+
+            let query = std::mem::take(&mut pending);
+            let case = TestCase::parse(
+                path.display().to_string(),
+                query.as_bytes(),
+                &self.config,
+                &env_dirs,
+            )
+            .await;
+            let case = match case {
+                Ok(case) => case,
+                Err(e) => {
+                    println!("Discard invalid query, error: {}", e);
+                    continue;
+                }
+            };
+
+            let mut result = vec![];
+            case.execute(&self.db, &mut result).await?;
+            if let Ok(text) = std::str::from_utf8(&result) {
+                result = normalize(&self.config, text).into_bytes();
+            }
+            stdout.write_all(&result).await?;
+
+            let encoding = self.config.file_encoding;
+            case_file.write_all(&encoding.encode(&query)).await?;
+            case_file.write_all("\n".as_bytes()).await?;
+            match std::str::from_utf8(&result) {
+                Ok(text) => result_file.write_all(&encoding.encode(text)).await?,
+                Err(_) => result_file.write_all(&result).await?,
+            }
+        }
+
+        case_file.flush().await?;
+        result_file.flush().await?;
+        println!("\nRecorded to {}", path.display());
+
+        Ok(())
+    }
+
+    async fn open_append<P: AsRef<Path>>(path: P) -> Result<tokio::fs::File> {
+        Ok(OpenOptions::default()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?)
+    }
+}
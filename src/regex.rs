@@ -0,0 +1,754 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! A small regular expression engine for replacing parts of results, see the
+//! `REPLACE` interceptor. Patterns are compiled to a program run by a Pike VM,
+//! which takes time linear in the length of the text (for a given pattern) and
+//! constant stack, so long lines of results are fine.
+//!
+//! Supported syntax: literals, `.`, classes like `[a-z_]` and `[^0-9]`, `\d`,
+//! `\w`, `\s` (and negated `\D`, `\W`, `\S`), `\b`, anchors `^` and `$`,
+//! groups `(...)` and `(?:...)` with alternation `|`, and quantifiers `*`,
+//! `+`, `?`, `{n}`, `{n,}` and `{n,m}` (lazy with a trailing `?`, bounds up to
+//! 1000).
+
+/// Start and end of each group, group 0 is the whole match.
+type Captures = Vec<Option<(usize, usize)>>;
+
+/// Max `n` and `m` of bounds `{n,m}`.
+const MAX_REPEAT: usize = 1000;
+/// Max instructions of a compiled pattern, as repeated nodes are copied.
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+#[derive(Debug)]
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+    groups: usize,
+}
+
+/// Instruction of the compiled program.
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    /// Continue at both, preferring the first.
+    Split(usize, usize),
+    Jump(usize),
+    /// Record the position in capture slot `n`, `2 * group` for the start of
+    /// a group and `2 * group + 1` for the end.
+    Save(usize),
+    Match,
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    /// `.`, any char except line feed.
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    Group {
+        alternatives: Vec<Vec<Node>>,
+        /// `None` for non-capturing groups.
+        index: Option<usize>,
+    },
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+/// Chars in (or not in, if negated) any of the ranges.
+#[derive(Debug, Clone)]
+struct ClassItem {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl ClassItem {
+    fn range(start: char, end: char) -> Self {
+        Self {
+            ranges: vec![(start, end)],
+            negated: false,
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&c))
+            != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> std::result::Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unmatched `)` in pattern `{}`", pattern));
+        }
+
+        if group_len(&alternatives, true) > MAX_PROGRAM_LEN {
+            return Err(format!("pattern `{}` is too large", pattern));
+        }
+        let mut program = vec![];
+        compile_group(&alternatives, Some(0), &mut program);
+        program.push(Inst::Match);
+
+        Ok(Self {
+            program,
+            groups: parser.groups,
+        })
+    }
+
+    /// Replace all matches in `text` with `replacement`, in which `$n` or
+    /// `${n}` is the text of group `n` (`$0` for the whole match) and `$$` is
+    /// a literal `$`.
+    pub(crate) fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut copied = 0;
+        let mut start = 0;
+        while start <= text.len() {
+            let captures = match self.find_at(text, start) {
+                Some(captures) => captures,
+                None => break,
+            };
+            let (match_start, match_end) = captures[0].unwrap();
+            replaced.push_str(&text[copied..match_start]);
+            expand(replacement, text, &captures, &mut replaced);
+            copied = match_end;
+            start = if match_end > match_start {
+                match_end
+            } else {
+                // an empty match, move on to the next char
+                let next = text[match_end..].chars().next().map_or(1, char::len_utf8);
+                replaced.push_str(text.get(match_end..match_end + next).unwrap_or_default());
+                copied = match_end + next;
+                match_end + next
+            };
+        }
+        if copied < text.len() {
+            replaced.push_str(&text[copied..]);
+        }
+
+        replaced
+    }
+
+    /// Captures of the leftmost match at or after `start`, if any. Among
+    /// matches starting at the same position, the one preferred by
+    /// alternation order and greediness wins, like backtracking engines.
+    fn find_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let slots = 2 * (self.groups + 1);
+        let mut threads = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            // a new match may start here unless one is found, with the lowest
+            // priority, so the leftmost match wins
+            if matched.is_none() {
+                self.add_thread(&mut threads, 0, vec![None; slots], text, pos);
+            }
+            if threads.list.is_empty() && matched.is_some() {
+                break;
+            }
+            let c = text[pos..].chars().next();
+            let end = pos + c.map_or(0, char::len_utf8);
+            for (pc, slots) in std::mem::take(&mut threads.list) {
+                let consumed = match (&self.program[pc], c) {
+                    (Inst::Match, _) => {
+                        matched = Some(slots);
+                        // threads with lower priority are cut off
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => c == *expected,
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class { items, negated }, Some(c)) => {
+                        items.iter().any(|item| item.matches(c)) != *negated
+                    }
+                    _ => false,
+                };
+                if consumed {
+                    self.add_thread(&mut next, pc + 1, slots, text, end);
+                }
+            }
+            if c.is_none() {
+                break;
+            }
+            std::mem::swap(&mut threads, &mut next);
+            next.clear();
+            pos = end;
+        }
+
+        matched.map(|slots| {
+            (0..=self.groups)
+                .map(|group| match (slots[2 * group], slots[2 * group + 1]) {
+                    (Some(start), Some(end)) => Some((start, end)),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// Add the thread at `pc` to `threads`, following instructions which
+    /// don't consume chars at `pos`, in priority order.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        slots: Vec<Option<usize>>,
+        text: &str,
+        pos: usize,
+    ) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if threads.visited[pc] {
+                continue;
+            }
+            threads.visited[pc] = true;
+            let prev = text[..pos].chars().next_back();
+            let next = text[pos..].chars().next();
+            match &self.program[pc] {
+                Inst::Jump(target) => stack.push((*target, slots)),
+                Inst::Split(first, second) => {
+                    // popped first
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::LineStart => {
+                    if prev.is_none() || prev == Some('\n') {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                Inst::LineEnd => {
+                    if next.is_none() || next == Some('\n') {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                Inst::WordBoundary => {
+                    if is_word(prev) != is_word(next) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => threads.list.push((pc, slots)),
+            }
+        }
+    }
+}
+
+/// Threads of the VM at a position, in priority order.
+struct Threads {
+    list: Vec<(usize, Vec<Option<usize>>)>,
+    /// Instructions already added at this position.
+    visited: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            list: vec![],
+            visited: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.visited.iter_mut().for_each(|visited| *visited = false);
+    }
+}
+
+/// Whether `c` is matched by `\w`, which `\b` is defined by too.
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(|c| WORD.iter().any(|(start, end)| (*start..=*end).contains(&c)))
+}
+
+/// Count of instructions compiling `node` takes.
+fn node_len(node: &Node) -> usize {
+    match node {
+        Node::Group {
+            alternatives,
+            index,
+        } => group_len(alternatives, index.is_some()),
+        Node::Repeat { node, min, max, .. } => {
+            let len = node_len(node);
+            match max {
+                None => min.saturating_add(1).saturating_mul(len).saturating_add(2),
+                Some(max) => max.saturating_mul(len).saturating_add(max - min),
+            }
+        }
+        _ => 1,
+    }
+}
+
+/// Count of instructions [`compile_group`] takes.
+fn group_len(alternatives: &[Vec<Node>], capturing: bool) -> usize {
+    let nodes = alternatives
+        .iter()
+        .flatten()
+        .map(node_len)
+        .fold(0, usize::saturating_add);
+    let branches = 2 * alternatives.len().saturating_sub(1);
+
+    nodes
+        .saturating_add(branches)
+        .saturating_add(if capturing { 2 } else { 0 })
+}
+
+/// Compile alternatives of a group, recorded as group `index` if any.
+fn compile_group(alternatives: &[Vec<Node>], index: Option<usize>, program: &mut Vec<Inst>) {
+    if let Some(index) = index {
+        program.push(Inst::Save(2 * index));
+    }
+    let mut jumps = vec![];
+    for (i, alternative) in alternatives.iter().enumerate() {
+        let split = (i + 1 < alternatives.len()).then(|| {
+            program.push(Inst::Split(program.len() + 1, 0));
+            program.len() - 1
+        });
+        for node in alternative {
+            compile(node, program);
+        }
+        if let Some(split) = split {
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            let next = program.len();
+            if let Inst::Split(_, second) = &mut program[split] {
+                *second = next;
+            }
+        }
+    }
+    let end = program.len();
+    for jump in jumps {
+        program[jump] = Inst::Jump(end);
+    }
+    if let Some(index) = index {
+        program.push(Inst::Save(2 * index + 1));
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class { items, negated } => program.push(Inst::Class {
+            items: items.clone(),
+            negated: *negated,
+        }),
+        Node::LineStart => program.push(Inst::LineStart),
+        Node::LineEnd => program.push(Inst::LineEnd),
+        Node::WordBoundary => program.push(Inst::WordBoundary),
+        Node::Group {
+            alternatives,
+            index,
+        } => compile_group(alternatives, *index, program),
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            let split = |body, out| {
+                if *greedy {
+                    Inst::Split(body, out)
+                } else {
+                    Inst::Split(out, body)
+                }
+            };
+            match max {
+                None => {
+                    // L: split(body, out); body; jump L
+                    let start = program.len();
+                    program.push(Inst::Jump(0));
+                    compile(node, program);
+                    program.push(Inst::Jump(start));
+                    let out = program.len();
+                    program[start] = split(start + 1, out);
+                }
+                Some(max) => {
+                    // split(body, out); body; split(body, out); body; ...
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Jump(0));
+                        compile(node, program);
+                    }
+                    let out = program.len();
+                    for start in splits {
+                        program[start] = split(start + 1, out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Append `replacement` with group references expanded to `output`.
+fn expand(replacement: &str, text: &str, captures: &Captures, output: &mut String) {
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            output.push(c);
+            continue;
+        }
+
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut digits = String::new();
+        while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(*digit);
+            chars.next();
+        }
+        if braced && chars.peek() == Some(&'}') {
+            chars.next();
+        }
+        match digits.parse::<usize>() {
+            Ok(group) => {
+                if let Some(Some((start, end))) = captures.get(group) {
+                    output.push_str(&text[*start..*end]);
+                }
+            }
+            Err(_) if !braced && chars.peek() == Some(&'$') => {
+                chars.next();
+                output.push('$');
+            }
+            Err(_) => output.push('$'),
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> std::result::Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> std::result::Result<Vec<Node>, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let node = self.atom()?;
+            nodes.push(self.quantified(node)?);
+        }
+
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> std::result::Result<Node, String> {
+        let c = self.next().unwrap();
+        let node = match c {
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let alternatives = self.alternatives()?;
+                if self.next() != Some(')') {
+                    return Err("unclosed group".to_string());
+                }
+                Node::Group {
+                    alternatives,
+                    index,
+                }
+            }
+            '[' => self.class()?,
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '\\' => match self.next() {
+                Some('b') => Node::WordBoundary,
+                Some(c) => match Self::perl_class(c) {
+                    Some(item) => Node::Class {
+                        items: vec![item],
+                        negated: false,
+                    },
+                    None => Node::Char(Self::escaped(c)),
+                },
+                None => return Err("trailing `\\`".to_string()),
+            },
+            '*' | '+' | '?' => return Err(format!("nothing to repeat before `{}`", c)),
+            c => Node::Char(c),
+        };
+
+        Ok(node)
+    }
+
+    fn class(&mut self) -> std::result::Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = vec![];
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or_else(|| "unclosed `[`".to_string())?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let start = if c == '\\' {
+                let escaped = self.next().ok_or_else(|| "trailing `\\`".to_string())?;
+                if let Some(item) = Self::perl_class(escaped) {
+                    items.push(item);
+                    continue;
+                }
+                Self::escaped(escaped)
+            } else {
+                c
+            };
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']');
+            if is_range {
+                self.pos += 1;
+                let end = match self.next() {
+                    Some('\\') => Self::escaped(self.next().unwrap_or('\\')),
+                    Some(end) => end,
+                    None => return Err("unclosed `[`".to_string()),
+                };
+                if end < start {
+                    return Err(format!("invalid range `{}-{}`", start, end));
+                }
+                items.push(ClassItem::range(start, end));
+            } else {
+                items.push(ClassItem::range(start, start));
+            }
+        }
+
+        Ok(Node::Class { items, negated })
+    }
+
+    fn perl_class(c: char) -> Option<ClassItem> {
+        let (ranges, negated) = match c {
+            'd' => (DIGIT, false),
+            'w' => (WORD, false),
+            's' => (SPACE, false),
+            'D' => (DIGIT, true),
+            'W' => (WORD, true),
+            'S' => (SPACE, true),
+            _ => return None,
+        };
+
+        Some(ClassItem {
+            ranges: ranges.to_vec(),
+            negated,
+        })
+    }
+
+    fn escaped(c: char) -> char {
+        match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            c => c,
+        }
+    }
+
+    fn quantified(&mut self, node: Node) -> std::result::Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.bounds()? {
+                Some(bounds) => bounds,
+                None => return Ok(node),
+            },
+            _ => return Ok(node),
+        };
+        // skip the quantifier, or the `}` of bounds
+        self.pos += 1;
+        if matches!(node, Node::LineStart | Node::LineEnd | Node::WordBoundary) {
+            return Err("nothing to repeat".to_string());
+        }
+        let greedy = self.peek() != Some('?');
+        if !greedy {
+            self.pos += 1;
+        }
+
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Parse `{n}`, `{n,}` or `{n,m}` and stop at the `}`. Return `None` and
+    /// keep the position if it's not a valid one, then `{` is a literal.
+    /// Bounds larger than [`MAX_REPEAT`] are errors.
+    fn bounds(&mut self) -> std::result::Result<Option<(usize, Option<usize>)>, String> {
+        let close = match self.chars[self.pos..].iter().position(|c| *c == '}') {
+            Some(offset) => self.pos + offset,
+            None => return Ok(None),
+        };
+        let inner: String = self.chars[self.pos + 1..close].iter().collect();
+        let parse = |bound: &str| -> Option<usize> { bound.parse().ok() };
+        let bounds = match inner.split_once(',') {
+            None => parse(&inner).map(|n| (n, Some(n))),
+            Some((min, "")) => parse(min).map(|min| (min, None)),
+            Some((min, max)) => match (parse(min), parse(max)) {
+                (Some(min), Some(max)) if min <= max => Some((min, Some(max))),
+                _ => None,
+            },
+        };
+        let (min, max) = match bounds {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(format!(
+                "repetition `{{{}}}` exceeds the limit {}",
+                inner, MAX_REPEAT
+            ));
+        }
+        self.pos = close;
+
+        Ok(Some((min, max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(pattern: &str, text: &str, replacement: &str) -> String {
+        Regex::new(pattern).unwrap().replace_all(text, replacement)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert_eq!(replace("ab", "xabyab", "-"), "x-y-");
+        assert_eq!(replace("a.c", "abc a\nc", "-"), "- a\nc");
+        assert_eq!(replace("[a-c]", "abcd", "-"), "---d");
+        assert_eq!(replace("[^a-c]", "abcd", "-"), "abc-");
+        assert_eq!(replace(r"\d+", "id 123, 45", "N"), "id N, N");
+        assert_eq!(replace(r"\w+\s", "foo bar", "-"), "-bar");
+        assert_eq!(replace(r"\.", "a.b", "-"), "a-b");
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(replace("^a", "aa\naa", "-"), "-a\n-a");
+        assert_eq!(replace("a$", "aa\naa", "-"), "a-\na-");
+        assert_eq!(replace(r"\bid\b", "id idx _id id", "-"), "- idx _id -");
+    }
+
+    #[test]
+    fn groups_and_alternation() {
+        assert_eq!(replace("a|ab", "ab", "-"), "-b");
+        assert_eq!(replace("ab|a", "ab", "-"), "-");
+        assert_eq!(replace("(a|b)c", "acbcc", "$1"), "abc");
+        assert_eq!(replace("(?:a|b)(c)", "acbc", "${1}!"), "c!c!");
+        assert_eq!(replace("(a)|(b)", "ab", "[$1$2]"), "[a][b]");
+        assert_eq!(replace("(a)", "a", "$$1 $0"), "$1 a");
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert_eq!(replace("a*", "baaa", "-"), "-b--");
+        assert_eq!(replace("a+", "baaa", "-"), "b-");
+        assert_eq!(replace("ba?", "b ba", "-"), "- -");
+        assert_eq!(replace("a{2}", "aaaaa", "-"), "--a");
+        assert_eq!(replace("a{2,}", "a aaaa", "-"), "a -");
+        assert_eq!(replace("a{1,2}", "aaa", "-"), "--");
+        assert_eq!(replace("<.*>", "<a><b>", "-"), "-");
+        assert_eq!(replace("<.*?>", "<a><b>", "-"), "--");
+        assert_eq!(replace("a+?", "aaa", "-"), "---");
+        assert_eq!(replace("(a*)*b", "aab", "[$1]"), "[aa]");
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(replace(r"\bid\b", "id, ids, _id", "-"), "-, ids, _id");
+        // `\b` agrees with `\w` on non-ASCII characters
+        assert_eq!(replace(r"\b\w+\b", "café", "-"), "-é");
+        assert_eq!(replace(r"\w+", "café", "-"), "-é");
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in ["(a", "a)", "[a", "*a", r"\"] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn large_repetitions() {
+        assert_eq!(replace("a{1000}", &"a".repeat(1001), "-"), "-a");
+        assert!(Regex::new("a{1001}").is_err());
+        assert!(Regex::new("a{1,1000000}").is_err());
+        assert!(Regex::new("a{1000000,}").is_err());
+        // nested ones are limited by the size of program
+        assert!(Regex::new("(a{1000}){1000}").is_err());
+        assert!(Regex::new("((a{1000}){1000}){1000}").is_err());
+        // not bounds, but literals
+        assert_eq!(replace("a{x}", "a{x}", "-"), "-");
+    }
+
+    #[test]
+    fn long_inputs() {
+        let line = "x".repeat(100_000);
+        // and an empty match at the end
+        assert_eq!(replace(".*", &line, "-"), "--");
+        assert_eq!(replace("(x|y)*$", &line, "$1"), "x");
+
+        let line = format!("a{}z", "x".repeat(10_000));
+        assert_eq!(replace("a.*z", &line, "-"), "-");
+        assert_eq!(replace("a.*y", &line, "-"), line);
+    }
+}
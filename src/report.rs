@@ -0,0 +1,169 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Reports of a run for consumers other than terminals, like CI artifacts.
+
+use std::{
+    cmp::Reverse,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    case::QueryTiming, compare::QueryDiff, environment::ResourceUsage, resources::ResourceStats,
+};
+
+pub(crate) mod allure;
+pub(crate) mod html;
+pub(crate) mod markdown;
+
+/// Result of a finished case.
+pub(crate) struct CaseReport {
+    pub env: String,
+    /// Path of the case file relative to [`Config::case_dir`].
+    ///
+    /// [`Config::case_dir`]: crate::Config::case_dir
+    pub case: String,
+    /// Same as in events, e.g. `pass`.
+    pub outcome: &'static str,
+    pub start: SystemTime,
+    pub elapsed: Duration,
+    /// Reason of skipped cases, or the error.
+    pub reason: Option<String>,
+    pub owners: Vec<String>,
+    pub tags: Vec<String>,
+    pub details: CaseDetails,
+    /// Paths and tails of [`ServerInfo::server_logs`], only for failed cases.
+    ///
+    /// [`ServerInfo::server_logs`]: crate::case::ServerInfo::server_logs
+    pub server_logs: Vec<(String, String)>,
+}
+
+/// Details filled when a case runs.
+#[derive(Default)]
+pub(crate) struct CaseDetails {
+    pub queries: Vec<QueryTiming>,
+    /// Queries with different results.
+    pub diffs: Vec<QueryDiff>,
+    /// Queries exceeding [`Config::slow_query_threshold`].
+    ///
+    /// [`Config::slow_query_threshold`]: crate::Config::slow_query_threshold
+    pub slow_queries: Vec<SlowQuery>,
+    /// Resource usage sampled while the case runs, see
+    /// [`Config::resource_sample_interval`].
+    ///
+    /// [`Config::resource_sample_interval`]: crate::Config::resource_sample_interval
+    pub resource_samples: Vec<ResourceUsage>,
+}
+
+/// A query exceeding [`Config::slow_query_threshold`].
+///
+/// [`Config::slow_query_threshold`]: crate::Config::slow_query_threshold
+#[derive(Debug, Clone)]
+pub(crate) struct SlowQuery {
+    /// Line number (1-based) of the query in the case file.
+    pub line: usize,
+    pub elapsed: Duration,
+}
+
+/// Collector of case results of a run.
+#[derive(Default)]
+pub(crate) struct Reports {
+    cases: Mutex<Vec<CaseReport>>,
+    /// Fields of [`RunMetadata`], so archived reports are self-describing.
+    ///
+    /// [`RunMetadata`]: crate::metadata::RunMetadata
+    metadata: Mutex<Vec<(&'static str, String)>>,
+}
+
+impl Reports {
+    pub(crate) fn record(&self, case: CaseReport) {
+        self.cases.lock().unwrap().push(case);
+    }
+
+    pub(crate) fn record_metadata(&self, metadata: Vec<(&'static str, String)>) {
+        *self.metadata.lock().unwrap() = metadata;
+    }
+
+    /// Recorded cases grouped by environment, in the order they run.
+    fn by_env(cases: &[CaseReport]) -> Vec<(&str, Vec<&CaseReport>)> {
+        let mut envs: Vec<(&str, Vec<&CaseReport>)> = vec![];
+        for case in cases {
+            match envs.iter_mut().find(|(env, _)| *env == case.env) {
+                Some((_, cases)) => cases.push(case),
+                None => envs.push((&case.env, vec![case])),
+            }
+        }
+
+        envs
+    }
+
+    /// Number of cases of each outcome, in a fixed order.
+    fn counts<'a>(cases: impl IntoIterator<Item = &'a CaseReport>) -> Vec<(&'static str, usize)> {
+        let mut counts: Vec<_> = OUTCOMES.iter().map(|outcome| (*outcome, 0)).collect();
+        for case in cases {
+            if let Some((_, count)) = counts
+                .iter_mut()
+                .find(|(outcome, _)| *outcome == case.outcome)
+            {
+                *count += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Peak and average resource usage of `cases`.
+    fn resources<'a>(cases: impl IntoIterator<Item = &'a CaseReport>) -> Option<ResourceStats> {
+        let samples: Vec<_> = cases
+            .into_iter()
+            .flat_map(|case| &case.details.resource_samples)
+            .collect();
+
+        ResourceStats::from_samples(samples.iter().copied())
+    }
+
+    /// Slow queries of all cases, the slowest first.
+    fn slow_queries(cases: &[CaseReport]) -> Vec<(&CaseReport, &SlowQuery)> {
+        let mut queries: Vec<_> = cases
+            .iter()
+            .flat_map(|case| {
+                case.details
+                    .slow_queries
+                    .iter()
+                    .map(move |query| (case, query))
+            })
+            .collect();
+        queries.sort_by_key(|(_, query)| Reverse(query.elapsed));
+
+        queries
+    }
+}
+
+/// All outcomes of cases, see [`CaseReport::outcome`].
+const OUTCOMES: [&str; 5] = ["pass", "diff", "updated", "skipped", "error"];
+
+/// Whether the case with `outcome` failed.
+pub(crate) fn is_failure(outcome: &str) -> bool {
+    matches!(outcome, "diff" | "error")
+}
+
+/// Lines of diffs of a case, like in the console output.
+fn diff_lines(diff: &QueryDiff) -> Vec<&str> {
+    let mut lines = vec![];
+    for (i, hunk) in diff.hunks.iter().enumerate() {
+        if i > 0 {
+            lines.push("...");
+        }
+        lines.extend(hunk.lines.iter().map(String::as_str));
+    }
+
+    lines
+}
+
+/// Title of a query with different result, like `#2 select-all`.
+fn query_title(diff: &QueryDiff) -> String {
+    match &diff.name {
+        Some(name) => format!("#{} {}", diff.index + 1, name),
+        None => format!("#{}", diff.index + 1),
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Allure results, see [`Config::allure_results_dir`].
+//!
+//! [`Config::allure_results_dir`]: crate::Config::allure_results_dir
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::fs::{create_dir_all, write};
+
+use super::{diff_lines, query_title, CaseReport, Reports};
+use crate::{
+    event::{object, string},
+    hash::{md5_hex, random_hex},
+};
+
+/// Max length of query text in step names.
+const MAX_STEP_NAME_LENGTH: usize = 80;
+
+impl Reports {
+    /// Write a `<uuid>-result.json` file for each recorded case into `dir`,
+    /// along with attachments, and run metadata as `environment.properties`
+    /// shown in the environment widget.
+    pub(crate) async fn write_allure(&self, dir: &Path) -> std::io::Result<()> {
+        create_dir_all(dir).await?;
+        let results: Vec<_> = self.cases.lock().unwrap().iter().map(result).collect();
+        for (uuid, json, attachments) in results {
+            for (source, content) in attachments {
+                write(dir.join(source), content).await?;
+            }
+            write(dir.join(format!("{}-result.json", uuid)), json).await?;
+        }
+        let properties: String = self
+            .metadata
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, property_value(value)))
+            .collect();
+        if !properties.is_empty() {
+            write(dir.join("environment.properties"), properties).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Escape `value` of a Java properties file, in one line.
+fn property_value(value: &str) -> String {
+    value.trim_end().replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Render test result of `case`, return its UUID, JSON and attachments (file
+/// name and content).
+fn result(case: &CaseReport) -> (String, String, Vec<(String, String)>) {
+    let uuid = new_uuid();
+    let mut attachments = vec![];
+    let mut steps = vec![];
+    for (index, query) in case.details.queries.iter().enumerate() {
+        let diff = case.details.diffs.iter().find(|diff| diff.index == index);
+        let mut step_attachments = vec![];
+        if let Some(diff) = diff {
+            let source = format!("{}-attachment.diff", new_uuid());
+            step_attachments.push(attachment("diff", &source, "text/plain"));
+            attachments.push((source, diff_lines(diff).join("\n")));
+        }
+        let title = match diff {
+            Some(diff) => query_title(diff),
+            None => format!("#{}", index + 1),
+        };
+        let statement = query.query.split_whitespace().collect::<Vec<_>>().join(" ");
+        let statement: String = statement.chars().take(MAX_STEP_NAME_LENGTH).collect();
+        steps.push(object(vec![
+            ("name", string(&format!("{} {}", title, statement))),
+            (
+                "status",
+                string(if diff.is_some() { "failed" } else { "passed" }),
+            ),
+            ("stage", string("finished")),
+            ("start", millis(query.start).to_string()),
+            ("stop", millis(query.start + query.elapsed).to_string()),
+            (
+                "parameters",
+                object_array(vec![vec![
+                    ("name", string("query_id")),
+                    ("value", string(&query.query_id)),
+                ]]),
+            ),
+            ("attachments", format!("[{}]", step_attachments.join(","))),
+        ]));
+    }
+
+    let mut case_attachments = vec![];
+    for (path, content) in &case.server_logs {
+        let source = format!("{}-attachment.log", new_uuid());
+        case_attachments.push(attachment(path, &source, "text/plain"));
+        attachments.push((source, content.clone()));
+    }
+
+    let status = match case.outcome {
+        "pass" | "updated" => "passed",
+        "diff" => "failed",
+        "skipped" => "skipped",
+        _ => "broken",
+    };
+    let mut labels = vec![
+        vec![("name", string("suite")), ("value", string(&case.env))],
+        vec![("name", string("framework")), ("value", string("sqlness"))],
+    ];
+    labels.extend(
+        case.owners
+            .iter()
+            .map(|owner| vec![("name", string("owner")), ("value", string(owner))]),
+    );
+    labels.extend(
+        case.tags
+            .iter()
+            .map(|tag| vec![("name", string("tag")), ("value", string(tag))]),
+    );
+    let mut fields = vec![
+        ("uuid", string(&uuid)),
+        // the relative path includes the env
+        ("historyId", string(&md5_hex(case.case.as_bytes()))),
+        ("name", string(&case.case)),
+        ("fullName", string(&case.case)),
+        ("status", string(status)),
+        ("stage", string("finished")),
+        ("start", millis(case.start).to_string()),
+        ("stop", millis(case.start + case.elapsed).to_string()),
+        ("labels", object_array(labels)),
+        ("steps", format!("[{}]", steps.join(","))),
+        ("attachments", format!("[{}]", case_attachments.join(","))),
+    ];
+    if let Some(reason) = &case.reason {
+        fields.push(("statusDetails", object(vec![("message", string(reason))])));
+    }
+
+    (uuid, object(fields), attachments)
+}
+
+fn attachment(name: &str, source: &str, content_type: &str) -> String {
+    object(vec![
+        ("name", string(name)),
+        ("source", string(source)),
+        ("type", string(content_type)),
+    ])
+}
+
+/// Random UUID like `3f2a...-....`, in the layout of version 4.
+fn new_uuid() -> String {
+    let hex = random_hex(32);
+    format!(
+        "{}-{}-4{}-a{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[13..16],
+        &hex[17..20],
+        &hex[20..32]
+    )
+}
+
+fn millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis()
+}
+
+fn object_array(objects: Vec<Vec<(&str, String)>>) -> String {
+    let objects: Vec<_> = objects.into_iter().map(object).collect();
+    format!("[{}]", objects.join(","))
+}
@@ -0,0 +1,171 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Self-contained HTML report, see [`Config::html_report`].
+//!
+//! [`Config::html_report`]: crate::Config::html_report
+
+use std::fmt::Write;
+
+use super::{diff_lines, is_failure, query_title, CaseReport, Reports};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+th, td { border: 1px solid #ddd; padding: 4px 8px; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; }
+.pass, .updated { color: #1a7f37; }
+.diff, .error { color: #cf222e; font-weight: bold; }
+.skipped { color: #9a6700; }
+pre { margin: 4px 0; background: #f6f8fa; padding: 4px; overflow-x: auto; }
+.del { color: #cf222e; }
+.ins { color: #1a7f37; }
+#filters { margin-bottom: 1em; }";
+
+/// Hide rows not matching the case name filter or the outcome filter.
+const SCRIPT: &str = "\
+function filterCases() {
+  var name = document.getElementById('name').value.toLowerCase();
+  var outcome = document.getElementById('outcome').value;
+  document.querySelectorAll('tr.case').forEach(function (row) {
+    var show = row.dataset.case.toLowerCase().indexOf(name) >= 0
+      && (outcome === '' || row.dataset.outcome === outcome);
+    row.style.display = show ? '' : 'none';
+  });
+}";
+
+impl Reports {
+    /// Render recorded cases as an HTML page with a section per environment.
+    pub(crate) fn to_html(&self) -> String {
+        let cases = self.cases.lock().unwrap();
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>sqlness report</title>\n");
+        writeln!(html, "<style>\n{}\n</style>", STYLE).unwrap();
+        writeln!(html, "<script>\n{}\n</script>", SCRIPT).unwrap();
+        html.push_str("</head>\n<body>\n<h1>sqlness report</h1>\n");
+        html.push_str(&summary(Reports::counts(cases.iter())));
+        let metadata = self.metadata.lock().unwrap();
+        if !metadata.is_empty() {
+            html.push_str("<details><summary>Run metadata</summary>\n<table>\n");
+            for (key, value) in metadata.iter() {
+                writeln!(
+                    html,
+                    "<tr><th>{}</th><td><pre>{}</pre></td></tr>",
+                    key,
+                    escape(value.trim_end())
+                )
+                .unwrap();
+            }
+            html.push_str("</table>\n</details>\n");
+        }
+
+        html.push_str("<div id=\"filters\">\n");
+        html.push_str(
+            "<input id=\"name\" placeholder=\"Filter cases\" oninput=\"filterCases()\">\n",
+        );
+        html.push_str("<select id=\"outcome\" onchange=\"filterCases()\">\n");
+        html.push_str("<option value=\"\">all</option>\n");
+        for outcome in super::OUTCOMES {
+            writeln!(html, "<option>{}</option>", outcome).unwrap();
+        }
+        html.push_str("</select>\n</div>\n");
+
+        for (env, env_cases) in Reports::by_env(&cases) {
+            let elapsed: u128 = env_cases.iter().map(|case| case.elapsed.as_millis()).sum();
+            writeln!(html, "<h2>{} ({}ms)</h2>", escape(env), elapsed).unwrap();
+            html.push_str(&summary(Reports::counts(env_cases.iter().copied())));
+            if let Some(stats) = Reports::resources(env_cases.iter().copied()) {
+                writeln!(html, "<p>Resource usage: {}</p>", escape(&stats.describe())).unwrap();
+            }
+            html.push_str(
+                "<table>\n<tr><th>Case</th><th>Outcome</th><th>Time</th><th>Details</th></tr>\n",
+            );
+            for case in env_cases {
+                html.push_str(&row(case));
+            }
+            html.push_str("</table>\n");
+        }
+        let slow_queries = Reports::slow_queries(&cases);
+        if !slow_queries.is_empty() {
+            html.push_str("<h2>Slow queries</h2>\n");
+            html.push_str("<table>\n<tr><th>Case</th><th>Line</th><th>Time</th></tr>\n");
+            for (case, query) in slow_queries {
+                writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}ms</td></tr>",
+                    escape(&case.case),
+                    query.line,
+                    query.elapsed.as_millis()
+                )
+                .unwrap();
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+
+        html
+    }
+}
+
+fn summary(counts: Vec<(&str, usize)>) -> String {
+    let counts: Vec<_> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(outcome, count)| format!("<span class=\"{0}\">{0}: {1}</span>", outcome, count))
+        .collect();
+
+    format!("<p>{}</p>\n", counts.join(", "))
+}
+
+fn row(case: &CaseReport) -> String {
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<tr class=\"case\" data-case=\"{0}\" data-outcome=\"{1}\"><td>{0}</td>\
+         <td class=\"{1}\">{1}</td><td>{2}ms</td><td>",
+        escape(&case.case),
+        case.outcome,
+        case.elapsed.as_millis()
+    )
+    .unwrap();
+    if let Some(reason) = &case.reason {
+        writeln!(html, "<pre>{}</pre>", escape(reason)).unwrap();
+    }
+    if let Some(stats) = Reports::resources([case]) {
+        writeln!(html, "<p>Resource usage: {}</p>", escape(&stats.describe())).unwrap();
+    }
+    for diff in &case.details.diffs {
+        // failures are expanded by default
+        let open = if is_failure(case.outcome) {
+            " open"
+        } else {
+            ""
+        };
+        writeln!(
+            html,
+            "<details{}><summary>{}</summary><pre>",
+            open,
+            escape(&query_title(diff))
+        )
+        .unwrap();
+        for line in diff_lines(diff) {
+            let class = match line.chars().next() {
+                Some('-') => "del",
+                Some('+') => "ins",
+                _ => "ctx",
+            };
+            writeln!(html, "<span class=\"{}\">{}</span>", class, escape(line)).unwrap();
+        }
+        html.push_str("</pre></details>\n");
+    }
+    html.push_str("</td></tr>\n");
+
+    html
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,171 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Compact Markdown summary for pull request comments, see
+//! [`Config::markdown_report`].
+//!
+//! [`Config::markdown_report`]: crate::Config::markdown_report
+
+use std::fmt::Write;
+
+use super::{diff_lines, is_failure, query_title, Reports};
+
+/// Max diff lines shown for each failing case.
+const MAX_DIFF_LINES: usize = 20;
+/// Max length of the summary, below the limit of GitHub comments (65536).
+const MAX_LENGTH: usize = 60000;
+
+impl Reports {
+    /// Render counts of cases per environment, and failing cases with their
+    /// diffs truncated.
+    pub(crate) fn to_markdown(&self) -> String {
+        let cases = self.cases.lock().unwrap();
+        let failures: Vec<_> = cases
+            .iter()
+            .filter(|case| is_failure(case.outcome))
+            .collect();
+        let mut markdown = String::new();
+        if failures.is_empty() {
+            writeln!(markdown, "### sqlness: all {} cases passed\n", cases.len()).unwrap();
+        } else {
+            writeln!(
+                markdown,
+                "### sqlness: {} of {} cases failed\n",
+                failures.len(),
+                cases.len()
+            )
+            .unwrap();
+        }
+
+        let envs = Reports::by_env(&cases);
+        let outcomes: Vec<_> = super::OUTCOMES
+            .iter()
+            .filter(|outcome| cases.iter().any(|case| case.outcome == **outcome))
+            .collect();
+        let header: Vec<_> = outcomes.iter().map(|outcome| outcome.to_string()).collect();
+        writeln!(markdown, "| env | {} | time |", header.join(" | ")).unwrap();
+        writeln!(markdown, "|---|{}---|", "---|".repeat(outcomes.len())).unwrap();
+        for (env, env_cases) in &envs {
+            let counts = Reports::counts(env_cases.iter().copied());
+            let counts: Vec<_> = outcomes
+                .iter()
+                .map(|outcome| {
+                    counts
+                        .iter()
+                        .find(|(o, _)| o == *outcome)
+                        .map(|(_, count)| count.to_string())
+                        .unwrap_or_default()
+                })
+                .collect();
+            let elapsed: u128 = env_cases.iter().map(|case| case.elapsed.as_millis()).sum();
+            writeln!(
+                markdown,
+                "| {} | {} | {}ms |",
+                env,
+                counts.join(" | "),
+                elapsed
+            )
+            .unwrap();
+        }
+
+        // config is left out to keep the summary compact
+        let metadata: Vec<_> = self
+            .metadata
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| *key != "config")
+            .map(|(key, value)| format!("- {}: `{}`", key, value))
+            .collect();
+        if !metadata.is_empty() {
+            writeln!(
+                markdown,
+                "\n<details><summary>Run metadata</summary>\n\n{}\n\n</details>",
+                metadata.join("\n")
+            )
+            .unwrap();
+        }
+
+        let resources: Vec<_> = envs
+            .iter()
+            .filter_map(|(env, env_cases)| {
+                Reports::resources(env_cases.iter().copied()).map(|stats| (env, stats))
+            })
+            .collect();
+        if !resources.is_empty() {
+            markdown.push_str("\n#### Resource usage\n\n| env | peak (average) |\n|---|---|\n");
+            for (env, stats) in resources {
+                writeln!(markdown, "| {} | {} |", env, stats.describe()).unwrap();
+            }
+        }
+
+        let slow_queries = Reports::slow_queries(&cases);
+        if !slow_queries.is_empty() {
+            markdown.push_str("\n#### Slow queries\n\n| case | line | time |\n|---|---|---|\n");
+            for (case, query) in slow_queries {
+                writeln!(
+                    markdown,
+                    "| `{}` | {} | {}ms |",
+                    case.case,
+                    query.line,
+                    query.elapsed.as_millis()
+                )
+                .unwrap();
+            }
+        }
+
+        if !failures.is_empty() {
+            markdown.push_str("\n#### Failing cases\n\n");
+        }
+        for (i, case) in failures.iter().enumerate() {
+            let mut section = String::new();
+            let queries: Vec<_> = case.details.diffs.iter().map(query_title).collect();
+            if queries.is_empty() {
+                writeln!(
+                    section,
+                    "<details><summary><code>{}</code> {}</summary>\n",
+                    case.case, case.outcome
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    section,
+                    "<details><summary><code>{}</code> {}: {}</summary>\n",
+                    case.case,
+                    case.outcome,
+                    queries.join(", ")
+                )
+                .unwrap();
+            }
+            let mut lines: Vec<_> = case.reason.iter().map(String::as_str).collect();
+            for diff in &case.details.diffs {
+                lines.push("");
+                lines.extend(diff_lines(diff));
+            }
+            let lines: Vec<_> = lines
+                .into_iter()
+                .skip_while(|line| line.is_empty())
+                .collect();
+            section.push_str("```diff\n");
+            for line in lines.iter().take(MAX_DIFF_LINES) {
+                writeln!(section, "{}", line).unwrap();
+            }
+            if lines.len() > MAX_DIFF_LINES {
+                writeln!(section, "... {} more lines", lines.len() - MAX_DIFF_LINES).unwrap();
+            }
+            section.push_str("```\n\n</details>\n");
+
+            if markdown.len() + section.len() > MAX_LENGTH {
+                writeln!(
+                    markdown,
+                    "\n... {} more failing cases are omitted",
+                    failures.len() - i
+                )
+                .unwrap();
+                break;
+            }
+            markdown.push_str(&section);
+        }
+
+        markdown
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Peak and average of resource usage sampled while cases run, see
+//! [`Config::resource_sample_interval`].
+//!
+//! [`Config::resource_sample_interval`]: crate::Config::resource_sample_interval
+
+use crate::environment::ResourceUsage;
+
+/// Peak and average of samples of a case or an environment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ResourceStats {
+    pub peak: ResourceUsage,
+    pub average: ResourceUsage,
+}
+
+impl ResourceStats {
+    /// Aggregate `samples` metric by metric, `None` if there is no sample.
+    pub(crate) fn from_samples<'a>(
+        samples: impl IntoIterator<Item = &'a ResourceUsage> + Clone,
+    ) -> Option<Self> {
+        samples.clone().into_iter().next()?;
+
+        let bytes = |f: fn(&ResourceUsage) -> Option<u64>| {
+            let values: Vec<_> = samples.clone().into_iter().filter_map(f).collect();
+            let peak = values.iter().max().copied();
+            let average =
+                (!values.is_empty()).then(|| values.iter().sum::<u64>() / values.len() as u64);
+            (peak, average)
+        };
+        let (rss_peak, rss_average) = bytes(|usage| usage.rss_bytes);
+        let (disk_peak, disk_average) = bytes(|usage| usage.disk_bytes);
+        let cpu: Vec<_> = samples
+            .into_iter()
+            .filter_map(|usage| usage.cpu_percent)
+            .collect();
+        let cpu_peak = cpu.iter().copied().reduce(f64::max);
+        let cpu_average = (!cpu.is_empty()).then(|| cpu.iter().sum::<f64>() / cpu.len() as f64);
+
+        Some(Self {
+            peak: ResourceUsage {
+                rss_bytes: rss_peak,
+                cpu_percent: cpu_peak,
+                disk_bytes: disk_peak,
+            },
+            average: ResourceUsage {
+                rss_bytes: rss_average,
+                cpu_percent: cpu_average,
+                disk_bytes: disk_average,
+            },
+        })
+    }
+
+    /// Like `rss 512.0MiB (avg 300.2MiB), cpu 85.0% (avg 20.1%)`, metrics not
+    /// collected are omitted.
+    pub(crate) fn describe(&self) -> String {
+        let mut parts = vec![];
+        if let (Some(peak), Some(average)) = (self.peak.rss_bytes, self.average.rss_bytes) {
+            parts.push(format!("rss {} (avg {})", bytes(peak), bytes(average)));
+        }
+        if let (Some(peak), Some(average)) = (self.peak.cpu_percent, self.average.cpu_percent) {
+            parts.push(format!("cpu {:.1}% (avg {:.1}%)", peak, average));
+        }
+        if let (Some(peak), Some(average)) = (self.peak.disk_bytes, self.average.disk_bytes) {
+            parts.push(format!("disk {} (avg {})", bytes(peak), bytes(average)));
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Format `value` in MiB.
+pub(crate) fn bytes(value: u64) -> String {
+    format!("{:.1}MiB", value as f64 / (1024.0 * 1024.0))
+}
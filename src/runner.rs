@@ -1,19 +1,46 @@
 // Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
 
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, SystemTime};
 
-use prettydiff::basic::DiffOp;
-use prettydiff::diff_lines;
-use tokio::fs::{read_dir, remove_file, File, OpenOptions};
+use tokio::fs::{read, remove_file, rename, write, File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::time::Instant;
-use walkdir::WalkDir;
+use tokio::sync::OnceCell;
+use tokio::time::{timeout, Instant};
 
-use crate::case::TestCase;
+use crate::annotation;
+use crate::benchmark::{self, Benchmarks};
+use crate::cancel::CancellationToken;
+use crate::case::{CaseMetadata, ServerInfo, TestCase};
+use crate::compare;
+use crate::compression::Compression;
+use crate::discovery::{self, CollectedCase, EnvDirs};
 use crate::error::{Result, SqlnessError};
-use crate::{config::Config, environment::EnvController};
+use crate::event::{Event, MessageFormat};
+use crate::interceptor::blocks::parse_duration;
+use crate::metadata::RunMetadata;
+use crate::metrics::Metrics;
+use crate::normalize::{mask_elapsed, normalize};
+use crate::owners::Owners;
+use crate::patch;
+use crate::report::{self, CaseDetails, CaseReport, Reports, SlowQuery};
+use crate::resources::ResourceStats;
+use crate::shrink::{self, Block};
+use crate::trace::{Span, Tracer};
+use crate::version::Version;
+use crate::{
+    config::{BenchmarkConfig, Config, ConnectionMode, MissingResultPolicy},
+    database::{Database, ExecutionContext},
+    environment::{EnvController, ResourceUsage},
+};
 
 /// The entrypoint of this crate.
 ///
@@ -33,29 +60,101 @@ use crate::{config::Config, environment::EnvController};
 pub struct Runner<E: EnvController> {
     config: Config,
     env_controller: Arc<E>,
+    cancellation: CancellationToken,
+    tracer: Tracer,
+    metrics: Metrics,
+    reports: Reports,
+    benchmarks: Benchmarks,
+    /// Resolved on first use, shared by all cases parsed in the run.
+    env_dirs: OnceCell<EnvDirs>,
+}
+
+/// A finished case of [`Runner::run_env_cases`].
+struct ExecutedCase<'a> {
+    path: &'a PathBuf,
+    /// Empty if the case can't be parsed.
+    metadata: CaseMetadata,
+    case_result: Result<CaseOutcome>,
+    details: CaseDetails,
+    span_id: String,
+    start_time: SystemTime,
+    elapsed: Duration,
+}
+
+/// Constraints of running a case along with others.
+struct Schedule {
+    /// Lexical path of the case.
+    path: PathBuf,
+    serial: bool,
+    /// Lexical paths of cases it depends on.
+    dependencies: Vec<PathBuf>,
+}
+
+impl Schedule {
+    /// Whether the case can start while `running` cases run. Serial cases run
+    /// alone, and cases wait for their dependencies to finish.
+    fn can_run_with(&self, running: &[&Schedule]) -> bool {
+        running.is_empty()
+            || !self.serial
+                && running
+                    .iter()
+                    .all(|other| !other.serial && !self.dependencies.contains(&other.path))
+    }
+}
+
+/// Wait for one of `futures` to finish, remove it and return its index and
+/// output.
+async fn next_finished<F: Future + Unpin>(futures: &mut Vec<F>) -> (usize, F::Output) {
+    let (index, output) = std::future::poll_fn(|cx| {
+        for (index, fut) in futures.iter_mut().enumerate() {
+            if let Poll::Ready(output) = Pin::new(fut).poll(cx) {
+                return Poll::Ready((index, output));
+            }
+        }
+        Poll::Pending
+    })
+    .await;
+    futures.remove(index);
+
+    (index, output)
+}
+
+/// Number of last lines of server logs attached to failed cases.
+const SERVER_LOG_LINES: usize = 200;
+
+/// Owner of failures without declared owners, in reports.
+const UNOWNED: &str = "(unowned)";
+/// Cases listed by their peak resource usage after each environment.
+const TOP_RESOURCE_CASES: usize = 5;
+
+/// Whether any failure has a declared owner, otherwise grouping by owner is
+/// meaningless.
+fn owners_declared(failures_by_owner: &BTreeMap<String, Vec<String>>) -> bool {
+    failures_by_owner.keys().any(|owner| owner != UNOWNED)
+}
+
+/// Outcome of one test case.
+enum CaseOutcome {
+    Pass,
+    Diff,
+    /// Different, and the expected result is updated.
+    Updated,
+    Skipped(String),
 }
 
 impl<E: EnvController> Runner<E> {
     pub async fn try_new<P: AsRef<Path>>(config_path: P, env: E) -> Result<Self> {
-        let mut config_file =
-            File::open(config_path.as_ref())
-                .await
-                .map_err(|e| SqlnessError::ReadPath {
-                    source: e,
-                    path: config_path.as_ref().to_path_buf(),
-                })?;
-
-        let mut config_buf = vec![];
-        config_file.read_to_end(&mut config_buf).await?;
-        let config: Config =
-            toml::from_slice(&config_buf).map_err(|e| SqlnessError::ParseToml {
-                source: e,
-                file: config_path.as_ref().to_path_buf(),
-            })?;
+        let config = Config::from_file(config_path).await?;
 
         Ok(Self {
             config,
             env_controller: Arc::new(env),
+            cancellation: CancellationToken::default(),
+            tracer: Tracer::default(),
+            metrics: Metrics::default(),
+            reports: Reports::default(),
+            benchmarks: Benchmarks::default(),
+            env_dirs: OnceCell::new(),
         })
     }
 
@@ -63,157 +162,1401 @@ impl<E: EnvController> Runner<E> {
         Ok(Self {
             config,
             env_controller: Arc::new(env),
+            cancellation: CancellationToken::default(),
+            tracer: Tracer::default(),
+            metrics: Metrics::default(),
+            reports: Reports::default(),
+            benchmarks: Benchmarks::default(),
+            env_dirs: OnceCell::new(),
         })
     }
 
+    /// Environment directories of the run, see [`EnvDirs`].
+    async fn env_dirs(&self) -> Result<&EnvDirs> {
+        self.env_dirs
+            .get_or_try_init(|| EnvDirs::resolve(&self.config))
+            .await
+    }
+
+    /// Parse the case file at `path`.
+    async fn parse_case(&self, path: impl AsRef<Path>) -> Result<TestCase> {
+        TestCase::from_file_with(path, &self.config, self.env_dirs().await?).await
+    }
+
+    /// Run cases of all environments.
+    ///
+    /// Failures of environments don't stop the run, and the most severe one
+    /// is returned at last, see [`SqlnessError::exit_code`].
     pub async fn run(&self) -> Result<()> {
-        let environments = self.collect_env().await?;
+        self.config.validate()?;
+        if self.config.dry_run {
+            return self.dry_run().await;
+        }
+
+        if let (true, Some(patch)) = (self.config.update_results, &self.config.update_patch) {
+            if Path::new(patch).exists() {
+                remove_file(patch).await?;
+            }
+        }
+
+        let run_start = SystemTime::now();
+        let run_timer = Instant::now();
+        // written into reports and JSON messages, but not the console
+        let metadata = RunMetadata::collect(&self.config).await;
+        self.reports.record_metadata(metadata.fields());
+        self.emit(Event::RunStarted {
+            metadata: &metadata,
+        });
+
+        let deadline = self.deadline()?;
+        let environments = discovery::collect_envs(&self.config).await?;
+        let mut not_executed = 0;
+        // the most severe failure of environments, see `SqlnessError::exit_code`
+        let mut failure: Option<SqlnessError> = None;
         for env in environments {
-            let env_config = self.read_env_config(&env).await;
-            let config_path = env_config.as_path();
-            let config_path = if config_path.exists() {
-                Some(config_path)
-            } else {
-                None
+            if Self::expired(deadline) {
+                let cases: Vec<_> =
+                    discovery::collect_cases(&self.config, &env, self.env_dirs().await?)
+                        .await?
+                        .iter()
+                        .map(|case| case.path.display().to_string())
+                        .collect();
+                self.report_not_executed(&env, &cases);
+                not_executed += cases.len();
+                continue;
+            }
+            match self.run_env_until(&env, deadline).await {
+                Err(SqlnessError::Cancelled) => return Err(SqlnessError::Cancelled),
+                Err(SqlnessError::BudgetExceeded { count }) => not_executed += count,
+                Err(e) => {
+                    self.print(format_args!(
+                        "Environment {} run failed with error {:?}",
+                        env, e
+                    ));
+                    failure = Some(match failure {
+                        Some(failure) => failure.merge(e),
+                        None => e,
+                    });
+                }
+                Ok(()) => {}
+            }
+        }
+        let failed = failure.is_some();
+
+        self.record_span(
+            Span::new(
+                self.tracer.root_span_id().to_string(),
+                None,
+                "run",
+                run_start,
+                run_timer.elapsed(),
+            )
+            .error(failed || not_executed > 0),
+        );
+        self.export_trace().await;
+        self.export_metrics(run_timer.elapsed(), !failed && not_executed == 0)
+            .await;
+        self.write_reports().await;
+
+        if not_executed > 0 {
+            let exceeded = SqlnessError::BudgetExceeded {
+                count: not_executed,
             };
-            let db = self.env_controller.start(&env, config_path).await;
-            if let Err(e) = self.run_env(&env, &db).await {
-                println!("Environment {} run failed with error {:?}", env, e);
+            failure = Some(match failure {
+                Some(failure) => failure.merge(exceeded),
+                None => exceeded,
+            });
+        }
+        match failure {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+
+    /// Record `span` if it's exported, see [`Config::otlp_endpoint`].
+    fn record_span(&self, span: Span) {
+        if self.config.otlp_endpoint.is_some() {
+            self.tracer.record(span);
+        }
+    }
+
+    async fn export_trace(&self) {
+        if let Some(endpoint) = &self.config.otlp_endpoint {
+            if let Err(e) = self.tracer.export(endpoint).await {
+                self.print(format_args!(
+                    "Failed to export trace to {}, error: {}",
+                    endpoint, e
+                ));
             }
-            self.env_controller.stop(&env, db).await;
         }
+    }
 
-        Ok(())
+    /// Write metrics to [`Config::metrics_file`] and push them to
+    /// [`Config::pushgateway_url`].
+    async fn export_metrics(&self, elapsed: Duration, success: bool) {
+        if self.config.metrics_file.is_none() && self.config.pushgateway_url.is_none() {
+            return;
+        }
+
+        let text = self.metrics.render(elapsed, success);
+        if let Some(path) = &self.config.metrics_file {
+            if let Err(e) = Metrics::write_file(path, &text).await {
+                self.print(format_args!(
+                    "Failed to write metrics to {}, error: {}",
+                    path, e
+                ));
+            }
+        }
+        if let Some(url) = &self.config.pushgateway_url {
+            if let Err(e) = Metrics::push(url, &text).await {
+                self.print(format_args!(
+                    "Failed to push metrics to {}, error: {}",
+                    url, e
+                ));
+            }
+        }
+    }
+
+    /// Write reports of cases, see [`Config::html_report`],
+    /// [`Config::markdown_report`] and [`Config::allure_results_dir`].
+    async fn write_reports(&self) {
+        if let Some(path) = &self.config.html_report {
+            self.write_report("HTML", path, self.reports.to_html())
+                .await;
+        }
+        if let Some(path) = &self.config.markdown_report {
+            self.write_report("Markdown", path, self.reports.to_markdown())
+                .await;
+        }
+        if let Some(path) = self
+            .config
+            .benchmark
+            .as_ref()
+            .and_then(|benchmark| benchmark.report.as_ref())
+        {
+            self.write_report("benchmark", path, self.benchmarks.to_csv())
+                .await;
+        }
+        if let Some(dir) = &self.config.allure_results_dir {
+            if let Err(e) = self.reports.write_allure(Path::new(dir)).await {
+                self.print(format_args!(
+                    "Failed to write Allure results to {}, error: {}",
+                    dir, e
+                ));
+            }
+        }
+    }
+
+    async fn write_report(&self, format: &str, path: &str, report: String) {
+        if let Err(e) = write(path, report).await {
+            self.print(format_args!(
+                "Failed to write {} report to {}, error: {}",
+                format, path, e
+            ));
+        }
+    }
+
+    /// Deadline of a run starting now, see [`Config::max_run_duration`].
+    fn deadline(&self) -> Result<Option<Instant>> {
+        let duration = Self::duration("max_run_duration", &self.config.max_run_duration)?;
+
+        Ok(duration.map(|duration| Instant::now() + duration))
+    }
+
+    /// Parse duration `value` of config `field`.
+    fn duration(field: &str, value: &Option<String>) -> Result<Option<Duration>> {
+        value
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+            .map_err(|reason| SqlnessError::InvalidConfig {
+                field: field.to_string(),
+                reason,
+            })
+    }
+
+    /// Start environment `env`, within [`Config::env_start_timeout`].
+    async fn start_env(&self, env: &str, config_path: Option<&Path>) -> Result<E::DB> {
+        let start = self.env_controller.start(env, config_path);
+        let config = self.config.for_env(env);
+        match Self::duration("env_start_timeout", &config.env_start_timeout)? {
+            Some(duration) => {
+                timeout(duration, start)
+                    .await
+                    .map_err(|_| SqlnessError::EnvStartFailed {
+                        env: env.to_string(),
+                        reason: format!("not started within {:?}", duration),
+                    })
+            }
+            None => Ok(start.await),
+        }
+    }
+
+    /// Stop environment `env`, within [`Config::env_stop_timeout`].
+    async fn stop_env(&self, env: &str, db: E::DB) -> Result<()> {
+        let stop = self.env_controller.stop(env, db);
+        let config = self.config.for_env(env);
+        match Self::duration("env_stop_timeout", &config.env_stop_timeout)? {
+            Some(duration) => timeout(duration, stop)
+                .await
+                .map_err(|_| SqlnessError::EnvTimeout {
+                    env: env.to_string(),
+                    action: "stop",
+                    duration,
+                }),
+            None => {
+                stop.await;
+                Ok(())
+            }
+        }
+    }
+
+    fn expired(deadline: Option<Instant>) -> bool {
+        deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn report_not_executed(&self, env: &str, cases: &[String]) {
+        if cases.is_empty() {
+            return;
+        }
+        self.print(format_args!(
+            "Run time budget exceeded, cases not executed in environment {}:",
+            env
+        ));
+        self.print(format_args!("{:#?}", cases));
+        self.emit(Event::CasesNotExecuted { env, cases });
+    }
+
+    /// Print human readable `message`, to stderr if events are printed to
+    /// stdout, or nowhere in [`MessageFormat::Plain`].
+    fn print(&self, message: std::fmt::Arguments) {
+        match self.config.message_format {
+            MessageFormat::Human => println!("{}", message),
+            MessageFormat::Json => eprintln!("{}", message),
+            MessageFormat::Plain => {}
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        match self.config.message_format {
+            MessageFormat::Human => {}
+            MessageFormat::Json => println!("{}", event.to_json()),
+            MessageFormat::Plain => {
+                for line in event.to_plain() {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+
+    /// Token to cancel runs of this runner from other tasks.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Run all cases of environment `env`, which is started and stopped by the
+    /// [`EnvController`].
+    ///
+    /// Return [`SqlnessError::BudgetExceeded`] if [`Config::max_run_duration`]
+    /// is exceeded before all cases run.
+    pub async fn run_env(&self, env: &str) -> Result<()> {
+        self.config.validate()?;
+        self.run_env_until(env, self.deadline()?).await
+    }
+
+    async fn run_env_until(&self, env: &str, deadline: Option<Instant>) -> Result<()> {
+        if self.cancellation.is_cancelled() {
+            return Err(SqlnessError::Cancelled);
+        }
+        let env_config = self.read_env_config(env).await?;
+        let config_path = Self::existing(&env_config);
+        let span_id = Tracer::span_id();
+        let start = SystemTime::now();
+        let timer = Instant::now();
+        let db = self.start_env(env, config_path).await?;
+        let result = self
+            .run_env_cases(env, &db, config_path, deadline, &span_id)
+            .await;
+        let stopped = self.stop_env(env, db).await;
+        // failures of cases take precedence over failures of stopping
+        let result = result.and(stopped);
+        self.record_span(
+            Span::new(
+                span_id,
+                Some(self.tracer.root_span_id()),
+                format!("env {}", env),
+                start,
+                timer.elapsed(),
+            )
+            .attribute("sqlness.env", env)
+            .error(result.is_err()),
+        );
+
+        result
+    }
+
+    /// Run a single case at `path`, e.g. `<case_dir>/<env>/select.sql`. Its
+    /// environment is started and stopped by the [`EnvController`].
+    ///
+    /// Return [`SqlnessError::RunFailed`] if the result is different.
+    pub async fn run_case<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.config.validate()?;
+        let path = path.as_ref().with_extension("");
+        let env = self.case_env(&path).await?;
+
+        let env_config = self.read_env_config(&env).await?;
+        let config_path = Self::existing(&env_config);
+        let db = self.start_env(&env, config_path).await?;
+        let span_id = Tracer::span_id();
+        let mut details = CaseDetails::default();
+        let case = self
+            .parse_case(path.with_extension(&self.config.test_case_extension))
+            .await;
+        let outcome = match self.probe_server(&db, config_path).await {
+            Ok(server) => tokio::select! {
+                biased;
+                _ = self.cancellation.cancelled() => Err(SqlnessError::Cancelled),
+                outcome = self.run_single_case(&db, &path, case, &server, &span_id, &mut details) => outcome,
+            },
+            Err(e) => Err(e),
+        };
+        let stopped = self.stop_env(&env, db).await;
+
+        match outcome? {
+            CaseOutcome::Pass | CaseOutcome::Updated | CaseOutcome::Skipped(_) => stopped,
+            CaseOutcome::Diff => Err(SqlnessError::RunFailed { count: 1 }),
+        }
+    }
+
+    /// Environment of the case at `path`, see [`discovery::env_of_case`].
+    async fn case_env(&self, path: &Path) -> Result<String> {
+        discovery::env_of_case(&self.config, path)
+            .await?
+            .ok_or_else(|| SqlnessError::CaseNotFound {
+                path: path.to_path_buf(),
+            })
     }
 
-    async fn read_env_config(&self, env: &str) -> PathBuf {
-        let mut path_buf = std::path::PathBuf::new();
-        path_buf.push(&self.config.case_dir);
-        path_buf.push(env);
-        path_buf.push(&self.config.env_config_file);
+    /// Shrink the different case at `path` to a minimal case reproducing the
+    /// difference, and write it to `output`. Queries, and then inner lines of
+    /// queries (like rows of multi-line `INSERT`s), are removed as long as the
+    /// first different query still gets the same unexpected result. Fixtures
+    /// of the case run around each attempt, so the case should be re-runnable.
+    pub async fn shrink_case<P: AsRef<Path>>(&self, path: P, output: &Path) -> Result<()> {
+        self.config.validate()?;
+        let path = path.as_ref().with_extension("");
+        let env = self.case_env(&path).await?;
+        let env_config = self.read_env_config(&env).await?;
+        let db = self.start_env(&env, Self::existing(&env_config)).await?;
+        let shrunk = self.shrink_with(&db, &path).await;
+        let stopped = self.stop_env(&env, db).await;
+        let (blocks, original, attempts) = shrunk?;
+
+        write(
+            output,
+            self.config
+                .file_encoding
+                .encode(&shrink::to_case_file(&blocks)),
+        )
+        .await?;
+        self.print(format_args!(
+            "Shrunk {} from {} queries to {} in {} attempts, written to {}",
+            path.display(),
+            original,
+            blocks.len(),
+            attempts,
+            output.display()
+        ));
 
-        path_buf
+        stopped
     }
 
-    async fn collect_env(&self) -> Result<Vec<String>> {
-        let mut dirs = read_dir(&self.config.case_dir).await?;
-        let mut result = vec![];
+    /// Return remaining queries, the number of original queries, and the
+    /// number of attempts.
+    async fn shrink_with(&self, db: &E::DB, path: &Path) -> Result<(Vec<Block>, usize, usize)> {
+        let case_path = path.with_extension(&self.config.test_case_extension);
+        let case = self.parse_case(&case_path).await?;
+        let expect_path = compare::expect_result_path(&self.config, path);
+        if !expect_path.exists() {
+            return Err(SqlnessError::MissingResult { path: expect_path });
+        }
+        let expected = compare::read_expect_result(&self.config, &expect_path).await?;
+        let expected = mask_elapsed(&self.config, &normalize(&self.config, &expected));
+        let expected_blocks = case.split_result(&expected);
+        let actual = self.shrink_attempt(db, path, &case).await?;
+        let actual_blocks = case.split_result(&actual);
+        // the first query with different result, and its results
+        let (target, expected, actual) = expected_blocks
+            .iter()
+            .zip(&actual_blocks)
+            .find(|(expected, actual)| expected.result != actual.result)
+            .map(|(expected, actual)| {
+                (actual.index, expected.result.clone(), actual.result.clone())
+            })
+            .ok_or_else(|| SqlnessError::NothingToShrink {
+                path: case_path.clone(),
+            })?;
 
-        while let Some(dir) = dirs.next_entry().await? {
-            if dir.file_type().await?.is_dir() {
-                let file_name = dir.file_name().to_str().unwrap().to_string();
-                result.push(file_name);
+        let mut blocks = case.shrink_blocks();
+        let original = blocks.len();
+        blocks[target].target = true;
+        let (blocks, attempts) = shrink::shrink(blocks, |candidate| {
+            let (expected, actual, case_path) = (&expected, &actual, &case_path);
+            async move {
+                let content = shrink::to_case_file(&candidate);
+                let case = match TestCase::parse(
+                    case_path.display().to_string(),
+                    content.as_bytes(),
+                    &self.config,
+                    self.env_dirs().await?,
+                )
+                .await
+                {
+                    Ok(case) if case.query_count() == candidate.len() => case,
+                    _ => return Ok(false),
+                };
+                let output = self.shrink_attempt(db, path, &case).await?;
+                let target = shrink::target_index(&candidate);
+
+                Ok(case
+                    .split_result(&output)
+                    .get(target)
+                    .map(|block| &block.result != expected && &block.result == actual)
+                    .unwrap_or(false))
+            }
+        })
+        .await?;
+
+        Ok((blocks, original, attempts))
+    }
+
+    /// Run `case` with fixtures of the case at `path`, return its normalized
+    /// output.
+    async fn shrink_attempt(&self, db: &E::DB, path: &Path, case: &TestCase) -> Result<String> {
+        self.run_fixture(db, path, discovery::SETUP_SUFFIX).await?;
+        let mut output = vec![];
+        let executed = case.execute(db, &mut output).await;
+        self.run_teardown(db, path, executed, &mut String::new())
+            .await?;
+
+        Ok(mask_elapsed(
+            &self.config,
+            &normalize(&self.config, &String::from_utf8(output)?),
+        ))
+    }
+
+    fn existing(path: &Path) -> Option<&Path> {
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Print environments and cases that would run, without executing them.
+    async fn dry_run(&self) -> Result<()> {
+        let mut case_count = 0;
+        let mut errors = vec![];
+        for env in discovery::collect_envs(&self.config).await? {
+            self.print(format_args!("Environment {}:", env));
+            for CollectedCase { path, case } in
+                discovery::collect_cases(&self.config, &env, self.env_dirs().await?).await?
+            {
+                match case {
+                    Ok(case) => {
+                        case_count += 1;
+                        self.print(format_args!(
+                            "    {} ({} queries)",
+                            path.display(),
+                            case.query_count()
+                        ));
+                    }
+                    Err(e) => {
+                        self.print(format_args!("    {} (invalid: {})", path.display(), e));
+                        errors.push((path.display().to_string(), e));
+                    }
+                }
             }
         }
 
-        Ok(result)
+        self.print(format_args!(
+            "Dry run finished, {} cases would run",
+            case_count
+        ));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            self.print(format_args!("Invalid cases:"));
+            self.print(format_args!("{:#?}", errors));
+            Err(SqlnessError::ExecutionFailed {
+                count: errors.len(),
+            })
+        }
     }
 
-    async fn run_env(&self, env: &str, db: &E::DB) -> Result<()> {
-        let case_paths = self.collect_case_paths(env).await?;
+    async fn read_env_config(&self, env: &str) -> Result<PathBuf> {
+        discovery::env_config_path(&self.config, env).await
+    }
+
+    async fn run_env_cases(
+        &self,
+        env: &str,
+        db: &E::DB,
+        config_path: Option<&Path>,
+        deadline: Option<Instant>,
+        span_id: &str,
+    ) -> Result<()> {
+        let parallelism = self.config.for_env(env).parallelism;
+        let cases = discovery::collect_cases(&self.config, env, self.env_dirs().await?).await?;
+        let mut case_paths = Vec::with_capacity(cases.len());
+        // parsed cases, taken when they start
+        let mut parsed = Vec::with_capacity(cases.len());
+        for CollectedCase { path, case } in cases {
+            case_paths.push(path);
+            parsed.push(Some(case));
+        }
+        let server = self.probe_server(db, config_path).await?;
+        let owners = Owners::load(&self.config).await?;
+        let sample_interval = Self::duration(
+            "resource_sample_interval",
+            &self.config.resource_sample_interval,
+        )?;
+        // owner -> failed cases
+        let mut failures_by_owner: BTreeMap<String, Vec<String>> = BTreeMap::new();
         let mut diff_cases = vec![];
+        let mut skipped_cases = vec![];
+        let mut updated_cases = vec![];
+        let mut slow_queries = vec![];
+        let mut env_samples = vec![];
+        // (case, peak and average of its samples)
+        let mut case_resources = vec![];
         let mut errors = vec![];
+        let mut not_executed = vec![];
+        let mut cancelled = false;
+        // no more cases are started once set, see `fail_fast`
+        let mut stopping = false;
         let start = Instant::now();
-        for path in case_paths {
-            let case_result = self.run_single_case(db, &path).await;
+        let server_version = server.version.as_ref().map(|version| version.to_string());
+        if let Some(version) = &server_version {
+            self.print(format_args!(
+                "Environment {} server version: {}",
+                env, version
+            ));
+        }
+        self.emit(Event::EnvStarted {
+            env,
+            server_version,
+        });
+        let schedules: Vec<_> = case_paths
+            .iter()
+            .zip(&parsed)
+            .map(|(path, case)| {
+                self.schedule(path, case.as_ref().and_then(|case| case.as_ref().ok()))
+            })
+            .collect();
+        let mut next = 0;
+        let mut running = vec![];
+        // paths and schedules of running cases, in the same order
+        let mut running_paths: Vec<&PathBuf> = vec![];
+        let mut running_schedules: Vec<&Schedule> = vec![];
+        loop {
+            while !stopping && next < case_paths.len() && running.len() < parallelism {
+                if Self::expired(deadline) {
+                    not_executed = case_paths[next..]
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    next = case_paths.len();
+                    break;
+                }
+                let schedule = &schedules[next];
+                if !schedule.can_run_with(&running_schedules) {
+                    break;
+                }
+                running.push(Box::pin(self.execute_case(
+                    env,
+                    db,
+                    &case_paths[next],
+                    parsed[next].take().unwrap(),
+                    &server,
+                    sample_interval,
+                    parallelism > 1,
+                )));
+                running_paths.push(&case_paths[next]);
+                running_schedules.push(schedule);
+                next += 1;
+            }
+            if running.is_empty() {
+                break;
+            }
+            let (finished, executed) = tokio::select! {
+                biased;
+                _ = self.cancellation.cancelled() => {
+                    cancelled = true;
+                    break;
+                }
+                finished = next_finished(&mut running) => finished,
+            };
+            running_paths.remove(finished);
+            running_schedules.remove(finished);
+            let ExecutedCase {
+                path,
+                metadata,
+                case_result,
+                details,
+                span_id: case_span_id,
+                start_time: case_start_time,
+                elapsed,
+            } = executed;
             let case_name = path.as_os_str().to_str().unwrap().to_owned();
+            env_samples.extend_from_slice(&details.resource_samples);
+            let (outcome, reason) = match &case_result {
+                Ok(CaseOutcome::Pass) => ("pass", None),
+                Ok(CaseOutcome::Diff) => ("diff", None),
+                Ok(CaseOutcome::Updated) => ("updated", None),
+                Ok(CaseOutcome::Skipped(reason)) => ("skipped", Some(reason.clone())),
+                Err(e) => ("error", Some(e.to_string())),
+            };
+            self.record_span(
+                Span::new(
+                    case_span_id,
+                    Some(span_id),
+                    format!("case {}", discovery::relative_case_path(&self.config, path)),
+                    case_start_time,
+                    elapsed,
+                )
+                .attribute("sqlness.case", case_name.clone())
+                .attribute("sqlness.outcome", outcome)
+                .error(matches!(outcome, "diff" | "error")),
+            );
+            let relative_path = discovery::relative_case_path(&self.config, path);
+            self.metrics
+                .record_case(env, &relative_path, outcome, elapsed);
+            let case_owners = match &metadata.owner {
+                Some(owner) => vec![owner.clone()],
+                None => owners.lookup(&relative_path),
+            };
+            let server_logs = if report::is_failure(outcome) {
+                self.tail_server_logs(&server).await
+            } else {
+                vec![]
+            };
+            let case_path = path.with_extension(&self.config.test_case_extension);
+            for query in &details.slow_queries {
+                slow_queries.push((
+                    format!("{}:{}", case_path.display(), query.line),
+                    format!("{}ms", query.elapsed.as_millis()),
+                ));
+            }
+            if let Some(stats) = ResourceStats::from_samples(&details.resource_samples) {
+                case_resources.push((case_path.display().to_string(), stats));
+            }
+            self.reports.record(CaseReport {
+                env: env.to_string(),
+                case: relative_path,
+                outcome,
+                start: case_start_time,
+                elapsed,
+                reason: reason.clone(),
+                owners: case_owners.clone(),
+                tags: metadata.tags.clone(),
+                details,
+                server_logs,
+            });
+            self.emit(Event::CaseFinished {
+                env,
+                case: &case_name,
+                outcome,
+                elapsed_ms: elapsed.as_millis(),
+                reason: reason.as_deref(),
+                metadata: &metadata,
+                owners: &case_owners,
+            });
+            if matches!(case_result, Ok(CaseOutcome::Diff) | Err(_)) {
+                let owners = if case_owners.is_empty() {
+                    vec![UNOWNED.to_string()]
+                } else {
+                    case_owners.clone()
+                };
+                for owner in owners {
+                    failures_by_owner
+                        .entry(owner)
+                        .or_default()
+                        .push(case_name.clone());
+                }
+            }
+            // failures are listed with their owners
+            let case_name = if case_owners.is_empty() {
+                case_name
+            } else {
+                format!("{} (owner: {})", case_name, case_owners.join(" "))
+            };
             match case_result {
-                Ok(true) => diff_cases.push(case_name),
-                Ok(false) => {}
+                Ok(CaseOutcome::Diff) => diff_cases.push(case_name),
+                Ok(CaseOutcome::Updated) => updated_cases.push(case_name),
+                Ok(CaseOutcome::Skipped(reason)) => skipped_cases.push((case_name, reason)),
+                Ok(CaseOutcome::Pass) => {}
                 Err(e) => {
-                    if self.config.fail_fast {
-                        println!("Case {} failed with error {:?}", case_name, e);
-                        println!("Stopping environment {} due to previous error.", env);
-                        break;
-                    } else {
-                        errors.push((case_name, e))
+                    if annotation::enabled(&self.config) {
+                        let case_path = path.with_extension(&self.config.test_case_extension);
+                        let message = format!("Case failed with error {}", e);
+                        self.print(format_args!(
+                            "{}",
+                            annotation::error(&case_path, None, &message)
+                        ));
+                    }
+                    if self.config.fail_fast && !stopping {
+                        self.print(format_args!("Case {} failed with error {:?}", case_name, e));
+                        self.print(format_args!(
+                            "Stopping environment {} due to previous error.",
+                            env
+                        ));
+                        // running cases are still waited for, so their
+                        // results are reported and outputs complete
+                        stopping = true;
                     }
+                    errors.push((case_name, e))
+                }
+            }
+        }
+        if cancelled {
+            // running cases are dropped, outputs they were writing are
+            // incomplete
+            drop(running);
+            for path in running_paths {
+                not_executed.push(path.display().to_string());
+                let output_path = path.with_extension(&self.config.output_result_extension);
+                if output_path.exists() {
+                    remove_file(&output_path).await?;
                 }
             }
         }
 
-        println!(
+        self.print(format_args!(
             "Environment {} run finished, cost:{}ms",
             env,
             start.elapsed().as_millis()
-        );
+        ));
 
-        let mut error_count = 0;
+        self.metrics.record_env(env, start.elapsed());
+        self.emit(Event::EnvFinished {
+            env,
+            elapsed_ms: start.elapsed().as_millis(),
+            diff: diff_cases.len(),
+            skipped: skipped_cases.len(),
+            errors: errors.len(),
+        });
+        if cancelled {
+            self.print(format_args!(
+                "Environment {} cancelled, remaining cases are not run",
+                env
+            ));
+        }
+        self.report_not_executed(env, &not_executed);
+        if !updated_cases.is_empty() {
+            self.print(format_args!("Updated cases:"));
+            self.print(format_args!("{:#?}", updated_cases));
+        }
+        if !skipped_cases.is_empty() {
+            self.print(format_args!("Skipped cases:"));
+            self.print(format_args!("{:#?}", skipped_cases));
+        }
+        if !slow_queries.is_empty() {
+            self.print(format_args!("Slow queries:"));
+            self.print(format_args!("{:#?}", slow_queries));
+        }
+        if let Some(stats) = ResourceStats::from_samples(&env_samples) {
+            self.print(format_args!(
+                "Environment {} resource usage: {}",
+                env,
+                stats.describe()
+            ));
+            // cases with the highest peak memory are most likely to blow up
+            case_resources.sort_by_key(|(_, stats)| Reverse(stats.peak.rss_bytes));
+            self.print(format_args!("Peak resource usage by case:"));
+            for (case, stats) in case_resources.iter().take(TOP_RESOURCE_CASES) {
+                self.print(format_args!("  {}: {}", case, stats.describe()));
+            }
+        }
         if !diff_cases.is_empty() {
-            println!("Different cases:");
-            println!("{:#?}", diff_cases);
-            error_count += diff_cases.len();
+            self.print(format_args!("Different cases:"));
+            self.print(format_args!("{:#?}", diff_cases));
         }
         if !errors.is_empty() {
-            println!("Error cases:");
-            println!("{:#?}", errors);
-            error_count += errors.len();
+            self.print(format_args!("Error cases:"));
+            self.print(format_args!("{:#?}", errors));
         }
-        if error_count == 0 {
-            Ok(())
+        if !failures_by_owner.is_empty() && owners_declared(&failures_by_owner) {
+            self.print(format_args!("Failures by owner:"));
+            for (owner, cases) in &failures_by_owner {
+                self.print(format_args!("  {}: {:?}", owner, cases));
+            }
+        }
+        if cancelled {
+            Err(SqlnessError::Cancelled)
+        } else if !not_executed.is_empty() {
+            Err(SqlnessError::BudgetExceeded {
+                count: not_executed.len(),
+            })
+        } else if !errors.is_empty() {
+            Err(SqlnessError::ExecutionFailed {
+                count: errors.len(),
+            })
+        } else if !diff_cases.is_empty() {
+            Err(SqlnessError::RunFailed {
+                count: diff_cases.len(),
+            })
         } else {
-            Err(SqlnessError::RunFailed { count: error_count })
+            Ok(())
         }
     }
 
-    async fn run_single_case(&self, db: &E::DB, path: &PathBuf) -> Result<bool> {
-        let case_path = path.with_extension(&self.config.test_case_extension);
-        let case = TestCase::from_file(case_path, &self.config).await?;
+    /// Run the case at `path` of environment `env`, see [`Self::run_env_cases`].
+    /// Cases running `concurrent`ly run on their own connections if possible,
+    /// see [`Config::parallelism`].
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_case<'a>(
+        &self,
+        env: &str,
+        db: &E::DB,
+        path: &'a PathBuf,
+        case: Result<TestCase>,
+        server: &ServerInfo,
+        sample_interval: Option<Duration>,
+        concurrent: bool,
+    ) -> ExecutedCase<'a> {
+        self.emit(Event::CaseStarted {
+            env,
+            case: path.as_os_str().to_str().unwrap(),
+        });
+        let span_id = Tracer::span_id();
+        let start_time = SystemTime::now();
+        let start = Instant::now();
+        let mut details = CaseDetails::default();
+        let metadata = case
+            .as_ref()
+            .map(|case| case.metadata().clone())
+            .unwrap_or_default();
+        let run = async {
+            let connection = if concurrent {
+                self.case_connection(db, path, &case, server).await?
+            } else {
+                None
+            };
+            let db = connection.as_deref().unwrap_or(db);
+            self.run_single_case(db, path, case, server, &span_id, &mut details)
+                .await
+        };
+        let (case_result, samples) = self.sampled(env, db, sample_interval, run).await;
+        details.resource_samples = samples;
+
+        ExecutedCase {
+            path,
+            metadata,
+            case_result,
+            details,
+            span_id,
+            start_time,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Open a connection of its own for the case at `path` running along with
+    /// others, so session state set by `SESSION` or `USE` doesn't leak between
+    /// them. `None` if the database doesn't support [`Database::connection`],
+    /// then the case runs on the shared connection, unless it changes session
+    /// state.
+    async fn case_connection(
+        &self,
+        db: &E::DB,
+        path: &Path,
+        case: &Result<TestCase>,
+        server: &ServerInfo,
+    ) -> Result<Option<Box<dyn Database>>> {
+        let connection = db.connection(&path.display().to_string()).await;
+        match &connection {
+            Some(connection) => Self::init_connection(connection.as_ref(), server).await,
+            None if case.as_ref().is_ok_and(TestCase::changes_session) => {
+                return Err(SqlnessError::SharedSession {
+                    path: path.with_extension(&self.config.test_case_extension),
+                })
+            }
+            None => {}
+        }
+
+        Ok(connection)
+    }
+
+    /// How the case at `path` is scheduled, see [`Config::parallelism`].
+    /// `case` is `None` if it can't be parsed.
+    fn schedule(&self, path: &Path, case: Option<&TestCase>) -> Schedule {
+        let serial = case.map(TestCase::is_serial).unwrap_or(false)
+            || discovery::is_serial(&self.config, path);
+        let dependencies = case
+            .map(|case| {
+                case.dependencies()
+                    .iter()
+                    .map(|p| discovery::lexical(p))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Schedule {
+            path: discovery::lexical(path),
+            serial,
+            dependencies,
+        }
+    }
+
+    /// Run `fut` while sampling resource usage of `db` every `interval`, and
+    /// once more after it finishes. Nothing is sampled without `interval`.
+    async fn sampled<T>(
+        &self,
+        env: &str,
+        db: &E::DB,
+        interval: Option<Duration>,
+        fut: impl Future<Output = T>,
+    ) -> (T, Vec<ResourceUsage>) {
+        let interval = match interval {
+            Some(interval) => interval,
+            None => return (fut.await, vec![]),
+        };
+
+        tokio::pin!(fut);
+        let mut samples = vec![];
+        let mut ticker = tokio::time::interval(interval);
+        // the first tick completes immediately, skip it since the case just starts
+        ticker.tick().await;
+        let output = loop {
+            tokio::select! {
+                output = &mut fut => break output,
+                _ = ticker.tick() => {
+                    samples.extend(self.env_controller.sample_resources(env, db));
+                }
+            }
+        };
+        samples.extend(self.env_controller.sample_resources(env, db));
+
+        (output, samples)
+    }
+
+    /// Last lines of [`ServerInfo::server_logs`], if Allure results are
+    /// written. Unreadable logs are skipped.
+    async fn tail_server_logs(&self, server: &ServerInfo) -> Vec<(String, String)> {
+        if self.config.allure_results_dir.is_none() {
+            return vec![];
+        }
+
+        let mut logs = vec![];
+        for path in &server.server_logs {
+            if let Ok(content) = read(path).await {
+                let content = String::from_utf8_lossy(&content);
+                let lines: Vec<_> = content.lines().collect();
+                let tail = lines[lines.len().saturating_sub(SERVER_LOG_LINES)..].join("\n");
+                logs.push((path.display().to_string(), tail));
+            }
+        }
+
+        logs
+    }
+
+    /// Collect server version and supported features of an environment.
+    async fn probe_server(&self, db: &E::DB, config_path: Option<&Path>) -> Result<ServerInfo> {
+        let mut server = ServerInfo::default();
+        if let Some(config_path) = config_path {
+            server.init_statements =
+                Self::read_string_array(config_path, "init_statements").await?;
+            server
+                .features
+                .extend(Self::read_string_array(config_path, "features").await?);
+            // relative to the env config file
+            let env_dir = config_path.parent().unwrap_or(Path::new(""));
+            server.server_logs = Self::read_string_array(config_path, "server_logs")
+                .await?
+                .into_iter()
+                .map(|log| env_dir.join(log))
+                .collect();
+        }
+        Self::init_connection(db, &server).await;
+        server.version = self.probe_version(db).await;
+        server.features.extend(db.features().await);
+
+        Ok(server)
+    }
+
+    /// Execute `init_statements` declared in env config file on a new
+    /// connection, their results are ignored.
+    async fn init_connection(db: &dyn Database, server: &ServerInfo) {
+        for statement in &server.init_statements {
+            db.query(ExecutionContext::default(), statement.clone())
+                .await;
+        }
+    }
+
+    /// Query server version with [`Config::version_probe`].
+    async fn probe_version(&self, db: &E::DB) -> Option<Version> {
+        let probe = self.config.version_probe.as_ref()?;
+        let result = db
+            .query(ExecutionContext::default(), probe.clone())
+            .await
+            .to_string();
+        let version = Version::extract(&result);
+        if version.is_none() {
+            self.print(format_args!(
+                "Unable to extract server version from {:?}",
+                result
+            ));
+        }
+
+        version
+    }
+
+    /// Read the array of strings named `key` from env config file.
+    async fn read_string_array(config_path: &Path, key: &str) -> Result<Vec<String>> {
+        let mut config_buf = vec![];
+        File::open(config_path)
+            .await
+            .map_err(|e| SqlnessError::ReadPath {
+                source: e,
+                path: config_path.to_path_buf(),
+            })?
+            .read_to_end(&mut config_buf)
+            .await?;
+        let config: toml::Value =
+            toml::from_slice(&config_buf).map_err(|e| SqlnessError::ParseToml {
+                source: e,
+                file: config_path.to_path_buf(),
+            })?;
+
+        let values = config
+            .get(key)
+            .and_then(|values| values.as_array())
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(values)
+    }
+
+    async fn run_single_case(
+        &self,
+        db: &dyn Database,
+        path: &PathBuf,
+        case: Result<TestCase>,
+        server: &ServerInfo,
+        span_id: &str,
+        details: &mut CaseDetails,
+    ) -> Result<CaseOutcome> {
+        // console output of a case is buffered and flushed at once, so outputs
+        // of cases running concurrently are not interleaved
+        let mut log = String::new();
+        let outcome = self
+            .run_single_case_logged(db, path, case, server, span_id, &mut log, details)
+            .await;
+        let _ = match self.config.message_format {
+            MessageFormat::Human => std::io::stdout().lock().write_all(log.as_bytes()),
+            MessageFormat::Json => std::io::stderr().lock().write_all(log.as_bytes()),
+            MessageFormat::Plain => Ok(()),
+        };
+
+        outcome
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_single_case_logged(
+        &self,
+        db: &dyn Database,
+        path: &PathBuf,
+        case: Result<TestCase>,
+        server: &ServerInfo,
+        span_id: &str,
+        log: &mut String,
+        details: &mut CaseDetails,
+    ) -> Result<CaseOutcome> {
+        let case = case?;
+        if let Some(reason) = case.skip_reason(server) {
+            writeln!(log, "Test case {:?} skipped, {}", path.as_os_str(), reason).unwrap();
+            return Ok(CaseOutcome::Skipped(reason));
+        }
+        if let Some(benchmark) = &self.config.benchmark {
+            return self
+                .run_benchmark(db, path, &case, benchmark, server, log)
+                .await;
+        }
+        let expect_path = compare::expect_result_path(&self.config, path);
+        let missing_result = !expect_path.exists();
+        if missing_result {
+            match self.config.missing_result {
+                MissingResultPolicy::Create => {}
+                MissingResultPolicy::Fail => {
+                    return Err(SqlnessError::MissingResult { path: expect_path })
+                }
+                MissingResultPolicy::Skip => {
+                    let reason = "expected result file is missing".to_string();
+                    writeln!(log, "Test case {:?} skipped, {}", path.as_os_str(), reason).unwrap();
+                    return Ok(CaseOutcome::Skipped(reason));
+                }
+            }
+        }
         let output_path = path.with_extension(&self.config.output_result_extension);
         let mut output_file = Self::open_output_file(&output_path).await?;
 
         let timer = Instant::now();
-        case.execute(db, &mut output_file).await?;
+        if self.config.connection_mode == ConnectionMode::PerCase {
+            db.reconnect().await;
+            Self::init_connection(db, server).await;
+        }
+        self.run_fixture(db, path, discovery::SETUP_SUFFIX).await?;
+        let timings = case.execute_timed(db, &mut output_file).await;
+        let timings = self.run_teardown(db, path, timings, log).await?;
         let elapsed = timer.elapsed();
+        for timing in &timings {
+            self.record_span(
+                Span::new(
+                    Tracer::span_id(),
+                    Some(span_id),
+                    "query",
+                    timing.start,
+                    timing.elapsed,
+                )
+                .attribute("db.statement", timing.query.clone())
+                .attribute("sqlness.query_id", timing.query_id.clone()),
+            );
+        }
+        let query_ids: Vec<_> = timings
+            .iter()
+            .map(|timing| timing.query_id.clone())
+            .collect();
+        if let Some(threshold) =
+            Self::duration("slow_query_threshold", &self.config.slow_query_threshold)?
+        {
+            details.slow_queries = timings
+                .iter()
+                .filter(|timing| timing.elapsed >= threshold)
+                .map(|timing| SlowQuery {
+                    line: timing.line,
+                    elapsed: timing.elapsed,
+                })
+                .collect();
+        }
+        details.queries = timings;
 
         output_file.flush().await?;
-        let is_different = self.compare(&path).await?;
-        if !is_different {
-            remove_file(output_path).await?;
+        self.normalize_output(&output_path).await?;
+        if missing_result {
+            let expect_path = match self.config.result_compression {
+                Some(compression) => {
+                    let compressed_path = compression.compressed_path(&expect_path);
+                    compression.compress(&output_path, &compressed_path).await?;
+                    remove_file(&output_path).await?;
+                    compressed_path
+                }
+                None => {
+                    rename(&output_path, &expect_path).await?;
+                    expect_path
+                }
+            };
+            writeln!(
+                log,
+                "Test case {:?} finished, cost: {}ms. Warning: expected result is missing, created {} from output",
+                path.as_os_str(),
+                elapsed.as_millis(),
+                expect_path.display()
+            )
+            .unwrap();
+            return Ok(CaseOutcome::Pass);
+        }
+        let is_different = compare::compare_with_query_ids(
+            &self.config,
+            path,
+            Some(&case),
+            &query_ids,
+            log,
+            &mut details.diffs,
+        )
+        .await?;
+        if is_different {
+            let case = path.display().to_string();
+            self.emit(Event::CaseDiff {
+                case: &case,
+                output: &output_path.display().to_string(),
+                expected: &expect_path.display().to_string(),
+            });
+            for diff in &details.diffs {
+                self.emit(Event::QueryDiff { case: &case, diff });
+            }
+        } else {
+            remove_file(&output_path).await?;
         }
 
-        println!(
+        writeln!(
+            log,
             "Test case {:?} finished, cost: {}ms",
             path.as_os_str(),
             elapsed.as_millis()
-        );
-        Ok(is_different)
-    }
-
-    async fn collect_case_paths(&self, env: &str) -> Result<Vec<PathBuf>> {
-        let mut root = PathBuf::from_str(&self.config.case_dir).unwrap();
-        root.push(env);
-
-        let test_case_extension = self.config.test_case_extension.as_str();
-        let mut cases: Vec<_> = WalkDir::new(&root)
-            .into_iter()
-            .filter_map(|entry| {
-                entry
-                    .map_or(None, |entry| Some(entry.path().to_path_buf()))
-                    .filter(|path| {
-                        path.extension()
-                            .map(|ext| ext == test_case_extension)
-                            .unwrap_or(false)
-                    })
-            })
-            .map(|path| path.with_extension(""))
-            .filter(|path| {
-                path.file_name()
-                    .unwrap_or_default()
-                    .to_str()
-                    .unwrap_or_default()
-                    .contains(&self.config.test_filter)
-            })
-            .collect();
+        )
+        .unwrap();
+        if !is_different {
+            Ok(CaseOutcome::Pass)
+        } else if self.config.update_results {
+            self.update_result(&output_path, &expect_path, log).await?;
+            Ok(CaseOutcome::Updated)
+        } else {
+            Ok(CaseOutcome::Diff)
+        }
+    }
 
-        // sort the cases in an os-independent order.
-        cases.sort_by(|a, b| {
-            let a_lower = a.to_string_lossy().to_lowercase();
-            let b_lower = b.to_string_lossy().to_lowercase();
-            a_lower.cmp(&b_lower)
-        });
+    /// Measure latencies of queries of `case` at `path`, see
+    /// [`Config::benchmark`].
+    async fn run_benchmark(
+        &self,
+        db: &dyn Database,
+        path: &Path,
+        case: &TestCase,
+        benchmark: &BenchmarkConfig,
+        server: &ServerInfo,
+        log: &mut String,
+    ) -> Result<CaseOutcome> {
+        let timer = Instant::now();
+        if self.config.connection_mode == ConnectionMode::PerCase {
+            db.reconnect().await;
+            Self::init_connection(db, server).await;
+        }
+        self.run_fixture(db, path, discovery::SETUP_SUFFIX).await?;
+        let latencies = case
+            .benchmark(db, benchmark.warmups, benchmark.iterations)
+            .await;
+        let latencies = self.run_teardown(db, path, latencies, log).await?;
+
+        writeln!(
+            log,
+            "Benchmark of {:?} finished, {} warmups and {} iterations, cost: {}ms",
+            path.as_os_str(),
+            benchmark.warmups,
+            benchmark.iterations,
+            timer.elapsed().as_millis()
+        )
+        .unwrap();
+        log.push_str(&benchmark::render(&latencies));
+        self.benchmarks
+            .record(discovery::relative_case_path(&self.config, path), latencies);
+
+        Ok(CaseOutcome::Pass)
+    }
+
+    /// Update the expected result with the output, or append the change to
+    /// [`Config::update_patch`].
+    async fn update_result(
+        &self,
+        output_path: &Path,
+        expect_path: &Path,
+        log: &mut String,
+    ) -> Result<()> {
+        match (
+            &self.config.update_patch,
+            Compression::from_path(expect_path),
+        ) {
+            (_, Some(compression)) => {
+                compression.compress(output_path, expect_path).await?;
+                remove_file(output_path).await?;
+                writeln!(log, "Updated {}", expect_path.display()).unwrap();
+            }
+            (Some(patch_path), None) => {
+                let expected = compare::read_expect_result(&self.config, expect_path).await?;
+                let output = self.config.file_encoding.decode(read(output_path).await?)?;
+                let patch = patch::unified_diff(expect_path, &expected, &output);
+                OpenOptions::default()
+                    .create(true)
+                    .append(true)
+                    .open(patch_path)
+                    .await?
+                    .write_all(patch.as_bytes())
+                    .await?;
+                remove_file(output_path).await?;
+                writeln!(
+                    log,
+                    "Change of {} is written to {}",
+                    expect_path.display(),
+                    patch_path
+                )
+                .unwrap();
+            }
+            (None, None) => {
+                rename(output_path, expect_path).await?;
+                writeln!(log, "Updated {}", expect_path.display()).unwrap();
+            }
+        }
 
-        Ok(cases)
+        Ok(())
+    }
+
+    /// Run fixture `<case>.<suffix>.<ext>` of case at `path` if it exists,
+    /// without recording its output.
+    async fn run_fixture(&self, db: &dyn Database, path: &Path, suffix: &str) -> Result<()> {
+        let mut fixture_path = path.as_os_str().to_owned();
+        fixture_path.push(".");
+        fixture_path.push(suffix);
+        fixture_path.push(".");
+        fixture_path.push(&self.config.test_case_extension);
+        let fixture_path = PathBuf::from(fixture_path);
+        if !fixture_path.exists() {
+            return Ok(());
+        }
+
+        let fixture = self.parse_case(&fixture_path).await?;
+        fixture.execute(db, &mut tokio::io::sink()).await?;
+
+        Ok(())
+    }
+
+    /// Run the teardown fixture of the case at `path`, even if the case failed
+    /// with `result`. The failure of the case is returned first, a failed
+    /// teardown after it is only logged.
+    async fn run_teardown<T>(
+        &self,
+        db: &dyn Database,
+        path: &Path,
+        result: Result<T>,
+        log: &mut String,
+    ) -> Result<T> {
+        let teardown = self.run_fixture(db, path, discovery::TEARDOWN_SUFFIX).await;
+        match (result, teardown) {
+            (Err(e), Err(teardown)) => {
+                writeln!(
+                    log,
+                    "Teardown of {:?} failed too, {}",
+                    path.as_os_str(),
+                    teardown
+                )
+                .unwrap();
+                Err(e)
+            }
+            (result, teardown) => teardown.and(result),
+        }
+    }
+
+    /// Normalize the output file and convert it to [`Config::file_encoding`].
+    /// It's rewritten only if changed.
+    async fn normalize_output(&self, path: &Path) -> Result<()> {
+        let raw = read(path).await?;
+        let output = match std::str::from_utf8(&raw) {
+            Ok(output) => output,
+            // reported when comparing
+            Err(_) => return Ok(()),
+        };
+        let normalized = self
+            .config
+            .file_encoding
+            .encode(&normalize(&self.config, output));
+        if normalized != raw {
+            write(path, normalized).await?;
+        }
+
+        Ok(())
     }
 
     async fn open_output_file<P: AsRef<Path>>(path: P) -> Result<File> {
@@ -224,46 +1567,211 @@ impl<E: EnvController> Runner<E> {
             .open(&path)
             .await?)
     }
+}
 
-    /// Compare files' diff, return true if two files are different
-    async fn compare<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
-        let mut result_lines = vec![];
-        File::open(
-            path.as_ref()
-                .with_extension(&self.config.expect_result_extension),
-        )
-        .await?
-        .read_to_end(&mut result_lines)
-        .await?;
-        let result_lines = String::from_utf8(result_lines)?;
+#[cfg(test)]
+mod tests {
+    use std::fmt::Display;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::ConfigBuilder;
 
-        let mut output_lines = vec![];
-        File::open(
-            path.as_ref()
-                .with_extension(&self.config.output_result_extension),
+    struct SlowDB;
+
+    #[async_trait]
+    impl Database for SlowDB {
+        async fn query(&self, _context: ExecutionContext, query: String) -> Box<dyn Display> {
+            if query.contains("FAIL") {
+                panic!("query failed");
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Box::new("done")
+        }
+    }
+
+    struct SlowController;
+
+    #[async_trait]
+    impl EnvController for SlowController {
+        type DB = SlowDB;
+
+        async fn start(&self, _env: &str, _config: Option<&Path>) -> Self::DB {
+            SlowDB
+        }
+
+        async fn stop(&self, _env: &str, _database: Self::DB) {}
+    }
+
+    #[tokio::test]
+    async fn fail_fast_waits_for_running_cases() {
+        let case_dir =
+            std::env::temp_dir().join(format!("sqlness-fail-fast-{}", std::process::id()));
+        let env_dir = case_dir.join("env");
+        tokio::fs::create_dir_all(&env_dir).await.unwrap();
+        for (name, query) in [("a", "FAIL;"), ("b", "SLOW;"), ("c", "SLOW;")] {
+            write(env_dir.join(format!("{}.sql", name)), query)
+                .await
+                .unwrap();
+            write(env_dir.join(format!("{}.result", name)), "")
+                .await
+                .unwrap();
+        }
+        let config = ConfigBuilder::default()
+            .case_dir(case_dir.display().to_string())
+            .parallelism(2)
+            .build()
+            .unwrap();
+        let runner = Runner::new_with_config(config, SlowController)
+            .await
+            .unwrap();
+
+        let result = runner.run().await;
+
+        assert!(matches!(
+            result,
+            Err(SqlnessError::ExecutionFailed { count: 1 })
+        ));
+        // b was running when a failed, it finishes with its output complete
+        let output = tokio::fs::read_to_string(env_dir.join("b.output"))
+            .await
+            .unwrap();
+        assert!(output.contains("done"), "{}", output);
+        // c is never started
+        assert!(!env_dir.join("c.output").exists());
+        tokio::fs::remove_dir_all(&case_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn case_failure_reported_before_teardown_failure() {
+        let case_dir =
+            std::env::temp_dir().join(format!("sqlness-teardown-{}", std::process::id()));
+        let env_dir = case_dir.join("env");
+        tokio::fs::create_dir_all(&env_dir).await.unwrap();
+        write(
+            env_dir.join("a.teardown.sql"),
+            "-- SQLNESS PRIORITY high\nSELECT 1;\n",
         )
-        .await?
-        .read_to_end(&mut output_lines)
-        .await?;
-        let output_lines = String::from_utf8(output_lines)?;
+        .await
+        .unwrap();
+        let config = ConfigBuilder::default()
+            .case_dir(case_dir.display().to_string())
+            .build()
+            .unwrap();
+        let runner = Runner::new_with_config(config, SlowController)
+            .await
+            .unwrap();
+        let path = env_dir.join("a");
 
-        let diff = diff_lines(&result_lines, &output_lines)
-            .set_diff_only(true)
-            .names("Expected", "Actual");
-        let is_different = diff.diff().iter().any(|d| !matches!(d, DiffOp::Equal(_)));
-        if is_different {
-            println!("Result unexpected, path:{:?}", path.as_ref());
-            println!(
-                "Hint: compare them with \"diff {} {}\"\n",
-                path.as_ref()
-                    .with_extension(&self.config.output_result_extension)
-                    .display(),
-                path.as_ref()
-                    .with_extension(&self.config.expect_result_extension)
-                    .display()
-            )
+        let mut log = String::new();
+        let failed: Result<()> = Err(SqlnessError::MissingResult {
+            path: PathBuf::from("a.result"),
+        });
+        let result = runner.run_teardown(&SlowDB, &path, failed, &mut log).await;
+        assert!(matches!(result, Err(SqlnessError::MissingResult { .. })));
+        assert!(log.contains("Teardown"), "{}", log);
+
+        let result = runner
+            .run_teardown(&SlowDB, &path, Ok(()), &mut String::new())
+            .await;
+        assert!(matches!(result, Err(SqlnessError::ParseCase { .. })));
+        tokio::fs::remove_dir_all(&case_dir).await.unwrap();
+    }
+
+    /// Returns the value set by the last `SET <value>` query of its session.
+    #[derive(Default)]
+    struct SessionDB {
+        value: std::sync::Mutex<String>,
+        supports_connections: bool,
+    }
+
+    #[async_trait]
+    impl Database for SessionDB {
+        async fn query(&self, _context: ExecutionContext, query: String) -> Box<dyn Display> {
+            if let Some(value) = query.strip_prefix("SET ") {
+                *self.value.lock().unwrap() = value.to_string();
+                return Box::new("");
+            }
+            // let the other case set its value meanwhile
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Box::new(self.value.lock().unwrap().clone())
+        }
+
+        async fn connection(&self, _name: &str) -> Option<Box<dyn Database>> {
+            self.supports_connections
+                .then(|| Box::new(SessionDB::default()) as Box<dyn Database>)
+        }
+    }
+
+    struct SessionController {
+        supports_connections: bool,
+    }
+
+    #[async_trait]
+    impl EnvController for SessionController {
+        type DB = SessionDB;
+
+        async fn start(&self, _env: &str, _config: Option<&Path>) -> Self::DB {
+            SessionDB {
+                supports_connections: self.supports_connections,
+                ..Default::default()
+            }
         }
 
-        Ok(is_different)
+        async fn stop(&self, _env: &str, _database: Self::DB) {}
+    }
+
+    #[tokio::test]
+    async fn concurrent_cases_have_own_sessions() {
+        let case_dir =
+            std::env::temp_dir().join(format!("sqlness-sessions-{}", std::process::id()));
+        let env_dir = case_dir.join("env");
+        for supports_connections in [true, false] {
+            tokio::fs::create_dir_all(&env_dir).await.unwrap();
+            for value in ["alpha", "beta"] {
+                write(
+                    env_dir.join(format!("{}.sql", value)),
+                    format!("-- SQLNESS SESSION SET {}\nSELECT value;\n", value),
+                )
+                .await
+                .unwrap();
+            }
+            let config = ConfigBuilder::default()
+                .case_dir(case_dir.display().to_string())
+                .parallelism(2)
+                .missing_result(MissingResultPolicy::Create)
+                .build()
+                .unwrap();
+            let runner = Runner::new_with_config(
+                config,
+                SessionController {
+                    supports_connections,
+                },
+            )
+            .await
+            .unwrap();
+
+            let result = runner.run().await;
+
+            if supports_connections {
+                result.unwrap();
+                let alpha = tokio::fs::read_to_string(env_dir.join("alpha.result"))
+                    .await
+                    .unwrap();
+                assert!(!alpha.contains("beta"), "{}", alpha);
+                let beta = tokio::fs::read_to_string(env_dir.join("beta.result"))
+                    .await
+                    .unwrap();
+                assert!(!beta.contains("alpha"), "{}", beta);
+            } else {
+                // both would run on the shared connection
+                assert!(matches!(
+                    result,
+                    Err(SqlnessError::ExecutionFailed { count: 2 })
+                ));
+            }
+            tokio::fs::remove_dir_all(&case_dir).await.unwrap();
+        }
     }
 }
@@ -0,0 +1,111 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Minimization of different cases, see [`Runner::shrink_case`].
+//!
+//! [`Runner::shrink_case`]: crate::Runner::shrink_case
+
+use std::future::Future;
+
+use crate::error::Result;
+
+/// A query of a case being shrunk.
+#[derive(Debug, Clone)]
+pub(crate) struct Block {
+    /// Directive lines in front of the query.
+    pub directives: Vec<String>,
+    /// Lines of the query text.
+    pub lines: Vec<String>,
+    /// Whether it's the query whose result differs, which is never removed.
+    pub target: bool,
+}
+
+/// Content of the case file of `blocks`.
+pub(crate) fn to_case_file(blocks: &[Block]) -> String {
+    let mut content = String::new();
+    for block in blocks {
+        for line in block.directives.iter().chain(&block.lines) {
+            content.push_str(line);
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+
+    content
+}
+
+/// Index of the target query in `blocks`.
+pub(crate) fn target_index(blocks: &[Block]) -> usize {
+    blocks.iter().position(|block| block.target).unwrap()
+}
+
+/// Remove queries of `blocks`, then lines inside remaining queries, as long
+/// as `interesting` holds for the result, i.e. the target query still
+/// differs in the same way. Queries are removed in chunks which are halved
+/// until single queries, like delta debugging. Only inner lines of queries
+/// are removed, like rows of multi-line `INSERT`s, so queries stay complete.
+///
+/// Return remaining blocks and the number of candidates tried.
+pub(crate) async fn shrink<F, Fut>(
+    mut blocks: Vec<Block>,
+    mut interesting: F,
+) -> Result<(Vec<Block>, usize)>
+where
+    F: FnMut(Vec<Block>) -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let mut attempts = 0;
+    let mut chunk = (blocks.len() / 2).max(1);
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start < blocks.len() {
+            let end = (start + chunk).min(blocks.len());
+            let candidate: Vec<_> = blocks
+                .iter()
+                .enumerate()
+                .filter(|(i, block)| block.target || !(start..end).contains(i))
+                .map(|(_, block)| block.clone())
+                .collect();
+            if candidate.len() == blocks.len() {
+                start = end;
+                continue;
+            }
+            attempts += 1;
+            if interesting(candidate.clone()).await? {
+                blocks = candidate;
+                removed = true;
+                // the target query may be kept in place
+                start += usize::from(blocks.get(start).map(|b| b.target).unwrap_or(false));
+            } else {
+                start = end;
+            }
+        }
+        if chunk == 1 && !removed {
+            break;
+        }
+        chunk = (chunk / 2).max(1);
+    }
+
+    loop {
+        let mut removed = false;
+        for index in 0..blocks.len() {
+            let mut line = 1;
+            while line + 1 < blocks[index].lines.len() {
+                let mut candidate = blocks.clone();
+                candidate[index].lines.remove(line);
+                attempts += 1;
+                if interesting(candidate.clone()).await? {
+                    blocks = candidate;
+                    removed = true;
+                } else {
+                    line += 1;
+                }
+            }
+        }
+        if !removed {
+            break;
+        }
+    }
+
+    Ok((blocks, attempts))
+}
@@ -0,0 +1,48 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::fmt::Debug;
+
+/// Decide where queries end in case files.
+///
+/// The default one splits queries by `;` at end of line like SQL, see
+/// [`Config::query_delimiter`]. Suites of other query languages (PromQL,
+/// InfluxQL or custom DSLs) can provide their own via
+/// [`Config::query_splitter`].
+///
+/// [`Config::query_delimiter`]: crate::Config::query_delimiter
+/// [`Config::query_splitter`]: crate::Config::query_splitter
+pub trait QuerySplitter: Debug + Send + Sync {
+    /// Return true if `line` is the last line of current query. Lines of
+    /// comments and interceptors are never passed in.
+    fn is_query_end(&self, line: &str) -> bool;
+}
+
+/// Queries end with a line ending with the delimiter, like `;` for SQL.
+#[derive(Debug)]
+pub struct DelimiterSplitter {
+    delimiter: String,
+}
+
+impl DelimiterSplitter {
+    pub fn new(delimiter: impl Into<String>) -> Self {
+        Self {
+            delimiter: delimiter.into(),
+        }
+    }
+}
+
+impl QuerySplitter for DelimiterSplitter {
+    fn is_query_end(&self, line: &str) -> bool {
+        line.ends_with(&self.delimiter)
+    }
+}
+
+/// Each line is a query, for languages like PromQL.
+#[derive(Debug)]
+pub struct LineSplitter;
+
+impl QuerySplitter for LineSplitter {
+    fn is_query_end(&self, _line: &str) -> bool {
+        true
+    }
+}
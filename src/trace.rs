@@ -0,0 +1,166 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+//! Spans of a run (run → env → case → query), exported to an OpenTelemetry
+//! collector, see [`Config::otlp_endpoint`].
+//!
+//! [`Config::otlp_endpoint`]: crate::Config::otlp_endpoint
+
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{event::string, hash::random_hex, http};
+
+/// Path of the OTLP/HTTP traces API, appended to endpoints without a path.
+const TRACES_PATH: &str = "/v1/traces";
+const SERVICE_NAME: &str = "sqlness";
+
+/// A finished span.
+pub(crate) struct Span {
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, String)>,
+    /// Whether the span failed, e.g. the case is different.
+    pub error: bool,
+}
+
+impl Span {
+    /// A span starting at `start` and lasting for `elapsed`.
+    pub(crate) fn new(
+        span_id: String,
+        parent_span_id: Option<&str>,
+        name: impl Into<String>,
+        start: SystemTime,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            span_id,
+            parent_span_id: parent_span_id.map(str::to_string),
+            name: name.into(),
+            start,
+            end: start + elapsed,
+            attributes: vec![],
+            error: false,
+        }
+    }
+
+    pub(crate) fn attribute(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.attributes.push((key, value.into()));
+        self
+    }
+
+    pub(crate) fn error(mut self, error: bool) -> Self {
+        self.error = error;
+        self
+    }
+
+    fn to_json(&self, trace_id: &str) -> String {
+        let attributes: Vec<_> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}",
+                    string(key),
+                    string(value)
+                )
+            })
+            .collect();
+        let parent = self
+            .parent_span_id
+            .as_ref()
+            .map(|parent| format!("\"parentSpanId\":{},", string(parent)))
+            .unwrap_or_default();
+        // STATUS_CODE_OK and STATUS_CODE_ERROR
+        let status = if self.error { 2 } else { 1 };
+
+        format!(
+            "{{\"traceId\":{},\"spanId\":{},{}\"name\":{},\"kind\":1,\
+             \"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\
+             \"attributes\":[{}],\"status\":{{\"code\":{}}}}}",
+            string(trace_id),
+            string(&self.span_id),
+            parent,
+            string(&self.name),
+            unix_nanos(self.start),
+            unix_nanos(self.end),
+            attributes.join(","),
+            status
+        )
+    }
+}
+
+/// Collector of spans of a run, all of which belong to one trace.
+pub(crate) struct Tracer {
+    trace_id: String,
+    /// ID of the span covering the whole run, parent of env spans.
+    root_span_id: String,
+    spans: Mutex<Vec<Span>>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self {
+            trace_id: random_hex(32),
+            root_span_id: Self::span_id(),
+            spans: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl Tracer {
+    /// Generate a new span ID.
+    pub(crate) fn span_id() -> String {
+        random_hex(16)
+    }
+
+    pub(crate) fn root_span_id(&self) -> &str {
+        &self.root_span_id
+    }
+
+    pub(crate) fn record(&self, span: Span) {
+        self.spans.lock().unwrap().push(span);
+    }
+
+    /// Send recorded spans to `endpoint` in OTLP/HTTP JSON encoding, like
+    /// `http://localhost:4318`.
+    pub(crate) async fn export(&self, endpoint: &str) -> std::io::Result<()> {
+        http::send(
+            "POST",
+            endpoint,
+            TRACES_PATH,
+            "application/json",
+            &self.to_json(),
+        )
+        .await
+    }
+
+    fn to_json(&self) -> String {
+        let spans: Vec<_> = self
+            .spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|span| span.to_json(&self.trace_id))
+            .collect();
+
+        format!(
+            "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{{\"key\":\"service.name\",\
+             \"value\":{{\"stringValue\":{}}}}}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":{}}},\
+             \"spans\":[{}]}}]}}]}}",
+            string(SERVICE_NAME),
+            string(SERVICE_NAME),
+            spans.join(",")
+        )
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
@@ -0,0 +1,106 @@
+// Copyright 2022 CeresDB Project Authors. Licensed under Apache-2.0.
+
+use std::{fmt::Display, str::FromStr};
+
+/// Dot-separated numeric version like `0.9.0`. Missing components are treated
+/// as zero when comparing, so `1.2` equals `1.2.0`.
+#[derive(Debug, Clone)]
+pub(crate) struct Version(Vec<u64>);
+
+impl Version {
+    /// Find the first version-like token in `text`, e.g. `15.2` from
+    /// `PostgreSQL 15.2 on x86_64-pc-linux-gnu`.
+    pub(crate) fn extract(text: &str) -> Option<Self> {
+        text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .map(|token| token.trim_matches('.'))
+            .filter(|token| token.contains('.'))
+            .find_map(|token| token.parse().ok())
+    }
+
+    fn component(&self, index: usize) -> u64 {
+        self.0.get(index).copied().unwrap_or(0)
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches('v');
+        s.split('.')
+            .map(|component| component.parse())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+            .map_err(|_| format!("invalid version `{}`", s))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let len = self.0.len().max(other.0.len());
+        (0..len)
+            .map(|i| self.component(i).cmp(&other.component(i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components: Vec<_> = self.0.iter().map(|c| c.to_string()).collect();
+        f.write_str(&components.join("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(version("v0.9.0").to_string(), "0.9.0");
+        assert_eq!(version(" 15 ").to_string(), "15");
+        for s in ["", "1..2", "1.x", "1.2-beta"] {
+            assert!(s.parse::<Version>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn compare_versions() {
+        assert_eq!(version("1.2"), version("1.2.0"));
+        assert!(version("1.10") > version("1.9.9"));
+        assert!(version("0.9") < version("0.9.1"));
+        assert!(version("2") > version("1.99"));
+    }
+
+    #[test]
+    fn extract_version() {
+        assert_eq!(
+            Version::extract("PostgreSQL 15.2 on x86_64-pc-linux-gnu").map(|v| v.to_string()),
+            Some("15.2".to_string())
+        );
+        assert_eq!(
+            Version::extract("GreptimeDB v0.9.0.").map(|v| v.to_string()),
+            Some("0.9.0".to_string())
+        );
+        assert!(Version::extract("no version 15").is_none());
+    }
+}